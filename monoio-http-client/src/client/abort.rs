@@ -0,0 +1,94 @@
+use std::{cell::RefCell, rc::Rc};
+
+use bytes::Bytes;
+use local_sync::semaphore::Semaphore;
+use monoio_http::h2::{Reason, SendStream};
+
+/// Handle to abort an in-flight request.
+///
+/// Aborting before the response head arrives makes the request fail with
+/// `ErrorKind::Aborted`. Aborting while the response body is being received makes
+/// the body yield an error. HTTP/1.1 connections of aborted requests are
+/// never returned to the pool, and HTTP/2 streams are reset with
+/// `RST_STREAM(CANCEL)`.
+#[derive(Clone, Default)]
+pub struct AbortHandle {
+    inner: Rc<AbortInner>,
+}
+
+struct AbortInner {
+    // Never has permits, it is closed to wake up all waiters on abort.
+    signal: Semaphore,
+    // The h2 send half kept after the request body is sent, so that the
+    // stream can still be reset explicitly.
+    h2_stream: RefCell<Option<SendStream<Bytes>>>,
+    // Aborted along with this one.
+    children: RefCell<Vec<AbortHandle>>,
+}
+
+impl Default for AbortInner {
+    fn default() -> Self {
+        Self {
+            signal: Semaphore::new(0),
+            h2_stream: RefCell::new(None),
+            children: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Abort the request.
+    pub fn abort(&self) {
+        self.inner.signal.close();
+        if let Some(mut stream) = self.inner.h2_stream.borrow_mut().take() {
+            stream.send_reset(Reason::CANCEL);
+        }
+        for child in self.inner.children.take() {
+            child.abort();
+        }
+    }
+
+    /// Returns true if the request has been aborted.
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        self.inner.signal.is_closed()
+    }
+
+    /// Resolves when the request is aborted.
+    pub(crate) async fn aborted(&self) {
+        // acquire only returns when the semaphore is closed.
+        let _ = self.inner.signal.acquire().await;
+    }
+
+    /// A handle which is aborted with this one, but can also be aborted on
+    /// its own.
+    pub(crate) fn child(&self) -> AbortHandle {
+        let child = AbortHandle::new();
+        if self.is_aborted() {
+            child.abort();
+        } else {
+            self.inner.children.borrow_mut().push(child.clone());
+        }
+        child
+    }
+
+    pub(crate) fn park_h2_stream(&self, mut stream: SendStream<Bytes>) {
+        if self.is_aborted() {
+            stream.send_reset(Reason::CANCEL);
+            return;
+        }
+        *self.inner.h2_stream.borrow_mut() = Some(stream);
+    }
+}
+
+impl std::fmt::Debug for AbortHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AbortHandle")
+            .field("aborted", &self.is_aborted())
+            .finish()
+    }
+}
@@ -0,0 +1,520 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::hash_map::{DefaultHasher, RandomState},
+    hash::{BuildHasher, Hash, Hasher},
+    net::SocketAddr,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use http::{header::HeaderName, HeaderMap};
+
+/// How an `EndpointSet` picks the endpoint of a request.
+#[derive(Clone, Debug)]
+pub enum Strategy {
+    RoundRobin,
+    /// The endpoint with the fewest requests waiting for the response head.
+    LeastOutstanding,
+    /// The less loaded of two random endpoints.
+    PowerOfTwoChoices,
+    /// Requests with the same value of the header go to the same endpoint,
+    /// only the ones of a removed endpoint move. Requests without the
+    /// header are sent round-robin.
+    ConsistentHash(HeaderName),
+}
+
+/// When endpoints are ejected from an `EndpointSet`. Ejected endpoints are
+/// skipped until the ejection expires, or if all endpoints are ejected.
+#[derive(Clone, Debug)]
+pub struct OutlierDetection {
+    consecutive_connect_failures: u32,
+    error_rate: f64,
+    min_requests: u32,
+    ejection: Duration,
+}
+
+impl Default for OutlierDetection {
+    fn default() -> Self {
+        Self {
+            consecutive_connect_failures: 5,
+            error_rate: 0.5,
+            min_requests: 20,
+            ejection: Duration::from_secs(30),
+        }
+    }
+}
+
+impl OutlierDetection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Eject after `n` connect failures in a row, 5 by default.
+    pub fn consecutive_connect_failures(mut self, n: u32) -> Self {
+        self.consecutive_connect_failures = n;
+        self
+    }
+
+    /// Eject if at least `rate` of the responses are 5xx, 0.5 by default.
+    /// The rate is checked every `min_requests` responses.
+    pub fn error_rate(mut self, rate: f64, min_requests: u32) -> Self {
+        self.error_rate = rate;
+        self.min_requests = min_requests.max(1);
+        self
+    }
+
+    /// How long an endpoint is ejected, 30 seconds by default.
+    pub fn ejection(mut self, duration: Duration) -> Self {
+        self.ejection = duration;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    addr: SocketAddr,
+    outstanding: Cell<usize>,
+    connect_failures: Cell<u32>,
+    requests: Cell<u32>,
+    errors: Cell<u32>,
+    ejected_until: Cell<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            outstanding: Cell::new(0),
+            connect_failures: Cell::new(0),
+            requests: Cell::new(0),
+            errors: Cell::new(0),
+            ejected_until: Cell::new(None),
+        }
+    }
+
+    fn is_ejected(&self, now: Instant) -> bool {
+        matches!(self.ejected_until.get(), Some(until) if until > now)
+    }
+
+    fn eject(&self, duration: Duration) {
+        self.ejected_until.set(Some(Instant::now() + duration));
+        self.connect_failures.set(0);
+        self.requests.set(0);
+        self.errors.set(0);
+    }
+}
+
+#[derive(Debug)]
+struct SetInner {
+    strategy: Strategy,
+    outlier: RefCell<Option<OutlierDetection>>,
+    endpoints: RefCell<Vec<Rc<Endpoint>>>,
+    next: Cell<usize>,
+    rng: Cell<u64>,
+}
+
+/// Addresses of a logical service, register it with `Builder::service` to
+/// balance requests to the service name across them. Each endpoint has its
+/// own pooled connections.
+///
+/// Clones share the set, so it can be updated at runtime.
+#[derive(Clone, Debug)]
+pub struct EndpointSet {
+    inner: Rc<SetInner>,
+}
+
+impl EndpointSet {
+    /// Outlier detection is enabled with the default settings.
+    pub fn new(strategy: Strategy, addrs: impl IntoIterator<Item = SocketAddr>) -> Self {
+        let set = Self {
+            inner: Rc::new(SetInner {
+                strategy,
+                outlier: RefCell::new(Some(OutlierDetection::default())),
+                endpoints: RefCell::new(Vec::new()),
+                next: Cell::new(0),
+                // Seed with the random keys of the std hasher.
+                rng: Cell::new(RandomState::new().hash_one(0u64) | 1),
+            }),
+        };
+        set.set_endpoints(addrs);
+        set
+    }
+
+    /// Set None to disable ejection.
+    pub fn set_outlier_detection(&self, outlier: Option<OutlierDetection>) {
+        *self.inner.outlier.borrow_mut() = outlier;
+    }
+
+    /// Replace the addresses, endpoints which are kept retain their state.
+    pub fn set_endpoints(&self, addrs: impl IntoIterator<Item = SocketAddr>) {
+        let mut endpoints = self.inner.endpoints.borrow_mut();
+        let old = std::mem::take(&mut *endpoints);
+        for addr in addrs {
+            if endpoints.iter().any(|e| e.addr == addr) {
+                continue;
+            }
+            let endpoint = match old.iter().find(|e| e.addr == addr) {
+                Some(e) => e.clone(),
+                None => Rc::new(Endpoint::new(addr)),
+            };
+            endpoints.push(endpoint);
+        }
+    }
+
+    pub fn endpoints(&self) -> Vec<SocketAddr> {
+        self.inner
+            .endpoints
+            .borrow()
+            .iter()
+            .map(|e| e.addr)
+            .collect()
+    }
+
+    /// Endpoints which are ejected now.
+    pub fn ejected(&self) -> Vec<SocketAddr> {
+        let now = Instant::now();
+        self.inner
+            .endpoints
+            .borrow()
+            .iter()
+            .filter(|e| e.is_ejected(now))
+            .map(|e| e.addr)
+            .collect()
+    }
+
+    /// Returns None if the set is empty.
+    pub(crate) fn pick(&self, headers: &HeaderMap) -> Option<Pick> {
+        let endpoints = self.inner.endpoints.borrow();
+        let now = Instant::now();
+        let mut candidates: Vec<_> = endpoints.iter().filter(|e| !e.is_ejected(now)).collect();
+        if candidates.is_empty() {
+            // Ejecting every endpoint would fail all requests.
+            candidates = endpoints.iter().collect();
+        }
+        let n = candidates.len();
+        if n == 0 {
+            return None;
+        }
+
+        let endpoint = match &self.inner.strategy {
+            Strategy::RoundRobin => candidates[self.round_robin() % n],
+            Strategy::LeastOutstanding => {
+                let start = self.round_robin();
+                (0..n)
+                    .map(|i| candidates[(start + i) % n])
+                    .min_by_key(|e| e.outstanding.get())
+                    .expect("candidates are not empty")
+            }
+            Strategy::PowerOfTwoChoices => {
+                let a = candidates[self.random() % n];
+                let b = candidates[self.random() % n];
+                if b.outstanding.get() < a.outstanding.get() {
+                    b
+                } else {
+                    a
+                }
+            }
+            Strategy::ConsistentHash(header) => match headers.get(header) {
+                // Rendezvous hashing, the endpoint of the highest score wins.
+                Some(value) => candidates
+                    .iter()
+                    .copied()
+                    .max_by_key(|e| {
+                        let mut hasher = DefaultHasher::new();
+                        e.addr.hash(&mut hasher);
+                        value.as_bytes().hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .expect("candidates are not empty"),
+                None => candidates[self.round_robin() % n],
+            },
+        };
+        endpoint.outstanding.set(endpoint.outstanding.get() + 1);
+        Some(Pick {
+            endpoint: endpoint.clone(),
+            outlier: self.inner.outlier.borrow().clone(),
+        })
+    }
+
+    fn round_robin(&self) -> usize {
+        let next = self.inner.next.get();
+        self.inner.next.set(next.wrapping_add(1));
+        next
+    }
+
+    // xorshift64
+    fn random(&self) -> usize {
+        let mut x = self.inner.rng.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.inner.rng.set(x);
+        x as usize
+    }
+}
+
+/// The endpoint picked for a request, it counts as outstanding until
+/// dropped.
+pub(crate) struct Pick {
+    endpoint: Rc<Endpoint>,
+    outlier: Option<OutlierDetection>,
+}
+
+impl Pick {
+    #[inline]
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.endpoint.addr
+    }
+
+    pub(crate) fn connect_failed(&self) {
+        let endpoint = &self.endpoint;
+        let failures = endpoint.connect_failures.get() + 1;
+        endpoint.connect_failures.set(failures);
+        if let Some(outlier) = self.outlier.as_ref() {
+            if failures >= outlier.consecutive_connect_failures {
+                endpoint.eject(outlier.ejection);
+            }
+        }
+    }
+
+    pub(crate) fn connected(&self) {
+        self.endpoint.connect_failures.set(0);
+    }
+
+    /// Record the status of a response.
+    pub(crate) fn responded(&self, status: http::StatusCode) {
+        let endpoint = &self.endpoint;
+        // Requests sent before the ejection don't count after it expires.
+        if endpoint.is_ejected(Instant::now()) {
+            return;
+        }
+        let requests = endpoint.requests.get() + 1;
+        let errors = endpoint.errors.get() + u32::from(status.is_server_error());
+        let outlier = match self.outlier.as_ref() {
+            Some(outlier) if requests >= outlier.min_requests => outlier,
+            _ => {
+                endpoint.requests.set(requests);
+                endpoint.errors.set(errors);
+                return;
+            }
+        };
+        if errors as f64 >= outlier.error_rate * requests as f64 {
+            endpoint.eject(outlier.ejection);
+        } else {
+            endpoint.requests.set(0);
+            endpoint.errors.set(0);
+        }
+    }
+}
+
+impl Drop for Pick {
+    fn drop(&mut self) {
+        let outstanding = &self.endpoint.outstanding;
+        outstanding.set(outstanding.get() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener as StdListener;
+
+    use monoio::{
+        io::{sink::SinkExt, stream::Stream},
+        net::TcpListener,
+    };
+    use monoio_http::{
+        common::body::{FixedBody, HttpBody},
+        h1::codec::ServerCodec,
+    };
+
+    use super::*;
+    use crate::Builder;
+
+    fn addrs(n: u16) -> Vec<SocketAddr> {
+        (1..=n)
+            .map(|port| SocketAddr::from(([127, 0, 0, 1], port)))
+            .collect()
+    }
+
+    fn pick(set: &EndpointSet) -> Pick {
+        set.pick(&HeaderMap::new()).unwrap()
+    }
+
+    #[test]
+    fn strategies() {
+        let set = EndpointSet::new(Strategy::RoundRobin, addrs(3));
+        let picked: Vec<_> = (0..6).map(|_| pick(&set).addr().port()).collect();
+        assert_eq!(picked, [1, 2, 3, 1, 2, 3]);
+
+        let set = EndpointSet::new(Strategy::LeastOutstanding, addrs(3));
+        let mut held: Vec<_> = (0..3).map(|_| pick(&set)).collect();
+        drop(held.remove(1));
+        assert_eq!(pick(&set).addr().port(), 2);
+
+        // One of the two choices is always less loaded.
+        let set = EndpointSet::new(Strategy::PowerOfTwoChoices, addrs(2));
+        let _held: Vec<_> = (0..4).map(|_| pick(&set)).collect();
+        let outstanding: Vec<_> = set
+            .inner
+            .endpoints
+            .borrow()
+            .iter()
+            .map(|e| e.outstanding.get())
+            .collect();
+        assert!(outstanding.iter().all(|n| *n >= 1), "{outstanding:?}");
+
+        let header = HeaderName::from_static("x-user");
+        let set = EndpointSet::new(Strategy::ConsistentHash(header.clone()), addrs(5));
+        let mut headers = HeaderMap::new();
+        let mut owners = Vec::new();
+        for user in 0..20 {
+            headers.insert(&header, user.into());
+            let addr = set.pick(&headers).unwrap().addr();
+            assert_eq!(set.pick(&headers).unwrap().addr(), addr);
+            owners.push(addr);
+        }
+        // Only the users of the removed endpoint move.
+        let removed = addrs(5)[2];
+        set.set_endpoints(addrs(5).into_iter().filter(|a| *a != removed));
+        for (user, owner) in owners.into_iter().enumerate() {
+            headers.insert(&header, user.into());
+            let addr = set.pick(&headers).unwrap().addr();
+            assert!(addr == owner || owner == removed);
+        }
+    }
+
+    #[test]
+    fn ejection() {
+        let set = EndpointSet::new(Strategy::RoundRobin, addrs(2));
+        set.set_outlier_detection(Some(
+            OutlierDetection::new()
+                .consecutive_connect_failures(2)
+                .error_rate(0.5, 4),
+        ));
+        for _ in 0..2 {
+            let p = pick(&set);
+            assert_eq!(p.addr().port(), 1);
+            p.connect_failed();
+            pick(&set).connected();
+        }
+        assert_eq!(set.ejected(), &addrs(2)[..1]);
+        for _ in 0..4 {
+            let p = pick(&set);
+            assert_eq!(p.addr().port(), 2);
+            p.responded(http::StatusCode::SERVICE_UNAVAILABLE);
+        }
+        // All ejected, every endpoint is used again.
+        assert_eq!(set.ejected(), addrs(2));
+        let picked: Vec<_> = (0..2).map(|_| pick(&set).addr().port()).collect();
+        assert_eq!(picked, [1, 2]);
+
+        set.set_endpoints(Vec::new());
+        assert!(set.pick(&HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn recovery() {
+        let set = EndpointSet::new(Strategy::RoundRobin, addrs(2));
+        set.set_outlier_detection(Some(
+            OutlierDetection::new()
+                .error_rate(0.5, 2)
+                .ejection(Duration::from_millis(20)),
+        ));
+        let in_flight = pick(&set);
+        assert_eq!(in_flight.addr().port(), 1);
+        let mut errors = 0;
+        while errors < 2 {
+            let p = pick(&set);
+            if p.addr().port() == 1 {
+                p.responded(http::StatusCode::SERVICE_UNAVAILABLE);
+                errors += 1;
+            } else {
+                p.responded(http::StatusCode::OK);
+            }
+        }
+        assert_eq!(set.ejected(), &addrs(2)[..1]);
+        // Answered during the ejection, it is not counted.
+        in_flight.responded(http::StatusCode::SERVICE_UNAVAILABLE);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(set.ejected().is_empty());
+        let mut picked: Vec<_> = (0..8)
+            .map(|_| {
+                let p = pick(&set);
+                p.responded(http::StatusCode::OK);
+                p.addr().port()
+            })
+            .collect();
+        // The recovered endpoint gets traffic again.
+        picked.sort();
+        assert_eq!(picked, [1, 1, 1, 1, 2, 2, 2, 2]);
+        assert!(set.ejected().is_empty());
+    }
+
+    // Responds the port of the listener.
+    async fn serve(listener: TcpListener) {
+        let port = listener.local_addr().unwrap().port();
+        loop {
+            let (io, _) = listener.accept().await.unwrap();
+            monoio::spawn(async move {
+                let mut codec = ServerCodec::new(io);
+                while let Some(Ok(_)) = codec.next().await {
+                    let body = HttpBody::fixed_body(Some(port.to_string().into()));
+                    if codec
+                        .send_and_flush(http::Response::new(body))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn balance() {
+        let mut endpoints = Vec::new();
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            endpoints.push(listener.local_addr().unwrap());
+            monoio::spawn(serve(listener));
+        }
+        // Nothing listens on it.
+        let closed = StdListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let set = EndpointSet::new(Strategy::RoundRobin, [closed]);
+        set.set_outlier_detection(Some(
+            OutlierDetection::new().consecutive_connect_failures(1),
+        ));
+        let client = Builder::new().service("users", set.clone()).build();
+        let err = match client.get("http://users/").send().await {
+            Ok(_) => panic!("connected to a closed port"),
+            Err(e) => e,
+        };
+        assert_eq!(err.key().unwrap().port, closed.port());
+        assert_eq!(set.ejected(), [closed]);
+
+        set.set_endpoints([closed, endpoints[0], endpoints[1]]);
+        let mut ports = Vec::new();
+        for _ in 0..4 {
+            let resp = client.get("http://users/").send().await.unwrap();
+            ports.push(resp.bytes().await.unwrap());
+        }
+        // The ejected endpoint is skipped.
+        let expected: Vec<_> = endpoints.iter().map(|a| a.port().to_string()).collect();
+        assert!(expected.contains(&String::from_utf8_lossy(&ports[0]).into_owned()));
+        assert_ne!(ports[0], ports[1]);
+        assert_eq!(ports[..2], ports[2..]);
+
+        set.set_endpoints([]);
+        let err = match client.get("http://users/").send().await {
+            Ok(_) => panic!("no endpoint to send to"),
+            Err(e) => e,
+        };
+        assert!(matches!(err.kind(), crate::ErrorKind::NoEndpoint(name) if name == "users"));
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use super::{key::Key, listener::ConnectionListener};
+
+/// State of the circuit of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent.
+    Closed,
+    /// Requests fail fast with `ErrorKind::CircuitOpen`.
+    Open,
+    /// The cool-down is over, a limited number of probe requests are sent.
+    /// A successful probe closes the circuit, a failed one opens it again.
+    HalfOpen,
+}
+
+/// Settings of the per key circuit breaker, set with
+/// `Builder::circuit_breaker`.
+///
+/// Connect errors, failures to send the request and 5xx responses count as
+/// failures.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    consecutive_failures: u32,
+    error_rate: Option<(f64, u32)>,
+    cool_down: Duration,
+    probes: u32,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: 5,
+            error_rate: None,
+            cool_down: Duration::from_secs(10),
+            probes: 1,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open after `n` failures in a row, 5 by default.
+    pub fn consecutive_failures(mut self, n: u32) -> Self {
+        self.consecutive_failures = n.max(1);
+        self
+    }
+
+    /// Also open if at least `rate` of the requests fail. The rate is checked
+    /// every `min_requests` requests. Disabled by default.
+    pub fn error_rate(mut self, rate: f64, min_requests: u32) -> Self {
+        self.error_rate = Some((rate, min_requests.max(1)));
+        self
+    }
+
+    /// How long the circuit stays open before probing, 10 seconds by
+    /// default.
+    pub fn cool_down(mut self, duration: Duration) -> Self {
+        self.cool_down = duration;
+        self
+    }
+
+    /// Probe requests in flight at most when half-open, 1 by default.
+    pub fn probes(mut self, n: u32) -> Self {
+        self.probes = n.max(1);
+        self
+    }
+}
+
+struct Circuit {
+    state: CircuitState,
+    failures: u32,
+    requests: u32,
+    errors: u32,
+    opened_at: Instant,
+    probes: u32,
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            failures: 0,
+            requests: 0,
+            errors: 0,
+            opened_at: Instant::now(),
+            probes: 0,
+        }
+    }
+}
+
+/// Circuits of the keys of a client.
+pub(crate) struct Circuits {
+    config: CircuitBreaker,
+    circuits: RefCell<HashMap<Key, Circuit>>,
+    listener: Option<Rc<dyn ConnectionListener>>,
+}
+
+impl std::fmt::Debug for Circuits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Circuits")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl Circuits {
+    pub(crate) fn new(
+        config: CircuitBreaker,
+        listener: Option<Rc<dyn ConnectionListener>>,
+    ) -> Self {
+        Self {
+            config,
+            circuits: Default::default(),
+            listener,
+        }
+    }
+
+    pub(crate) fn state(&self, key: &Key) -> CircuitState {
+        self.circuits
+            .borrow()
+            .get(key)
+            .map_or(CircuitState::Closed, |c| c.state)
+    }
+
+    /// Returns None if the request must fail fast.
+    pub(crate) fn attempt(&self, key: &Key) -> Option<Attempt<'_>> {
+        let mut circuits = self.circuits.borrow_mut();
+        let circuit = circuits.entry(key.clone()).or_default();
+        let mut changed = None;
+        if circuit.state == CircuitState::Open {
+            if circuit.opened_at.elapsed() < self.config.cool_down {
+                return None;
+            }
+            changed = Some(transit(circuit, CircuitState::HalfOpen));
+        }
+        let probe = circuit.state == CircuitState::HalfOpen;
+        let admitted = !probe || circuit.probes < self.config.probes;
+        if probe && admitted {
+            circuit.probes += 1;
+        }
+        drop(circuits);
+        self.notify(key, changed);
+        admitted.then(|| Attempt {
+            circuits: self,
+            key: key.clone(),
+            probe,
+            done: false,
+        })
+    }
+
+    fn record(&self, key: &Key, probe: bool, success: bool) {
+        let mut circuits = self.circuits.borrow_mut();
+        let circuit = match circuits.get_mut(key) {
+            Some(circuit) => circuit,
+            None => return,
+        };
+        let changed = match (circuit.state, probe) {
+            (CircuitState::HalfOpen, true) => Some(transit(
+                circuit,
+                match success {
+                    true => CircuitState::Closed,
+                    false => CircuitState::Open,
+                },
+            )),
+            (CircuitState::Closed, false) => {
+                circuit.requests += 1;
+                if success {
+                    circuit.failures = 0;
+                } else {
+                    circuit.failures += 1;
+                    circuit.errors += 1;
+                }
+                let mut trip = circuit.failures >= self.config.consecutive_failures;
+                if let Some((rate, min_requests)) = self.config.error_rate {
+                    if circuit.requests >= min_requests {
+                        trip |= circuit.errors as f64 >= rate * circuit.requests as f64;
+                        circuit.requests = 0;
+                        circuit.errors = 0;
+                    }
+                }
+                trip.then(|| transit(circuit, CircuitState::Open))
+            }
+            // Requests sent before the state changed.
+            _ => None,
+        };
+        drop(circuits);
+        self.notify(key, changed);
+    }
+
+    fn release(&self, key: &Key) {
+        if let Some(circuit) = self.circuits.borrow_mut().get_mut(key) {
+            if circuit.state == CircuitState::HalfOpen {
+                circuit.probes = circuit.probes.saturating_sub(1);
+            }
+        }
+    }
+
+    // Called without borrowing the circuits, the listener may inspect them.
+    fn notify(&self, key: &Key, changed: Option<CircuitState>) {
+        if let (Some(listener), Some(state)) = (self.listener.as_ref(), changed) {
+            listener.on_circuit_state_change(key, state);
+        }
+    }
+}
+
+fn transit(circuit: &mut Circuit, state: CircuitState) -> CircuitState {
+    *circuit = Circuit {
+        state,
+        opened_at: match state {
+            CircuitState::Open => Instant::now(),
+            _ => circuit.opened_at,
+        },
+        ..Default::default()
+    };
+    state
+}
+
+/// A request let through the circuit, its outcome is recorded with
+/// `success` or `failure`. Dropping it records nothing.
+pub(crate) struct Attempt<'a> {
+    circuits: &'a Circuits,
+    key: Key,
+    probe: bool,
+    done: bool,
+}
+
+impl Attempt<'_> {
+    pub(crate) fn success(self) {
+        self.finish(true)
+    }
+
+    pub(crate) fn failure(self) {
+        self.finish(false)
+    }
+
+    fn finish(mut self, success: bool) {
+        self.done = true;
+        self.circuits.record(&self.key, self.probe, success);
+    }
+}
+
+impl Drop for Attempt<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.done {
+            self.circuits.release(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use monoio::{
+        io::{sink::SinkExt, stream::Stream},
+        net::TcpListener,
+    };
+    use monoio_http::{
+        common::body::{FixedBody, HttpBody},
+        h1::codec::ServerCodec,
+    };
+
+    use super::*;
+    use crate::Builder;
+
+    fn key() -> Key {
+        Key::try_from(&http::Uri::from_static("http://upstream/")).unwrap()
+    }
+
+    #[test]
+    fn error_rate() {
+        let circuits = Circuits::new(
+            CircuitBreaker::new()
+                .consecutive_failures(100)
+                .error_rate(0.5, 4)
+                .cool_down(Duration::ZERO)
+                .probes(2),
+            None,
+        );
+        let key = key();
+        for success in [true, false, true, false] {
+            let attempt = circuits.attempt(&key).unwrap();
+            match success {
+                true => attempt.success(),
+                false => attempt.failure(),
+            }
+        }
+        assert_eq!(circuits.state(&key), CircuitState::Open);
+
+        // Only two probes are let through, a dropped one frees its slot.
+        let first = circuits.attempt(&key).unwrap();
+        assert_eq!(circuits.state(&key), CircuitState::HalfOpen);
+        let second = circuits.attempt(&key).unwrap();
+        assert!(circuits.attempt(&key).is_none());
+        drop(second);
+        circuits.attempt(&key).unwrap().failure();
+        assert_eq!(circuits.state(&key), CircuitState::Open);
+        // Finished after the state changed, it is ignored.
+        first.success();
+        assert_eq!(circuits.state(&key), CircuitState::Open);
+    }
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<CircuitState>>);
+
+    impl ConnectionListener for Rc<Recorder> {
+        fn on_circuit_state_change(&self, _key: &Key, state: CircuitState) {
+            self.0.borrow_mut().push(state);
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn circuit_breaker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap());
+        let failing = Rc::new(Cell::new(true));
+        let served = Rc::new(Cell::new(0));
+        let (failing_, served_) = (failing.clone(), served.clone());
+        monoio::spawn(async move {
+            loop {
+                let (io, _) = listener.accept().await.unwrap();
+                let (failing, served) = (failing_.clone(), served_.clone());
+                monoio::spawn(async move {
+                    let mut codec = ServerCodec::new(io);
+                    while let Some(Ok(_)) = codec.next().await {
+                        served.set(served.get() + 1);
+                        let mut resp = http::Response::new(HttpBody::fixed_body(None));
+                        if failing.get() {
+                            *resp.status_mut() = http::StatusCode::INTERNAL_SERVER_ERROR;
+                        }
+                        if codec.send_and_flush(resp).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        let recorder = Rc::new(Recorder::default());
+        let client = Builder::new()
+            .connection_listener(recorder.clone())
+            .circuit_breaker(
+                CircuitBreaker::new()
+                    .consecutive_failures(2)
+                    .cool_down(Duration::from_millis(50)),
+            )
+            .build();
+        let key = Key::try_from(&uri.parse::<http::Uri>().unwrap()).unwrap();
+        for _ in 0..2 {
+            let resp = client.get(&uri).send().await.unwrap();
+            assert_eq!(resp.status(), 500);
+        }
+        assert_eq!(client.circuit_state(&key), Some(CircuitState::Open));
+
+        let err = match client.get(&uri).send().await {
+            Ok(_) => panic!("circuit is open"),
+            Err(e) => e,
+        };
+        assert!(matches!(err.kind(), crate::ErrorKind::CircuitOpen));
+        assert_eq!(served.get(), 2);
+
+        failing.set(false);
+        monoio::time::sleep(Duration::from_millis(60)).await;
+        let resp = client.get(&uri).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(client.circuit_state(&key), Some(CircuitState::Closed));
+        assert_eq!(
+            *recorder.0.borrow(),
+            [
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Closed
+            ]
+        );
+    }
+}
@@ -0,0 +1,1070 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, HeaderValue, Method, StatusCode, Version,
+};
+use monoio_http::{
+    common::{
+        body::{Body, BodyExt, FixedBody, HttpBody},
+        request::Request,
+        response::Response,
+    },
+    h1::payload::{stream_payload_pair, Payload},
+};
+
+use super::{date::http_date, info::Timings, Client};
+use crate::error::Stage;
+
+// Heuristic freshness is capped, RFC 9111 suggests 10% of the time since
+// the last modification.
+const MAX_HEURISTIC_FRESHNESS: Duration = Duration::from_secs(24 * 3600);
+const DEFAULT_MAX_ENTRY_SIZE: usize = 1 << 20;
+
+/// Returned by the methods of `CacheStore`.
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Where an `HttpCache` keeps responses, keyed by the request uri.
+///
+/// Stores should not fail, an entry which can not be read is a miss.
+pub trait CacheStore {
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<CachedResponse>>;
+
+    fn put<'a>(&'a self, key: &'a str, response: CachedResponse) -> StoreFuture<'a, ()>;
+
+    fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()>;
+}
+
+/// Whether a response is served by the cache, it can be found in
+/// `ClientResponse::extensions()` of GET requests if the cache is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// Served from the cache without contacting the server.
+    Hit,
+    /// The stored response is validated by the server with a 304.
+    Revalidated,
+    /// Fetched from the server.
+    Miss,
+}
+
+/// A stored response with its body.
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+    // The request headers selected by `Vary`, None if absent.
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    request_time: SystemTime,
+    response_time: SystemTime,
+}
+
+impl CachedResponse {
+    /// Approximate size in memory.
+    pub fn size(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        self.body.len() + headers
+    }
+
+    /// Serialize the response, it is read back with `decode`.
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(self.size() + 128);
+        let version = match self.version {
+            Version::HTTP_09 => "0.9",
+            Version::HTTP_10 => "1.0",
+            Version::HTTP_2 => "2",
+            Version::HTTP_3 => "3",
+            _ => "1.1",
+        };
+        let millis = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        buf.put_slice(
+            format!(
+                "status {}\nversion {}\nrequest-time {}\nresponse-time {}\n",
+                self.status.as_u16(),
+                version,
+                millis(self.request_time),
+                millis(self.response_time)
+            )
+            .as_bytes(),
+        );
+        for (name, value) in self.vary.iter() {
+            buf.put_slice(b"vary ");
+            buf.put_slice(name.as_str().as_bytes());
+            if let Some(value) = value {
+                buf.put_slice(b": ");
+                buf.put_slice(value.as_bytes());
+            }
+            buf.put_u8(b'\n');
+        }
+        for (name, value) in self.headers.iter() {
+            buf.put_slice(b"header ");
+            buf.put_slice(name.as_str().as_bytes());
+            buf.put_slice(b": ");
+            buf.put_slice(value.as_bytes());
+            buf.put_u8(b'\n');
+        }
+        buf.put_u8(b'\n');
+        buf.put_slice(&self.body);
+        buf.freeze()
+    }
+
+    /// Returns None if the data is not produced by `encode`.
+    pub fn decode(data: Bytes) -> Option<Self> {
+        let mut response = CachedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            vary: Vec::new(),
+            request_time: UNIX_EPOCH,
+            response_time: UNIX_EPOCH,
+        };
+        let mut pos = 0;
+        loop {
+            let end = pos + data[pos..].iter().position(|b| *b == b'\n')?;
+            let line = &data[pos..end];
+            pos = end + 1;
+            if line.is_empty() {
+                break;
+            }
+            let space = line.iter().position(|b| *b == b' ')?;
+            let (field, value) = (&line[..space], &line[space + 1..]);
+            let text = || std::str::from_utf8(value).ok();
+            let time = || Some(UNIX_EPOCH + Duration::from_millis(text()?.parse().ok()?));
+            match field {
+                b"status" => response.status = StatusCode::from_bytes(value).ok()?,
+                b"version" => {
+                    response.version = match value {
+                        b"0.9" => Version::HTTP_09,
+                        b"1.0" => Version::HTTP_10,
+                        b"2" => Version::HTTP_2,
+                        b"3" => Version::HTTP_3,
+                        _ => Version::HTTP_11,
+                    }
+                }
+                b"request-time" => response.request_time = time()?,
+                b"response-time" => response.response_time = time()?,
+                b"vary" => response.vary.push(match split_header(value) {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (HeaderName::from_bytes(value).ok()?, None),
+                }),
+                b"header" => {
+                    let (name, value) = split_header(value)?;
+                    response.headers.append(name, value);
+                }
+                _ => return None,
+            }
+        }
+        response.body = data.slice(pos..);
+        Some(response)
+    }
+
+    fn matches_vary(&self, headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| joined(headers, name) == *value)
+    }
+
+    fn age(&self, now: SystemTime) -> Duration {
+        let date = date_header(&self.headers, header::DATE).unwrap_or(self.response_time);
+        let apparent = self.response_time.duration_since(date).unwrap_or_default();
+        let age = self
+            .headers
+            .get(header::AGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let delay = self
+            .response_time
+            .duration_since(self.request_time)
+            .unwrap_or_default();
+        let resident = now.duration_since(self.response_time).unwrap_or_default();
+        apparent.max(age + delay) + resident
+    }
+
+    fn into_response(self, age: Duration, status: CacheStatus) -> Response<HttpBody> {
+        let mut response = Response::new(HttpBody::fixed_body(Some(self.body)));
+        *response.status_mut() = self.status;
+        *response.version_mut() = self.version;
+        *response.headers_mut() = self.headers;
+        response
+            .headers_mut()
+            .insert(header::AGE, HeaderValue::from(age.as_secs()));
+        response.extensions_mut().insert(status);
+        response
+    }
+}
+
+fn split_header(value: &[u8]) -> Option<(HeaderName, HeaderValue)> {
+    let colon = value.iter().position(|b| *b == b':')?;
+    let name = HeaderName::from_bytes(&value[..colon]).ok()?;
+    let value = HeaderValue::from_bytes(value[colon + 1..].trim_ascii_start()).ok()?;
+    Some((name, value))
+}
+
+/// All values of the header joined by commas.
+fn joined(headers: &HeaderMap, name: &HeaderName) -> Option<HeaderValue> {
+    let mut values = headers.get_all(name).iter();
+    let first = values.next()?;
+    let mut joined = first.as_bytes().to_vec();
+    for value in values {
+        joined.extend_from_slice(b", ");
+        joined.extend_from_slice(value.as_bytes());
+    }
+    HeaderValue::from_bytes(&joined).ok()
+}
+
+fn date_header(headers: &HeaderMap, name: HeaderName) -> Option<SystemTime> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(http_date)
+}
+
+/// Names in a comma separated header, e.g. `Vary`.
+fn tokens(headers: &HeaderMap, name: HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(name)
+        .into_iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    public: bool,
+    must_revalidate: bool,
+    max_age: Option<Duration>,
+    s_maxage: Option<Duration>,
+}
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut cc = Self::default();
+        for directive in tokens(headers, header::CACHE_CONTROL) {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            let secs = || value.and_then(|v| v.parse().ok()).map(Duration::from_secs);
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => cc.no_store = true,
+                // The field-name forms are taken as the whole response.
+                "no-cache" => cc.no_cache = true,
+                "private" => cc.private = true,
+                "public" => cc.public = true,
+                "must-revalidate" | "proxy-revalidate" => cc.must_revalidate = true,
+                // An invalid max-age makes the response stale.
+                "max-age" => cc.max_age = Some(secs().unwrap_or_default()),
+                "s-maxage" => cc.s_maxage = Some(secs().unwrap_or_default()),
+                _ => {}
+            }
+        }
+        cc
+    }
+}
+
+/// Responses of these statuses can be cached without explicit freshness.
+fn heuristically_cacheable(status: StatusCode) -> bool {
+    matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+/// Caches responses of GET requests as described in RFC 9111, set with
+/// `Builder::cache`.
+///
+/// Fresh responses are served without contacting the server, stale ones are
+/// revalidated with `If-None-Match` or `If-Modified-Since`. One variant is
+/// kept per uri, a request which does not match its `Vary` headers replaces
+/// it. Successful unsafe requests invalidate the entry of their uri.
+///
+/// Bodies without a `Content-Length` are passed through as they arrive and
+/// stored once they are complete, a body larger than the max entry size is
+/// not stored.
+#[derive(Clone)]
+pub struct HttpCache {
+    store: Rc<dyn CacheStore>,
+    shared: bool,
+    max_entry_size: usize,
+}
+
+impl std::fmt::Debug for HttpCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpCache")
+            .field("shared", &self.shared)
+            .field("max_entry_size", &self.max_entry_size)
+            .finish()
+    }
+}
+
+impl HttpCache {
+    pub fn new(store: impl CacheStore + 'static) -> Self {
+        Self {
+            store: Rc::new(store),
+            shared: true,
+            max_entry_size: DEFAULT_MAX_ENTRY_SIZE,
+        }
+    }
+
+    /// A cache in memory holding up to `max_bytes` of responses.
+    pub fn memory(max_bytes: usize) -> Self {
+        Self::new(MemoryStore::new(max_bytes))
+    }
+
+    /// A shared cache does not store `private` responses, and responses to
+    /// requests with `Authorization` unless they are explicitly allowed. It
+    /// is shared by default, as a client usually serves many users.
+    pub fn shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Only bodies up to the size are cached, 1 MiB by default. A larger
+    /// `Content-Length` is passed through without being buffered.
+    pub fn max_entry_size(mut self, bytes: usize) -> Self {
+        self.max_entry_size = bytes;
+        self
+    }
+
+    fn freshness(&self, response: &CachedResponse) -> Duration {
+        let cc = CacheControl::parse(&response.headers);
+        if let (true, Some(s_maxage)) = (self.shared, cc.s_maxage) {
+            return s_maxage;
+        }
+        if let Some(max_age) = cc.max_age {
+            return max_age;
+        }
+        let date = date_header(&response.headers, header::DATE).unwrap_or(response.response_time);
+        if response.headers.contains_key(header::EXPIRES) {
+            // An invalid date means expired.
+            return date_header(&response.headers, header::EXPIRES)
+                .and_then(|expires| expires.duration_since(date).ok())
+                .unwrap_or_default();
+        }
+        match date_header(&response.headers, header::LAST_MODIFIED) {
+            Some(modified) if heuristically_cacheable(response.status) => {
+                (date.duration_since(modified).unwrap_or_default() / 10)
+                    .min(MAX_HEURISTIC_FRESHNESS)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    fn storable(&self, request: &HeaderMap, response: &CachedResponse) -> bool {
+        let cc = CacheControl::parse(&response.headers);
+        if cc.no_store || (self.shared && cc.private) {
+            return false;
+        }
+        if self.shared
+            && request.contains_key(header::AUTHORIZATION)
+            && !(cc.public || cc.s_maxage.is_some() || cc.must_revalidate)
+        {
+            return false;
+        }
+        if tokens(&response.headers, header::VARY).any(|v| v == "*") {
+            return false;
+        }
+        let explicit = cc.max_age.is_some()
+            || (self.shared && cc.s_maxage.is_some())
+            || response.headers.contains_key(header::EXPIRES);
+        if !(explicit || cc.public || heuristically_cacheable(response.status)) {
+            return false;
+        }
+        // Useless if it can be neither fresh nor revalidated.
+        !self.freshness(response).is_zero()
+            || response.headers.contains_key(header::ETAG)
+            || response.headers.contains_key(header::LAST_MODIFIED)
+    }
+
+    async fn send<B, F, Fut>(
+        &self,
+        mut req: Request<B>,
+        body_limit: Option<usize>,
+        send: F,
+    ) -> crate::Result<Response<HttpBody>>
+    where
+        F: FnOnce(Request<B>) -> Fut,
+        Fut: Future<Output = crate::Result<Response<HttpBody>>>,
+    {
+        let key = req.uri().to_string();
+        if req.method() != Method::GET {
+            let invalidate = !req.method().is_safe();
+            let resp = send(req).await?;
+            if invalidate && (resp.status().is_success() || resp.status().is_redirection()) {
+                self.store.remove(&key).await;
+            }
+            return Ok(resp);
+        }
+        let req_cc = CacheControl::parse(req.headers());
+        // Partial responses are not cached.
+        if req_cc.no_store || req.headers().contains_key(header::RANGE) {
+            return send(req).await;
+        }
+
+        let now = SystemTime::now();
+        let cached = match self.store.get(&key).await {
+            Some(cached) if cached.matches_vary(req.headers()) => Some(cached),
+            _ => None,
+        };
+        // Conditional requests of the user are passed through.
+        let conditional = req.headers().contains_key(header::IF_NONE_MATCH)
+            || req.headers().contains_key(header::IF_MODIFIED_SINCE);
+        let cached = cached.filter(|_| !conditional);
+        if let Some(cached) = cached.as_ref() {
+            let age = cached.age(now);
+            let fresh = age < self.freshness(cached)
+                && !req_cc.no_cache
+                && !CacheControl::parse(&cached.headers).no_cache
+                && req_cc.max_age.is_none_or(|max_age| age <= max_age);
+            if fresh {
+                return Ok(cached.clone().into_response(age, CacheStatus::Hit));
+            }
+            let headers = req.headers_mut();
+            if let Some(etag) = cached.headers.get(header::ETAG) {
+                headers.insert(header::IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(modified) = cached.headers.get(header::LAST_MODIFIED) {
+                headers.insert(header::IF_MODIFIED_SINCE, modified.clone());
+            }
+        }
+
+        let req_headers = req.headers().clone();
+        let resp = send(req).await?;
+        let response_time = SystemTime::now();
+        if let (Some(mut cached), StatusCode::NOT_MODIFIED) = (cached, resp.status()) {
+            for (name, value) in resp.headers().iter() {
+                if name != header::CONTENT_LENGTH {
+                    cached.headers.insert(name, value.clone());
+                }
+            }
+            cached.request_time = now;
+            cached.response_time = response_time;
+            if self.storable(&req_headers, &cached) {
+                self.store.put(&key, cached.clone()).await;
+            } else {
+                self.store.remove(&key).await;
+            }
+            let age = cached.age(response_time);
+            return Ok(cached.into_response(age, CacheStatus::Revalidated));
+        }
+
+        let (mut parts, body) = resp.into_parts();
+        parts.extensions.insert(CacheStatus::Miss);
+        let vary = tokens(&parts.headers, header::VARY)
+            .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+            .map(|name| {
+                let value = joined(&req_headers, &name);
+                (name, value)
+            })
+            .collect();
+        let mut cached = CachedResponse {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body: Bytes::new(),
+            vary,
+            request_time: now,
+            response_time,
+        };
+        if parts.status == StatusCode::PARTIAL_CONTENT || !self.storable(&req_headers, &cached) {
+            parts.headers = cached.headers;
+            return Ok(Response::from_parts(parts, body));
+        }
+        let length = cached
+            .headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<usize>().ok());
+        match length {
+            Some(length) if length <= self.max_entry_size => {}
+            _ => {
+                // The entry replaced by the response is outdated.
+                self.store.remove(&key).await;
+                parts.headers = cached.headers.clone();
+                let body = match length {
+                    Some(_) => body,
+                    None => self.tee(key, cached, body),
+                };
+                return Ok(Response::from_parts(parts, body));
+            }
+        }
+
+        let data = match body_limit {
+            Some(limit) => body.bytes_limited(limit).await,
+            None => body.bytes().await,
+        }
+        .map_err(|e| crate::Error::from(e).stage(Stage::Body))?;
+        if let Some(timings) = parts.extensions.get::<Timings>() {
+            timings.finish_body();
+        }
+        cached.body = data.clone();
+        parts.headers = cached.headers.clone();
+        self.store.put(&key, cached).await;
+        Ok(Response::from_parts(
+            parts,
+            HttpBody::fixed_body(Some(data)),
+        ))
+    }
+}
+
+impl HttpCache {
+    /// Pass the body through as it arrives and store the response once the
+    /// body is complete, unless it grows larger than the max entry size.
+    fn tee(&self, key: String, mut cached: CachedResponse, mut body: HttpBody) -> HttpBody {
+        let (payload, mut sender) = stream_payload_pair();
+        let (store, max_entry_size) = (self.store.clone(), self.max_entry_size);
+        monoio::spawn(async move {
+            let mut copy = Some(BytesMut::new());
+            loop {
+                match body.next_data().await {
+                    Some(Ok(data)) => {
+                        if sender.is_closed() {
+                            return;
+                        }
+                        copy = copy.filter(|copy| copy.len() + data.len() <= max_entry_size);
+                        if let Some(copy) = copy.as_mut() {
+                            copy.extend_from_slice(&data);
+                        }
+                        sender.feed_data(Some(data));
+                    }
+                    Some(Err(e)) => {
+                        sender.feed_error(e);
+                        return;
+                    }
+                    None => {
+                        if let Ok(Some(trailers)) = body.trailers().await {
+                            sender.feed_trailers(trailers);
+                        }
+                        // Stored before the end, so it is found once the
+                        // body is read.
+                        if let Some(copy) = copy {
+                            cached.body = copy.freeze();
+                            store.put(&key, cached).await;
+                        }
+                        sender.feed_data(None);
+                        return;
+                    }
+                }
+            }
+        });
+        Payload::Stream(payload).into()
+    }
+}
+
+impl<C> Client<C> {
+    /// Send the request with `send` through the cache if it is enabled.
+    pub(crate) async fn send_cached<B, F, Fut>(
+        &self,
+        req: Request<B>,
+        body_limit: Option<usize>,
+        send: F,
+    ) -> crate::Result<Response<HttpBody>>
+    where
+        F: FnOnce(Request<B>) -> Fut,
+        Fut: Future<Output = crate::Result<Response<HttpBody>>>,
+    {
+        match self.shared.cfg.cache.as_ref() {
+            Some(cache) => cache.send(req, body_limit, send).await,
+            None => send(req).await,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, (CachedResponse, u64)>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    size: usize,
+}
+
+/// In memory store which evicts the least recently used responses.
+pub struct MemoryStore {
+    max_bytes: usize,
+    lru: RefCell<Lru>,
+}
+
+impl MemoryStore {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            lru: Default::default(),
+        }
+    }
+}
+
+impl Lru {
+    fn remove(&mut self, key: &str) -> Option<CachedResponse> {
+        let (response, tick) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+        self.size -= response.size();
+        Some(response)
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<CachedResponse>> {
+        let mut lru = self.lru.borrow_mut();
+        let lru = &mut *lru;
+        lru.tick += 1;
+        let response = lru.entries.get_mut(key).map(|(response, tick)| {
+            lru.order.remove(tick);
+            *tick = lru.tick;
+            lru.order.insert(lru.tick, key.to_string());
+            response.clone()
+        });
+        Box::pin(std::future::ready(response))
+    }
+
+    fn put<'a>(&'a self, key: &'a str, response: CachedResponse) -> StoreFuture<'a, ()> {
+        let mut lru = self.lru.borrow_mut();
+        lru.remove(key);
+        let size = response.size();
+        if size <= self.max_bytes {
+            while lru.size + size > self.max_bytes {
+                let oldest = match lru.order.first_key_value() {
+                    Some((_, key)) => key.clone(),
+                    None => break,
+                };
+                lru.remove(&oldest);
+            }
+            lru.tick += 1;
+            let tick = lru.tick;
+            lru.order.insert(tick, key.to_string());
+            lru.entries.insert(key.to_string(), (response, tick));
+            lru.size += size;
+        }
+        Box::pin(std::future::ready(()))
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        self.lru.borrow_mut().remove(key);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Store with a file per response in a directory, which must exist.
+#[cfg(feature = "cache-fs")]
+pub struct DiskStore {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(feature = "cache-fs")]
+impl DiskStore {
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    // Named with the FNV-1a hash of the key, which is stable across builds
+    // so the files are found after an upgrade.
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        self.dir.join(format!("{hash:016x}"))
+    }
+}
+
+// The key is written first, as the file names may collide.
+#[cfg(feature = "cache-fs")]
+impl CacheStore for DiskStore {
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<CachedResponse>> {
+        Box::pin(async move {
+            let data = Bytes::from(monoio::fs::read(self.path(key)).await.ok()?);
+            let newline = data.iter().position(|b| *b == b'\n')?;
+            if &data[..newline] != key.as_bytes() {
+                return None;
+            }
+            CachedResponse::decode(data.slice(newline + 1..))
+        })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, response: CachedResponse) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let path = self.path(key);
+            let mut data = BytesMut::from(key.as_bytes());
+            data.put_u8(b'\n');
+            data.put_slice(&response.encode());
+            // Written aside and renamed, readers never see a partial file.
+            let tmp = path.with_extension("tmp");
+            let (result, _) = monoio::fs::write(&tmp, data.freeze()).await;
+            if result.is_err() || monoio::fs::rename(&tmp, &path).await.is_err() {
+                let _ = monoio::fs::remove_file(&tmp).await;
+            }
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let _ = monoio::fs::remove_file(self.path(key)).await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use monoio::{
+        io::{sink::SinkExt, stream::Stream, AsyncReadRent, AsyncWriteRentExt},
+        net::TcpListener,
+    };
+    use monoio_http::{common::body::Body, h1::codec::ServerCodec};
+
+    use super::*;
+    use crate::Builder;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (HeaderName::from_static(name), value.parse().unwrap()))
+            .collect()
+    }
+
+    fn cached(pairs: &[(&'static str, &str)]) -> CachedResponse {
+        let now = SystemTime::now();
+        CachedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: headers(pairs),
+            body: Bytes::from_static(b"body"),
+            vary: Vec::new(),
+            request_time: now,
+            response_time: now,
+        }
+    }
+
+    #[test]
+    fn cache_control() {
+        let cc = CacheControl::parse(&headers(&[
+            ("cache-control", "Public, max-age=\"60\""),
+            ("cache-control", "s-maxage=x, no-cache=\"set-cookie\""),
+        ]));
+        assert_eq!(
+            cc,
+            CacheControl {
+                public: true,
+                no_cache: true,
+                max_age: Some(Duration::from_secs(60)),
+                s_maxage: Some(Duration::ZERO),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn freshness() {
+        let shared = HttpCache::memory(1024);
+        let private = HttpCache::memory(1024).shared(false);
+        let response = cached(&[("cache-control", "max-age=60, s-maxage=10")]);
+        assert_eq!(shared.freshness(&response), Duration::from_secs(10));
+        assert_eq!(private.freshness(&response), Duration::from_secs(60));
+
+        let response = cached(&[
+            ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("expires", "Sun, 06 Nov 1994 08:50:37 GMT"),
+        ]);
+        assert_eq!(shared.freshness(&response), Duration::from_secs(60));
+        let response = cached(&[("expires", "0")]);
+        assert_eq!(shared.freshness(&response), Duration::ZERO);
+        assert!(!shared.storable(&HeaderMap::new(), &response));
+
+        let response = cached(&[
+            ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("last-modified", "Sun, 06 Nov 1994 08:39:37 GMT"),
+        ]);
+        assert_eq!(shared.freshness(&response), Duration::from_secs(60));
+        assert!(shared.storable(&HeaderMap::new(), &response));
+
+        let response = cached(&[("cache-control", "private, max-age=60")]);
+        assert!(!shared.storable(&HeaderMap::new(), &response));
+        assert!(private.storable(&HeaderMap::new(), &response));
+        let response = cached(&[("cache-control", "max-age=60")]);
+        let auth = headers(&[("authorization", "Bearer x")]);
+        assert!(!shared.storable(&auth, &response));
+        assert!(private.storable(&auth, &response));
+
+        // Aged by the Age header and the time spent in the cache.
+        let mut response = cached(&[("age", "30")]);
+        response.response_time -= Duration::from_secs(5);
+        let age = response.age(response.response_time + Duration::from_secs(20));
+        assert_eq!(age, Duration::from_secs(50));
+    }
+
+    #[test]
+    fn encode() {
+        let mut response = cached(&[
+            ("etag", "\"v1\""),
+            ("set-cookie", "a=1"),
+            ("set-cookie", "b=2"),
+        ]);
+        response.status = StatusCode::NOT_FOUND;
+        response.version = Version::HTTP_2;
+        response.body = Bytes::from_static(b"line\n\nbody");
+        response.vary = vec![
+            (header::ACCEPT, Some("text/html".parse().unwrap())),
+            (header::ACCEPT_LANGUAGE, None),
+        ];
+        let decoded = CachedResponse::decode(response.encode()).unwrap();
+        assert_eq!(decoded.status, response.status);
+        assert_eq!(decoded.version, response.version);
+        assert_eq!(decoded.headers, response.headers);
+        assert_eq!(decoded.body, response.body);
+        assert_eq!(decoded.vary, response.vary);
+        let millis = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        assert_eq!(millis(decoded.request_time), millis(response.request_time));
+        assert!(CachedResponse::decode(Bytes::from_static(b"garbage")).is_none());
+    }
+
+    #[monoio::test_all]
+    async fn lru() {
+        let size = cached(&[]).size();
+        let store = MemoryStore::new(size * 2);
+        store.put("a", cached(&[])).await;
+        store.put("b", cached(&[])).await;
+        assert!(store.get("a").await.is_some());
+        store.put("c", cached(&[])).await;
+        assert!(store.get("b").await.is_none());
+        assert!(store.get("a").await.is_some());
+        store.remove("a").await;
+        assert!(store.get("a").await.is_none());
+        assert!(store.get("c").await.is_some());
+        assert_eq!(store.lru.borrow().size, size);
+    }
+
+    #[cfg(feature = "cache-fs")]
+    #[monoio::test_all]
+    async fn disk_store() {
+        // Each runtime of the test gets its own directory.
+        static RUN: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let run = RUN.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir =
+            std::env::temp_dir().join(format!("monoio-http-cache-{}-{run}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = DiskStore::new(&dir);
+        store.put("http://a/", cached(&[("etag", "\"v1\"")])).await;
+        let response = store.get("http://a/").await.unwrap();
+        assert_eq!(response.headers["etag"], "\"v1\"");
+        assert_eq!(response.body, "body");
+        assert!(store.get("http://b/").await.is_none());
+        store.remove("http://a/").await;
+        assert!(store.get("http://a/").await.is_none());
+        // The file names do not change across builds.
+        assert_eq!(
+            store.path("http://a/").file_name().unwrap(),
+            "80f62ee31b629023"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    async fn serve(listener: TcpListener, served: Rc<Cell<usize>>) {
+        loop {
+            let (io, _) = listener.accept().await.unwrap();
+            let served = served.clone();
+            monoio::spawn(async move {
+                let mut codec = ServerCodec::new(io);
+                while let Some(Ok(req)) = codec.next().await {
+                    served.set(served.get() + 1);
+                    let n = served.get().to_string();
+                    let mut resp = http::Response::builder();
+                    let mut body = Some(Bytes::from(n));
+                    match req.uri().path() {
+                        "/fresh" => resp = resp.header("cache-control", "max-age=60"),
+                        "/private" => resp = resp.header("cache-control", "private, max-age=60"),
+                        "/etag" => {
+                            resp = resp
+                                .header("cache-control", "no-cache")
+                                .header("etag", "\"v1\"");
+                            if req.headers().get("if-none-match").is_some() {
+                                resp = resp.status(304);
+                                body = None;
+                            }
+                        }
+                        "/vary" => {
+                            resp = resp
+                                .header("cache-control", "max-age=60")
+                                .header("vary", "accept");
+                            body = req
+                                .headers()
+                                .get("accept")
+                                .map(|v| Bytes::copy_from_slice(v.as_bytes()));
+                        }
+                        _ => {}
+                    }
+                    let resp = resp.body(HttpBody::fixed_body(body)).unwrap();
+                    if codec.send_and_flush(resp).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn cache() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = Rc::new(Cell::new(0));
+        monoio::spawn(serve(listener, served.clone()));
+        let client = Builder::new().cache(HttpCache::memory(1 << 20)).build();
+        let get = |path: &str, accept: &'static str| {
+            client
+                .get(format!("http://{addr}{path}"))
+                .header("accept", accept)
+                .send()
+        };
+        let status =
+            |resp: &crate::ClientResponse| *resp.extensions().get::<CacheStatus>().unwrap();
+
+        let resp = get("/fresh", "a").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Miss);
+        assert_eq!(resp.bytes().await.unwrap(), "1");
+        let resp = get("/fresh", "a").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Hit);
+        assert_eq!(resp.headers()["age"], "0");
+        assert_eq!(resp.bytes().await.unwrap(), "1");
+        assert_eq!(served.get(), 1);
+
+        // Invalidated by a successful unsafe request.
+        client
+            .post(format!("http://{addr}/fresh"))
+            .send()
+            .await
+            .unwrap();
+        let resp = get("/fresh", "a").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Miss);
+        assert_eq!(resp.bytes().await.unwrap(), "3");
+
+        get("/etag", "a").await.unwrap();
+        let resp = get("/etag", "a").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Revalidated);
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.bytes().await.unwrap(), "4");
+        assert_eq!(served.get(), 5);
+
+        get("/private", "a").await.unwrap();
+        let resp = get("/private", "a").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Miss);
+        assert_eq!(served.get(), 7);
+
+        get("/vary", "a").await.unwrap();
+        let resp = get("/vary", "b").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Miss);
+        assert_eq!(resp.bytes().await.unwrap(), "b");
+        let resp = get("/vary", "b").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Hit);
+        assert_eq!(served.get(), 9);
+    }
+
+    // Chunked bodies, the body of `/endless` never ends.
+    async fn serve_chunked(listener: TcpListener, served: Rc<Cell<usize>>) {
+        loop {
+            let (mut io, _) = listener.accept().await.unwrap();
+            let served = served.clone();
+            monoio::spawn(async move {
+                let mut buf = Vec::with_capacity(4096);
+                loop {
+                    let (res, b) = io.read(buf).await;
+                    buf = b;
+                    if !matches!(res, Ok(n) if n > 0) {
+                        return;
+                    }
+                    served.set(served.get() + 1);
+                    let head = "HTTP/1.1 200 OK\r\ncache-control: \
+                                max-age=60\r\ntransfer-encoding: chunked\r\n\r\n";
+                    io.write_all(head).await.0.unwrap();
+                    if buf.starts_with(b"GET /endless") {
+                        io.write_all("1\r\na\r\n").await.0.unwrap();
+                        let _ = io.read(Vec::with_capacity(1)).await;
+                        return;
+                    }
+                    let chunks = match buf.starts_with(b"GET /large") {
+                        true => "8\r\n01234567\r\n8\r\n89abcdef\r\n0\r\n\r\n",
+                        false => "1\r\na\r\n1\r\nb\r\n0\r\n\r\n",
+                    };
+                    buf.clear();
+                    io.write_all(chunks).await.0.unwrap();
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn bodies_without_length() {
+        let status =
+            |resp: &crate::ClientResponse| *resp.extensions().get::<CacheStatus>().unwrap();
+
+        // Larger than the entry size.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/fresh", listener.local_addr().unwrap());
+        let served = Rc::new(Cell::new(0));
+        monoio::spawn(serve(listener, served.clone()));
+        let client = Builder::new()
+            .cache(HttpCache::memory(1 << 20).max_entry_size(0))
+            .build();
+        for n in ["1", "2"] {
+            let resp = client.get(&uri).send().await.unwrap();
+            assert_eq!(status(&resp), CacheStatus::Miss);
+            assert_eq!(resp.bytes().await.unwrap(), n);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = Rc::new(Cell::new(0));
+        monoio::spawn(serve_chunked(listener, served.clone()));
+        let client = Builder::new()
+            .cache(HttpCache::memory(1 << 20).max_entry_size(10))
+            .build();
+        let get = |path: &str| client.get(format!("http://{addr}{path}")).send();
+
+        // Stored once the body is complete.
+        let resp = get("/").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Miss);
+        assert_eq!(resp.bytes().await.unwrap(), "ab");
+        let resp = get("/").await.unwrap();
+        assert_eq!(status(&resp), CacheStatus::Hit);
+        assert_eq!(resp.bytes().await.unwrap(), "ab");
+        assert_eq!(served.get(), 1);
+
+        // Grows larger than the entry size.
+        for _ in 0..2 {
+            let resp = get("/large").await.unwrap();
+            assert_eq!(status(&resp), CacheStatus::Miss);
+            assert_eq!(resp.bytes().await.unwrap(), "0123456789abcdef");
+        }
+        assert_eq!(served.get(), 3);
+
+        // Passed through as it arrives.
+        for _ in 0..2 {
+            let resp = monoio::time::timeout(Duration::from_secs(1), get("/endless"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(status(&resp), CacheStatus::Miss);
+            let mut body = resp.raw_body();
+            let chunk = body.next_data().await.unwrap().unwrap();
+            assert_eq!(chunk, "a");
+        }
+    }
+}
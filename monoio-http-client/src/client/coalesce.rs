@@ -0,0 +1,212 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use bytes::Bytes;
+use http::StatusCode;
+use monoio_http::common::{
+    body::{Body, BodyExt, FixedBody, HttpBody},
+    error::HttpError,
+    request::Request,
+    response::Response,
+};
+
+use super::{
+    abort::AbortHandle,
+    connector::{Connector, PooledConnector},
+    key::{Key, ServerName},
+    pool::PooledConnection,
+    unified::UnifiedTransportConnection,
+    Client,
+};
+use crate::error::Stage;
+
+impl<C> Client<C> {
+    /// Take an idle http2 connection of another host which may serve the
+    /// key, if coalescing is enabled with `Builder::http2_coalescing`.
+    pub(crate) fn coalesced_connection(
+        &self,
+        key: &Key,
+    ) -> Option<PooledConnection<Key, UnifiedTransportConnection>> {
+        if !self.shared.cfg.http2_coalescing {
+            return None;
+        }
+        let name = server_name(key.server_name.as_ref()?)?;
+        // Resolved once a connection with a matching certificate is found.
+        let mut addrs: Option<Vec<SocketAddr>> = None;
+        self.shared.connector.coalesce(key, |owner, info| {
+            owner.port == key.port
+                && owner.version == key.version
+                && info.tls().is_some_and(|tls| tls.covers(&name))
+                && info.peer_addr().is_some_and(|peer| {
+                    addrs
+                        .get_or_insert_with(|| {
+                            key.to_socket_addrs()
+                                .map(Iterator::collect)
+                                .unwrap_or_default()
+                        })
+                        .contains(&peer)
+                })
+        })
+    }
+
+    /// Send the request on a coalesced connection. The body is buffered, so
+    /// the request can be sent again on a connection of its own if the
+    /// server answers `421 Misdirected Request`.
+    pub(crate) async fn send_on_coalesced<B: Body<Data = Bytes, Error = HttpError> + 'static>(
+        &self,
+        conn: PooledConnection<Key, UnifiedTransportConnection>,
+        key: &Key,
+        req: Request<B>,
+        abort: Option<AbortHandle>,
+        body_limit: Option<usize>,
+    ) -> crate::Result<Response<HttpBody>>
+    where
+        PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+            Key,
+            Connection = PooledConnection<Key, UnifiedTransportConnection>,
+            Error = crate::Error,
+        >,
+    {
+        let (parts, body) = req.into_parts();
+        let body = body
+            .bytes()
+            .await
+            .map_err(|e| crate::Error::from(e).stage(Stage::Send))?;
+        let body = Some(body).filter(|body| !body.is_empty());
+        let mut retry = Request::new(HttpBody::fixed_body(body.clone()));
+        *retry.method_mut() = parts.method.clone();
+        *retry.uri_mut() = parts.uri.clone();
+        *retry.version_mut() = parts.version;
+        *retry.headers_mut() = parts.headers.clone();
+
+        let req = Request::from_parts(parts, HttpBody::fixed_body(body));
+        let resp = conn.send_request(req, abort.clone(), body_limit).await?;
+        if resp.status() != StatusCode::MISDIRECTED_REQUEST {
+            return Ok(resp);
+        }
+        #[cfg(feature = "logging")]
+        tracing::debug!("misdirected request, sending again to key: {key:?}");
+        let conn = self
+            .shared
+            .connector
+            .connect(key.clone())
+            .await
+            .map_err(|e| e.stage(Stage::Connect))?;
+        conn.send_request(retry, abort, body_limit).await
+    }
+}
+
+/// The name the certificate must cover.
+#[cfg(not(feature = "native-tls"))]
+fn server_name(name: &ServerName) -> Option<String> {
+    match name {
+        ServerName::DnsName(name) => Some(name.as_ref().to_string()),
+        ServerName::IpAddress(ip) => Some(ip.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "native-tls")]
+fn server_name(name: &ServerName) -> Option<String> {
+    Some(name.0.to_string())
+}
+
+#[cfg(all(test, not(feature = "native-tls")))]
+mod tests {
+    use std::{cell::Cell, rc::Rc, sync::Arc};
+
+    use monoio::net::TcpListener;
+    use monoio_http::h2;
+
+    use super::*;
+    use crate::{
+        client::{info::ConnectionInfo, unified::UnifiedTransportConnector},
+        Builder,
+    };
+
+    // The certificate covers `localhost` and `127.0.0.1`.
+    const CERT: &[u8] = include_bytes!("../../tests/certs/coalesce.crt.der");
+    const KEY: &[u8] = include_bytes!("../../tests/certs/coalesce.key.der");
+
+    // Responds the index of the connection, requests to `/misdirected` are
+    // answered with 421 unless the host is the first one of the connection.
+    async fn serve(listener: TcpListener, accepted: Rc<Cell<usize>>) {
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(CERT.to_vec())],
+                rustls::PrivateKey(KEY.to_vec()),
+            )
+            .unwrap();
+        let acceptor = monoio_rustls::TlsAcceptor::from(config);
+        loop {
+            let (io, _) = listener.accept().await.unwrap();
+            let n = accepted.get();
+            accepted.set(n + 1);
+            let acceptor = acceptor.clone();
+            monoio::spawn(async move {
+                let io = acceptor.accept(io).await.unwrap();
+                let mut conn = h2::server::handshake(io).await.unwrap();
+                let mut first_host = None;
+                while let Some(Ok((req, mut respond))) = conn.accept().await {
+                    let host = req.uri().host().unwrap().to_string();
+                    let first_host = first_host.get_or_insert(host.clone());
+                    let mut resp = http::Response::new(());
+                    if req.uri().path() == "/misdirected" && *first_host != host {
+                        *resp.status_mut() = StatusCode::MISDIRECTED_REQUEST;
+                    }
+                    let mut send = respond.send_response(resp, false).unwrap();
+                    send.send_data(n.to_string().into(), true).unwrap();
+                }
+            });
+        }
+    }
+
+    fn client() -> Client {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&rustls::Certificate(CERT.to_vec())).unwrap();
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Builder::new()
+            .http2_client()
+            .http2_coalescing()
+            .build_with_connector(UnifiedTransportConnector::with_tls_config(Arc::new(config)))
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn coalesce() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = Rc::new(Cell::new(0));
+        monoio::spawn(serve(listener, accepted.clone()));
+        let get = |client: Client, uri: String| async move {
+            let resp = client.get(uri).send().await.unwrap();
+            let info = resp.extensions().get::<ConnectionInfo>().unwrap();
+            let tls = info.tls().unwrap();
+            assert!(tls.covers("localhost") && tls.covers("127.0.0.1"));
+            assert!(!tls.covers("example.com"));
+            (resp.status(), resp.bytes().await.unwrap())
+        };
+
+        let client = client();
+        let (status, body) = get(client.clone(), format!("https://localhost:{port}/")).await;
+        assert_eq!((status, body), (StatusCode::OK, "0".into()));
+        let (status, body) = get(client.clone(), format!("https://127.0.0.1:{port}/")).await;
+        assert_eq!((status, body), (StatusCode::OK, "0".into()));
+        assert_eq!(accepted.get(), 1);
+
+        // The misdirected request is sent again on a new connection, which
+        // is kept for the host.
+        let client = self::client();
+        let (_, body) = get(client.clone(), format!("https://localhost:{port}/")).await;
+        assert_eq!(body, "1");
+        let uri = format!("https://127.0.0.1:{port}/misdirected");
+        let (status, body) = get(client.clone(), uri.clone()).await;
+        assert_eq!((status, body), (StatusCode::OK, "2".into()));
+        let (status, body) = get(client.clone(), uri).await;
+        assert_eq!((status, body), (StatusCode::OK, "2".into()));
+        assert_eq!(accepted.get(), 3);
+    }
+}
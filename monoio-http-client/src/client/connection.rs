@@ -1,24 +1,34 @@
-use std::{future::poll_fn, task::Poll};
+use std::{
+    cell::Cell,
+    future::{poll_fn, Future},
+    rc::Rc,
+    task::Poll,
+    time::Instant,
+};
 
 use bytes::Bytes;
+use local_sync::mpsc::unbounded;
 use monoio::io::{sink::SinkExt, stream::Stream, AsyncReadRent, AsyncWriteRent, Split};
 use monoio_http::{
     common::{
         body::{Body, HttpBody, StreamHint},
-        error::HttpError,
+        error::{BodyTooLarge, HttpError},
         request::Request,
         response::Response,
     },
     h1::{
         codec::{
-            decoder::{DecodeError, PayloadDecoder},
+            decoder::{ChunkedBodyDecoder, DecodeError, FixedBodyDecoder, PayloadDecoder},
             ClientCodec,
         },
-        payload::{fixed_payload_pair, stream_payload_pair, Payload},
+        payload::{fixed_payload_pair, stream_payload_pair, Payload, PayloadSender},
     },
-    h2::{client::SendRequest, SendStream},
+    h2::{client::SendRequest, Reason, SendStream},
 };
 
+use super::{abort::AbortHandle, info::Timings};
+use crate::error::Stage;
+
 pub struct StreamBodyTask<B: Body> {
     stream_pipe: SendStream<Bytes>,
     body: B,
@@ -82,20 +92,35 @@ impl<B: Body<Data = Bytes>> StreamBodyTask<B> {
                         let _ = self.stream_pipe.send_data(Bytes::new(), true);
                         self.data_done = true;
                     }
-                    StreamHint::Fixed => {
-                        if let Some(Ok(data)) = self.body.next_data().await {
+                    StreamHint::Fixed => match self.body.next_data().await {
+                        Some(Ok(data)) => {
                             let _ = self.stream_pipe.send_data(data, true);
                             self.data_done = true;
                         }
-                    }
-                    StreamHint::Stream => {
-                        if let Some(Ok(data)) = self.body.next_data().await {
-                            let _ = self.stream_pipe.send_data(data, false);
-                        } else {
+                        Some(Err(_)) => return self.fail(),
+                        None => {
                             let _ = self.stream_pipe.send_data(Bytes::new(), true);
                             self.data_done = true;
                         }
-                    }
+                    },
+                    StreamHint::Stream => match self.body.next_data().await {
+                        Some(Ok(data)) => {
+                            let _ = self.stream_pipe.send_data(data, false);
+                        }
+                        Some(Err(_)) => return self.fail(),
+                        None => {
+                            match self.body.trailers().await {
+                                Ok(Some(trailers)) => {
+                                    let _ = self.stream_pipe.send_trailers(trailers);
+                                }
+                                Ok(None) => {
+                                    let _ = self.stream_pipe.send_data(Bytes::new(), true);
+                                }
+                                Err(_) => return self.fail(),
+                            }
+                            self.data_done = true;
+                        }
+                    },
                 }
             } else {
                 let stream_rst = poll_fn(|cx| match self.stream_pipe.poll_reset(cx) {
@@ -109,12 +134,197 @@ impl<B: Body<Data = Bytes>> StreamBodyTask<B> {
                     tracing::error!("H2 StreamBodyTask stream reset");
                     return;
                 }
-
-                // TODO: Handle trailer
                 break;
             }
         }
     }
+
+    // The body failed, reset the stream so the server doesn't take the
+    // truncated body as complete.
+    fn fail(&mut self) {
+        #[cfg(feature = "logging")]
+        tracing::error!("H2 StreamBodyTask body error");
+        self.stream_pipe.send_reset(Reason::INTERNAL_ERROR);
+    }
+}
+
+// Max bytes read from an unwanted http1 body before giving up the connection.
+const DRAIN_LIMIT: usize = 64 * 1024;
+// Http1 bodies with Content-Length larger than it are streamed in pieces
+// rather than buffered whole.
+const MAX_BUFFERED_FIXED: usize = 64 * 1024;
+
+/// Http1 response body which is still on the wire.
+/// It must be read before the connection can be reused.
+pub struct PendingBody {
+    decoder: PayloadDecoder<FixedBodyDecoder, ChunkedBodyDecoder>,
+    sender: PayloadSender<Bytes, HttpError>,
+}
+
+impl PendingBody {
+    /// Read the body from the connection and feed it to the payload.
+    /// Returns true if the body is fully read and the connection can be reused.
+    ///
+    /// If the payload has been dropped, the body is drained up to a small
+    /// limit; beyond that the connection should be discarded. A body larger
+    /// than `body_limit` fails the payload with `BodyTooLarge`, the connection
+    /// should be discarded too.
+    pub async fn read_from<IO>(
+        self,
+        codec: &mut ClientCodec<IO>,
+        abort: Option<&AbortHandle>,
+        body_limit: Option<usize>,
+    ) -> bool
+    where
+        IO: AsyncReadRent + AsyncWriteRent + Split,
+    {
+        let limit = body_limit.unwrap_or(usize::MAX);
+        let mut framed_payload = match &self.sender {
+            PayloadSender::Stream(_) => self.decoder.with_io(codec).stream_fixed(),
+            _ => self.decoder.with_io(codec),
+        };
+        match self.sender {
+            PayloadSender::None => true,
+            PayloadSender::Fixed(sender, length) => {
+                if sender.is_closed() && length > DRAIN_LIMIT {
+                    return false;
+                }
+                // Checked before the body is buffered.
+                if length > limit {
+                    sender.feed(Err(BodyTooLarge { limit }.into()));
+                    return false;
+                }
+                match or_abort(framed_payload.next_data(), abort).await {
+                    Some(Some(Ok(data))) => {
+                        sender.feed(Ok(data));
+                        true
+                    }
+                    Some(Some(Err(e))) => {
+                        #[cfg(feature = "logging")]
+                        tracing::error!("decode upstream response error {:?}", e);
+                        sender.feed(Err(e));
+                        false
+                    }
+                    Some(None) => false,
+                    None => {
+                        sender.feed(Err(aborted_error()));
+                        false
+                    }
+                }
+            }
+            PayloadSender::Stream(mut sender) => {
+                let mut drained = 0;
+                let mut received = 0;
+                loop {
+                    match or_abort(framed_payload.next_data(), abort).await {
+                        Some(Some(Ok(data))) => {
+                            if sender.is_closed() {
+                                drained += data.len();
+                                if drained > DRAIN_LIMIT {
+                                    return false;
+                                }
+                            } else {
+                                received += data.len();
+                                if received > limit {
+                                    sender.feed_error(BodyTooLarge { limit }.into());
+                                    return false;
+                                }
+                                sender.feed_data(Some(data));
+                            }
+                        }
+                        Some(Some(Err(e))) => {
+                            #[cfg(feature = "logging")]
+                            tracing::error!("decode upstream response error {:?}", e);
+                            sender.feed_error(e);
+                            return false;
+                        }
+                        Some(None) => {
+                            if let Ok(Some(trailers)) = framed_payload.trailers().await {
+                                sender.feed_trailers(trailers);
+                            }
+                            sender.feed_data(None);
+                            return true;
+                        }
+                        None => {
+                            sender.feed_error(aborted_error());
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Relay the http2 response body to a payload, a chunk is read once the
+/// previous one is taken so the flow control still applies. The completion
+/// of the body is recorded in `Timings`, and with a limit the payload fails
+/// with `BodyTooLarge` once more is received, like http1 bodies.
+pub(crate) fn relay_h2_body(
+    resp: Response<HttpBody>,
+    limit: Option<usize>,
+    drain: Option<unbounded::Tx<()>>,
+) -> Response<HttpBody> {
+    let (parts, mut body) = resp.into_parts();
+    let timings = parts.extensions.get::<Timings>().cloned();
+    let limit = limit.unwrap_or(usize::MAX);
+    let (payload, mut sender) = stream_payload_pair();
+    monoio::spawn(async move {
+        let mut received = 0;
+        loop {
+            match body.next_data().await {
+                Some(Ok(data)) => {
+                    received += data.len();
+                    if received > limit {
+                        sender.feed_error(BodyTooLarge { limit }.into());
+                        return;
+                    }
+                    sender.feed_data(Some(data));
+                    sender.drained().await;
+                    if sender.is_closed() {
+                        return;
+                    }
+                }
+                Some(Err(e)) => {
+                    sender.feed_error(e);
+                    return;
+                }
+                None => {
+                    if let Ok(Some(trailers)) = body.trailers().await {
+                        sender.feed_trailers(trailers);
+                    }
+                    drop(body);
+                    if let Some(timings) = timings {
+                        timings.finish_body();
+                    }
+                    sender.feed_data(None);
+                    // The drain token is kept until the end is seen, so a
+                    // graceful shutdown waits for the reader.
+                    sender.drained().await;
+                    drop(drain);
+                    return;
+                }
+            }
+        }
+    });
+    Response::from_parts(parts, Payload::Stream(payload).into())
+}
+
+/// Run the future until it completes or the request is aborted.
+pub(crate) async fn or_abort<F: Future>(fut: F, abort: Option<&AbortHandle>) -> Option<F::Output> {
+    match abort {
+        Some(abort) => {
+            monoio::select! {
+                output = fut => Some(output),
+                _ = abort.aborted() => None,
+            }
+        }
+        None => Some(fut.await),
+    }
+}
+
+fn aborted_error() -> HttpError {
+    std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "request aborted").into()
 }
 
 pub enum HttpConnection<IO: AsyncWriteRent> {
@@ -123,75 +333,77 @@ pub enum HttpConnection<IO: AsyncWriteRent> {
 }
 
 impl<IO: AsyncReadRent + AsyncWriteRent + Split> HttpConnection<IO> {
+    /// Send the request and wait for the response head.
+    ///
+    /// For http1, the response body is returned as `PendingBody` which must be
+    /// read from the connection by the caller. The returned bool indicates
+    /// whether the http2 connection should be removed from the pool.
+    ///
+    /// `Timings` of sending the request and waiting for the response head are
+    /// put into the response extensions.
     pub async fn send_request<B>(
         &mut self,
         request: Request<B>,
-    ) -> (crate::Result<Response<HttpBody>>, bool)
+        abort: Option<&AbortHandle>,
+    ) -> (
+        crate::Result<(Response<HttpBody>, Option<PendingBody>)>,
+        bool,
+    )
     where
         B: Body<Data = Bytes, Error = HttpError> + 'static,
     {
+        let start = Instant::now();
         match self {
             Self::H1(handle) => {
                 if let Err(e) = handle.send_and_flush(request).await {
                     #[cfg(feature = "logging")]
                     tracing::error!("send upstream request error {:?}", e);
-                    return (Err(e.into()), false);
+                    return (Err(crate::Error::from(e).stage(Stage::Send)), false);
                 }
 
+                let sent = start.elapsed();
                 match handle.next().await {
                     Some(Ok(resp)) => {
-                        let (parts, payload_decoder) = resp.into_parts();
-                        match payload_decoder {
-                            PayloadDecoder::None => {
-                                let payload = Payload::None;
-                                let response = Response::from_parts(parts, payload.into());
-                                (Ok(response), false)
+                        let (mut parts, decoder) = resp.into_parts();
+                        parts
+                            .extensions
+                            .insert(Timings::new(sent, start.elapsed() - sent));
+                        let (payload, sender) = match &decoder {
+                            PayloadDecoder::None => (Payload::None, PayloadSender::None),
+                            PayloadDecoder::Fixed(fixed)
+                                if fixed.length() <= MAX_BUFFERED_FIXED =>
+                            {
+                                let (payload, sender) = fixed_payload_pair();
+                                (
+                                    Payload::Fixed(payload),
+                                    PayloadSender::Fixed(sender, fixed.length()),
+                                )
                             }
-                            PayloadDecoder::Fixed(_) => {
-                                let mut framed_payload = payload_decoder.with_io(handle);
-                                let (payload, payload_sender) = fixed_payload_pair();
-                                if let Some(data) = framed_payload.next_data().await {
-                                    payload_sender.feed(data)
-                                }
-                                let payload = Payload::Fixed(payload);
-                                let response = Response::from_parts(parts, payload.into());
-                                (Ok(response), false)
-                            }
-                            PayloadDecoder::Streamed(_) => {
-                                let mut framed_payload = payload_decoder.with_io(handle);
-                                let (payload, mut payload_sender) = stream_payload_pair();
-                                loop {
-                                    match framed_payload.next_data().await {
-                                        Some(Ok(data)) => payload_sender.feed_data(Some(data)),
-                                        Some(Err(e)) => {
-                                            #[cfg(feature = "logging")]
-                                            tracing::error!(
-                                                "decode upstream response error {:?}",
-                                                e
-                                            );
-                                            return (Err(e.into()), false);
-                                        }
-                                        None => {
-                                            payload_sender.feed_data(None);
-                                            break;
-                                        }
-                                    }
-                                }
-                                let payload = Payload::Stream(payload);
-                                let response = Response::from_parts(parts, payload.into());
-                                (Ok(response), false)
+                            PayloadDecoder::Fixed(_) | PayloadDecoder::Streamed(_) => {
+                                let (payload, sender) = stream_payload_pair();
+                                (Payload::Stream(payload), PayloadSender::Stream(sender))
                             }
-                        }
+                        };
+                        let response = Response::from_parts(parts, payload.into());
+                        let pending = match sender {
+                            PayloadSender::None => None,
+                            sender => Some(PendingBody { decoder, sender }),
+                        };
+                        (Ok((response, pending)), false)
                     }
                     Some(Err(e)) => {
                         #[cfg(feature = "logging")]
                         tracing::error!("decode upstream response error {:?}", e);
-                        (Err(e.into()), false)
+                        (Err(crate::Error::from(e).stage(Stage::Receive)), false)
                     }
                     None => {
                         #[cfg(feature = "logging")]
                         tracing::error!("upstream return eof");
-                        (Err(DecodeError::UnexpectedEof.into()), false)
+                        (
+                            Err(crate::Error::from(DecodeError::UnexpectedEof)
+                                .stage(Stage::Receive)),
+                            false,
+                        )
                     }
                 }
             }
@@ -208,7 +420,7 @@ impl<IO: AsyncReadRent + AsyncWriteRent + Split> HttpConnection<IO> {
                     Err(e) => {
                         #[cfg(feature = "logging")]
                         tracing::error!("H2 conn manager ready error: {e:?}");
-                        return (Err(e.into()), true);
+                        return (Err(crate::Error::from(e).stage(Stage::Queue)), true);
                     }
                 };
 
@@ -217,25 +429,50 @@ impl<IO: AsyncReadRent + AsyncWriteRent + Split> HttpConnection<IO> {
                     Err(e) => {
                         #[cfg(feature = "logging")]
                         tracing::debug!("client send request error: {e}");
-                        return (Err(e.into()), false);
+                        return (Err(crate::Error::from(e).stage(Stage::Queue)), false);
                     }
                 };
 
+                let abort_handle = abort.cloned();
+                let sent_at = Rc::new(Cell::new(None));
+                let body_sent_at = sent_at.clone();
                 monoio::spawn(async move {
                     let mut stream_task = StreamBodyTask::new(send_stream, body);
-                    stream_task.drive().await;
+                    match abort_handle {
+                        Some(abort_handle) => {
+                            let aborted = or_abort(stream_task.drive(), Some(&abort_handle))
+                                .await
+                                .is_none();
+                            if aborted {
+                                stream_task.stream_pipe.send_reset(Reason::CANCEL);
+                            } else {
+                                body_sent_at.set(Some(Instant::now()));
+                                // Keep the stream so that it can be reset later.
+                                abort_handle.park_h2_stream(stream_task.stream_pipe);
+                            }
+                        }
+                        None => {
+                            stream_task.drive().await;
+                            body_sent_at.set(Some(Instant::now()));
+                        }
+                    }
                 });
 
                 match resp_fut.await {
                     Ok(resp) => {
                         #[cfg(feature = "logging")]
                         tracing::debug!("H2 Conn Response:");
-                        (Ok(HttpBody::response(resp)), false)
+                        let head_at = Instant::now();
+                        let sent_at = sent_at.get().unwrap_or(head_at);
+                        let mut resp = HttpBody::response(resp);
+                        resp.extensions_mut()
+                            .insert(Timings::new(sent_at - start, head_at - sent_at));
+                        (Ok((resp, None)), false)
                     }
                     Err(e) => {
                         #[cfg(feature = "logging")]
                         tracing::debug!("H2 conn Response error {e:?}");
-                        (Err(e.into()), false)
+                        (Err(crate::Error::from(e).stage(Stage::Receive)), false)
                     }
                 }
             }
@@ -249,6 +486,14 @@ impl<IO: AsyncReadRent + AsyncWriteRent + Split> HttpConnection<IO> {
         }
     }
 
+    /// The request handle of a http2 connection.
+    pub fn http2_send_request(&self) -> Option<&SendRequest<Bytes>> {
+        match self {
+            Self::H1(_) => None,
+            Self::H2(h) => Some(h),
+        }
+    }
+
     pub fn http2_conn_clone(&self) -> Self {
         match self {
             Self::H1(_) => unreachable!(),
@@ -256,3 +501,249 @@ impl<IO: AsyncReadRent + AsyncWriteRent + Split> HttpConnection<IO> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, time::Duration};
+
+    use http::HeaderMap;
+    use monoio::{
+        io::{AsyncReadRent, AsyncWriteRentExt},
+        net::TcpListener,
+    };
+    use monoio_http::h2;
+
+    use super::*;
+    use crate::{Builder, ErrorKind};
+
+    fn chunk(data: &[u8]) -> Vec<u8> {
+        let mut chunk = format!("{:x}\r\n", data.len()).into_bytes();
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(b"\r\n");
+        chunk
+    }
+
+    // Count accepted connections. The chunked body of `/pieces` is sent in
+    // two parts, `/large` is larger than DRAIN_LIMIT and `/stall` never ends.
+    async fn serve(listener: TcpListener, accepted: Rc<Cell<usize>>) {
+        loop {
+            let (mut io, _) = listener.accept().await.unwrap();
+            accepted.set(accepted.get() + 1);
+            monoio::spawn(async move {
+                let mut buf = Vec::with_capacity(4096);
+                loop {
+                    let (r, b) = io.read(buf).await;
+                    buf = b;
+                    if !matches!(r, Ok(n) if n > 0) {
+                        return;
+                    }
+                    let path = buf.split(|b| *b == b' ').nth(1).unwrap().to_vec();
+                    buf.clear();
+                    let head = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n";
+                    io.write_all(head).await.0.unwrap();
+                    match path.as_slice() {
+                        b"/pieces" => {
+                            io.write_all(chunk(b"a")).await.0.unwrap();
+                            monoio::time::sleep(Duration::from_millis(20)).await;
+                            io.write_all(chunk(b"b")).await.0.unwrap();
+                        }
+                        b"/large" => {
+                            for _ in 0..4 {
+                                io.write_all(chunk(&[0; DRAIN_LIMIT / 2])).await.0.unwrap();
+                            }
+                        }
+                        b"/stall" => {
+                            io.write_all(chunk(b"a")).await.0.unwrap();
+                            monoio::time::sleep(Duration::from_secs(10)).await;
+                            return;
+                        }
+                        _ => {
+                            io.write_all(chunk(b"ok")).await.0.unwrap();
+                        }
+                    }
+                    io.write_all("0\r\n\r\n").await.0.unwrap();
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn background_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        monoio::spawn(serve(listener, accepted.clone()));
+
+        let client = Builder::new().build();
+        for _ in 0..2 {
+            // The head is returned before the body is complete.
+            let resp = client
+                .get(format!("http://{addr}/pieces"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.bytes().await.unwrap(), "ab");
+            // Wait for the body task to return the connection.
+            monoio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(accepted.get(), 1);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn drain() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        monoio::spawn(serve(listener, accepted.clone()));
+        let client = Builder::new().build();
+        let get = |path: &str| client.get(format!("http://{addr}{path}")).send();
+
+        // A small unwanted body is discarded and the connection reused.
+        drop(get("/").await.unwrap());
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(get("/").await.unwrap().bytes().await.unwrap(), "ok");
+        assert_eq!(accepted.get(), 1);
+
+        // Beyond the limit the connection is given up.
+        drop(get("/large").await.unwrap());
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(get("/").await.unwrap().bytes().await.unwrap(), "ok");
+        assert_eq!(accepted.get(), 2);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn unread_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        monoio::spawn(serve(listener, accepted.clone()));
+        let client = Builder::new().build();
+
+        // The connection is busy until the body is read.
+        let _resp = client
+            .get(format!("http://{addr}/stall"))
+            .send()
+            .await
+            .unwrap();
+        let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "ok");
+        assert_eq!(accepted.get(), 2);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn abort_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        monoio::spawn(serve(listener, accepted.clone()));
+        let client = Builder::new().build();
+
+        let mut req = client.get(format!("http://{addr}/stall"));
+        let abort = req.abort_handle();
+        let resp = req.send().await.unwrap();
+        monoio::spawn(async move {
+            monoio::time::sleep(Duration::from_millis(10)).await;
+            abort.abort();
+        });
+        assert!(resp.bytes().await.is_err());
+
+        // The aborted connection is not reused.
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "ok");
+        assert_eq!(accepted.get(), 2);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn abort_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            // Accept and never read, the request can't be fully written.
+            let (_io, _) = listener.accept().await.unwrap();
+            monoio::time::sleep(Duration::from_secs(10)).await;
+        });
+        let client = Builder::new().build();
+
+        let mut req = client.post(format!("http://{addr}/"));
+        let abort = req.abort_handle();
+        monoio::spawn(async move {
+            monoio::time::sleep(Duration::from_millis(20)).await;
+            abort.abort();
+        });
+        let err = match req.send_body(vec![0; 64 << 20].into()).await {
+            Ok(_) => panic!("sent after abort"),
+            Err(e) => e,
+        };
+        assert!(matches!(err.kind(), ErrorKind::Aborted), "{err:?}");
+    }
+
+    // Yields a chunk, then fails in `next_data` or in `trailers` a bit later.
+    struct Failing {
+        sent: bool,
+        data_error: bool,
+    }
+
+    impl Body for Failing {
+        type Data = Bytes;
+        type Error = HttpError;
+
+        async fn next_data(&mut self) -> Option<Result<Bytes, HttpError>> {
+            if !self.sent {
+                self.sent = true;
+                return Some(Ok("a".into()));
+            }
+            // Let the server accept the stream before it is reset.
+            monoio::time::sleep(Duration::from_millis(10)).await;
+            self.data_error
+                .then(|| Err(std::io::Error::other("failed").into()))
+        }
+
+        fn stream_hint(&self) -> StreamHint {
+            StreamHint::Stream
+        }
+
+        async fn trailers(&mut self) -> Result<Option<HeaderMap>, HttpError> {
+            Err(std::io::Error::other("failed").into())
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn h2_body_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let reasons = Rc::new(RefCell::new(Vec::new()));
+        let server_reasons = reasons.clone();
+        monoio::spawn(async move {
+            let (io, _) = listener.accept().await.unwrap();
+            let mut conn = h2::server::handshake(io).await.unwrap();
+            while let Some(Ok((request, respond))) = conn.accept().await {
+                let reasons = server_reasons.clone();
+                monoio::spawn(async move {
+                    let _respond = respond;
+                    let mut body = request.into_body();
+                    assert_eq!(body.data().await.unwrap().unwrap(), "a");
+                    let err = body.data().await.unwrap().unwrap_err();
+                    reasons.borrow_mut().push(err.reason());
+                });
+            }
+        });
+
+        let io = monoio::net::TcpStream::connect(addr).await.unwrap();
+        let (mut send_request, conn) = h2::client::handshake(io).await.unwrap();
+        monoio::spawn(conn);
+        for data_error in [false, true] {
+            send_request = send_request.ready().await.unwrap();
+            let request = http::Request::post("http://localhost/").body(()).unwrap();
+            let (_resp, send_stream) = send_request.send_request(request, false).unwrap();
+            let body = Failing {
+                sent: false,
+                data_error,
+            };
+            StreamBodyTask::new(send_stream, body).drive().await;
+        }
+        // Wait for the resets to arrive.
+        monoio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*reasons.borrow(), [Some(Reason::INTERNAL_ERROR); 2]);
+    }
+}
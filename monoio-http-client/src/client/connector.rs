@@ -1,31 +1,96 @@
 use std::{
+    collections::HashMap,
     fmt::{Debug, Display},
-    future::Future,
+    future::{poll_fn, Future},
     hash::Hash,
     io,
-    net::ToSocketAddrs,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    os::fd::{AsRawFd, BorrowedFd},
     path::Path,
+    pin::Pin,
+    rc::Rc,
+    time::{Duration, Instant},
 };
 
 use http::Version;
 use monoio::{
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
     io::{AsyncReadRent, AsyncWriteRent, Split},
     net::{TcpStream, UnixStream},
+    BufResult,
 };
-use monoio_http::h1::codec::ClientCodec;
+use monoio_http::{h1::codec::ClientCodec, h2::Reason};
+use service_async::ParamRef;
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
 
 use super::{
     connection::HttpConnection,
-    key::HttpVersion,
-    pool::{ConnectionPool, PooledConnection},
+    info::{ConnectionInfo, TlsInfo, TransportInfo},
+    key::{HttpVersion, ServerName},
+    listener::{CloseReason, ConnectionListener},
+    pool::{ConnectionPool, Dialer, Drain, PooledConnection},
     ClientGlobalConfig, ConnectionConfig, Proto,
 };
 
 #[cfg(not(feature = "native-tls"))]
-pub type TlsStream<C> = monoio_rustls::ClientTlsStream<C>;
+type RawTlsStream<C> = monoio_rustls::ClientTlsStream<C>;
 
 #[cfg(feature = "native-tls")]
-pub type TlsStream<C> = monoio_native_tls::TlsStream<C>;
+type RawTlsStream<C> = monoio_native_tls::TlsStream<C>;
+
+/// Tls stream which remembers the info of the connection.
+pub struct TlsStream<C> {
+    stream: RawTlsStream<C>,
+    info: ConnectionInfo,
+}
+
+impl<C> TransportInfo for TlsStream<C> {
+    fn connection_info(&self) -> ConnectionInfo {
+        self.info.clone()
+    }
+}
+
+unsafe impl<C: Split> Split for TlsStream<C> {}
+
+impl<C> AsyncReadRent for TlsStream<C>
+where
+    RawTlsStream<C>: AsyncReadRent,
+{
+    #[inline]
+    async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        self.stream.read(buf).await
+    }
+
+    #[inline]
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        self.stream.readv(buf).await
+    }
+}
+
+impl<C> AsyncWriteRent for TlsStream<C>
+where
+    RawTlsStream<C>: AsyncWriteRent,
+{
+    #[inline]
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        self.stream.write(buf).await
+    }
+
+    #[inline]
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        self.stream.writev(buf_vec).await
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush().await
+    }
+
+    #[inline]
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await
+    }
+}
 
 pub trait Connector<K> {
     type Connection;
@@ -34,8 +99,227 @@ pub trait Connector<K> {
     fn connect(&self, key: K) -> impl Future<Output = Result<Self::Connection, Self::Error>>;
 }
 
+/// Options applied to tcp sockets before they connect.
+///
+/// Options only available on Linux fail the connect with
+/// `io::ErrorKind::Unsupported` on other platforms.
 #[derive(Default, Clone, Debug)]
-pub struct TcpConnector;
+pub struct SocketOptions {
+    local_address: Option<IpAddr>,
+    interface: Option<String>,
+    mark: Option<u32>,
+    keepalive_idle: Option<Duration>,
+    keepalive_interval: Option<Duration>,
+    keepalive_retries: Option<u32>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    tcp_fast_open: bool,
+    user_timeout: Option<Duration>,
+}
+
+impl SocketOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind the local address before connecting, the port is picked by the
+    /// system.
+    pub fn local_address(mut self, addr: IpAddr) -> Self {
+        self.local_address = Some(addr);
+        self
+    }
+
+    /// Bind the socket to the interface (`SO_BINDTODEVICE`), Linux only.
+    pub fn interface(mut self, interface: impl Into<String>) -> Self {
+        self.interface = Some(interface.into());
+        self
+    }
+
+    /// Set `SO_MARK` for policy routing, Linux only.
+    pub fn mark(mut self, mark: u32) -> Self {
+        self.mark = Some(mark);
+        self
+    }
+
+    /// Enable tcp keepalive, probes are sent after the connection is idle
+    /// for the duration.
+    pub fn keepalive_idle(mut self, idle: Duration) -> Self {
+        self.keepalive_idle = Some(idle);
+        self
+    }
+
+    /// Interval between keepalive probes, enables tcp keepalive.
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Number of unacknowledged keepalive probes before the connection is
+    /// dropped, enables tcp keepalive.
+    pub fn keepalive_retries(mut self, retries: u32) -> Self {
+        self.keepalive_retries = Some(retries);
+        self
+    }
+
+    /// Set `SO_RCVBUF`.
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Set `SO_SNDBUF`.
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Use TCP Fast Open (`TCP_FASTOPEN_CONNECT`), Linux only.
+    pub fn tcp_fast_open(mut self, enable: bool) -> Self {
+        self.tcp_fast_open = enable;
+        self
+    }
+
+    /// Set `TCP_USER_TIMEOUT`, Linux only.
+    pub fn user_timeout(mut self, timeout: Duration) -> Self {
+        self.user_timeout = Some(timeout);
+        self
+    }
+
+    fn apply(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(addr) = self.local_address {
+            socket.bind(&SocketAddr::new(addr, 0).into())?;
+        }
+        if let Some(interface) = &self.interface {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            socket.bind_device(Some(interface.as_bytes()))?;
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            return Err(unsupported("SO_BINDTODEVICE", interface));
+        }
+        if let Some(mark) = self.mark {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            socket.set_mark(mark)?;
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            return Err(unsupported("SO_MARK", mark));
+        }
+        if self.keepalive_idle.is_some()
+            || self.keepalive_interval.is_some()
+            || self.keepalive_retries.is_some()
+        {
+            let mut keepalive = TcpKeepalive::new();
+            if let Some(idle) = self.keepalive_idle {
+                keepalive = keepalive.with_time(idle);
+            }
+            if let Some(interval) = self.keepalive_interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+            if let Some(retries) = self.keepalive_retries {
+                keepalive = keepalive.with_retries(retries);
+            }
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if self.tcp_fast_open {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            {
+                let enable: libc::c_int = 1;
+                // SAFETY: the fd is valid and the option value is a c_int.
+                let ret = unsafe {
+                    libc::setsockopt(
+                        socket.as_raw_fd(),
+                        libc::IPPROTO_TCP,
+                        libc::TCP_FASTOPEN_CONNECT,
+                        &enable as *const _ as *const libc::c_void,
+                        std::mem::size_of_val(&enable) as libc::socklen_t,
+                    )
+                };
+                if ret != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            return Err(unsupported("TCP_FASTOPEN_CONNECT", true));
+        }
+        if let Some(timeout) = self.user_timeout {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            socket.set_tcp_user_timeout(Some(timeout))?;
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            return Err(unsupported("TCP_USER_TIMEOUT", timeout));
+        }
+        Ok(())
+    }
+
+    /// Like `TcpStream::connect`, the addresses are tried in turn and the
+    /// last error is returned. Addresses of another family than the local
+    /// address are skipped.
+    async fn connect_any(&self, addrs: impl Iterator<Item = SocketAddr>) -> io::Result<TcpStream> {
+        let mut last_err = None;
+        for addr in addrs {
+            if matches!(self.local_address, Some(local) if local.is_ipv4() != addr.is_ipv4()) {
+                continue;
+            }
+            match self.connect(addr).await {
+                Ok(io) => return Ok(io),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no address of the family of the local address",
+            )
+        }))
+    }
+
+    async fn connect(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        self.apply(&socket)?;
+        socket.set_nonblocking(true)?;
+        match socket.connect(&addr.into()) {
+            Ok(()) => {}
+            Err(e) if e.raw_os_error() == Some(libc::EINPROGRESS) => {}
+            Err(e) => return Err(e),
+        }
+        // sockets of the uring driver are blocking
+        if !monoio::utils::is_legacy() {
+            socket.set_nonblocking(false)?;
+        }
+        let stream = TcpStream::from_std(socket.into())?;
+        stream.writable(true).await?;
+        // SAFETY: the fd is owned by the stream which outlives the borrow.
+        let fd = unsafe { BorrowedFd::borrow_raw(stream.as_raw_fd()) };
+        if let Some(e) = SockRef::from(&fd).take_error()? {
+            return Err(e);
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn unsupported(option: &str, value: impl Debug) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{option}={value:?} is not supported on this platform"),
+    )
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct TcpConnector {
+    options: Option<SocketOptions>,
+}
+
+impl TcpConnector {
+    /// Apply the socket options to every connection.
+    pub fn new(options: SocketOptions) -> Self {
+        Self {
+            options: Some(options),
+        }
+    }
+}
 
 impl<T> Connector<T> for TcpConnector
 where
@@ -45,11 +329,13 @@ where
     type Error = io::Error;
 
     async fn connect(&self, key: T) -> Result<Self::Connection, Self::Error> {
-        TcpStream::connect(key).await.map(|io| {
-            // we will ignore the set nodelay error
-            let _ = io.set_nodelay(true);
-            io
-        })
+        let io = match &self.options {
+            None => TcpStream::connect(key).await?,
+            Some(options) => options.connect_any(key.to_socket_addrs()?).await?,
+        };
+        // we will ignore the set nodelay error
+        let _ = io.set_nodelay(true);
+        Ok(io)
     }
 }
 
@@ -72,7 +358,7 @@ where
 pub struct TlsConnector<C> {
     inner_connector: C,
     #[cfg(not(feature = "native-tls"))]
-    tls_connector: monoio_rustls::TlsConnector,
+    tls_config: std::sync::Arc<rustls::ClientConfig>,
     #[cfg(feature = "native-tls")]
     tls_connector: monoio_native_tls::TlsConnector,
 }
@@ -83,6 +369,26 @@ impl<C: Debug> std::fmt::Debug for TlsConnector<C> {
     }
 }
 
+impl<C: Default> TlsConnector<C> {
+    /// Establish tls over connections made by the inner connector.
+    pub fn with_inner_connector(inner_connector: C) -> Self {
+        Self {
+            inner_connector,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "native-tls")))]
+impl<C: Default> TlsConnector<C> {
+    pub(crate) fn with_tls_config(tls_config: std::sync::Arc<rustls::ClientConfig>) -> Self {
+        Self {
+            inner_connector: Default::default(),
+            tls_config,
+        }
+    }
+}
+
 impl<C: Default> Default for TlsConnector<C> {
     #[cfg(not(feature = "native-tls"))]
     fn default() -> Self {
@@ -102,7 +408,7 @@ impl<C: Default> Default for TlsConnector<C> {
 
         Self {
             inner_connector: Default::default(),
-            tls_connector: cfg.into(),
+            tls_config: std::sync::Arc::new(cfg),
         }
     }
 
@@ -120,7 +426,7 @@ impl<C, T> Connector<T> for TlsConnector<C>
 where
     T: service_async::Param<super::key::ServerName>,
     C: Connector<T, Error = std::io::Error>,
-    C::Connection: AsyncReadRent + AsyncWriteRent,
+    C::Connection: AsyncReadRent + AsyncWriteRent + TransportInfo,
 {
     type Connection = TlsStream<C::Connection>;
     type Error = monoio_rustls::TlsError;
@@ -129,17 +435,71 @@ where
         let server_name = key.param();
 
         let stream = self.inner_connector.connect(key).await?;
-        let tls_stream = self.tls_connector.connect(server_name, stream).await?;
-        Ok(tls_stream)
+        let mut info = stream.connection_info();
+        let start = Instant::now();
+        let (stream, tls) = rustls_handshake(self.tls_config.clone(), server_name, stream).await?;
+        info.set_tls_handshake(start.elapsed());
+        Ok(TlsStream {
+            stream,
+            info: info.with_tls(tls),
+        })
     }
 }
 
+/// Drive the handshake here rather than in monoio-rustls, so the session can
+/// be inspected before it is moved into the stream.
+#[cfg(not(feature = "native-tls"))]
+async fn rustls_handshake<IO: AsyncReadRent + AsyncWriteRent>(
+    config: std::sync::Arc<rustls::ClientConfig>,
+    server_name: rustls::ServerName,
+    mut io: IO,
+) -> Result<(RawTlsStream<IO>, TlsInfo), monoio_rustls::TlsError> {
+    use monoio::io::AsyncWriteRentExt;
+
+    const READ_BUF_SIZE: usize = 16 * 1024;
+
+    let mut session = rustls::ClientConnection::new(config, server_name)?;
+    let mut buf = Vec::with_capacity(READ_BUF_SIZE);
+    loop {
+        while session.wants_write() {
+            let mut out = Vec::new();
+            session.write_tls(&mut out)?;
+            io.write_all(out).await.0?;
+        }
+        if !session.is_handshaking() {
+            break;
+        }
+
+        buf.clear();
+        let (result, read) = io.read(buf).await;
+        buf = read;
+        if result? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "tls handshake eof").into());
+        }
+        // Records following the handshake are kept by the session.
+        let mut records = &buf[..];
+        while !records.is_empty() {
+            session.read_tls(&mut records)?;
+            if let Err(e) = session.process_new_packets() {
+                // Send the alert before failing.
+                let mut out = Vec::new();
+                if session.write_tls(&mut out).is_ok() {
+                    let _ = io.write_all(out).await;
+                }
+                return Err(e.into());
+            }
+        }
+    }
+    let tls = TlsInfo::from_rustls(&session);
+    Ok((RawTlsStream::new(io, session), tls))
+}
+
 #[cfg(feature = "native-tls")]
 impl<C, T> Connector<T> for TlsConnector<C>
 where
     T: service_async::Param<super::key::ServerName>,
     C: Connector<T, Error = std::io::Error>,
-    C::Connection: AsyncReadRent + AsyncWriteRent,
+    C::Connection: AsyncReadRent + AsyncWriteRent + TransportInfo,
 {
     type Connection = TlsStream<C::Connection>;
     type Error = monoio_native_tls::TlsError;
@@ -148,7 +508,14 @@ where
         let server_name = key.param();
 
         let stream = self.inner_connector.connect(key).await?;
-        self.tls_connector.connect(&server_name.0, stream).await
+        let mut info = stream.connection_info();
+        let start = Instant::now();
+        let stream = self.tls_connector.connect(&server_name.0, stream).await?;
+        info.set_tls_handshake(start.elapsed());
+        Ok(TlsStream {
+            stream,
+            info: info.with_tls(TlsInfo::default()),
+        })
     }
 }
 
@@ -162,7 +529,15 @@ impl HttpConnector {
         Self { conn_config }
     }
 
-    pub async fn connect<IO>(&self, io: IO, version: Version) -> crate::Result<HttpConnection<IO>>
+    /// Connect and report events of the http2 driver to the listener. The
+    /// http2 connection is shut down gracefully when the drain is signaled.
+    pub(crate) async fn connect<IO, K: 'static>(
+        &self,
+        io: IO,
+        version: Version,
+        listener: Option<(K, Rc<dyn ConnectionListener<K>>)>,
+        mut drain: Option<Drain>,
+    ) -> crate::Result<HttpConnection<IO>>
     where
         IO: AsyncReadRent + AsyncWriteRent + Split + Unpin + 'static,
     {
@@ -177,12 +552,34 @@ impl HttpConnector {
         };
 
         match proto {
-            Version::HTTP_11 => Ok(HttpConnection::H1(ClientCodec::new(io))),
+            Version::HTTP_11 => Ok(HttpConnection::H1(ClientCodec::with_config(
+                io,
+                self.conn_config.h1_decoder,
+                None,
+            ))),
             Version::HTTP_2 => {
-                let (send_request, h2_conn) = self.conn_config.h2_builder.handshake(io).await?;
+                let (send_request, mut h2_conn) = self.conn_config.h2_builder.handshake(io).await?;
                 monoio::spawn(async move {
-                    if let Err(e) = h2_conn.await {
-                        println!("H2 CONN ERR={:?}", e);
+                    let result = poll_fn(|cx| {
+                        if drain.as_mut().is_some_and(|d| d.poll_shutdown(cx)) {
+                            h2_conn.graceful_shutdown();
+                        }
+                        Pin::new(&mut h2_conn).poll(cx)
+                    })
+                    .await;
+                    #[cfg(feature = "logging")]
+                    if let Err(e) = &result {
+                        tracing::debug!("h2 connection error: {e:?}");
+                    }
+                    if let Some((key, listener)) = listener {
+                        match &result {
+                            Err(e) if e.is_go_away() && e.is_remote() => {
+                                listener.on_goaway(&key, e.reason().unwrap_or(Reason::NO_ERROR))
+                            }
+                            Err(e) => listener.on_h2_conn_error(&key, e),
+                            Ok(_) => {}
+                        }
+                        listener.on_connection_closed(&key, CloseReason::Http2Closed);
                     }
                 });
                 Ok(HttpConnection::H2(send_request))
@@ -202,6 +599,7 @@ pub struct PooledConnector<TC, K, IO: AsyncWriteRent> {
     transport_connector: TC,
     http_connector: HttpConnector,
     pool: ConnectionPool<K, IO>,
+    listener: Option<Rc<dyn ConnectionListener<K>>>,
 }
 
 impl<TC: Clone, K, IO: AsyncWriteRent> Clone for PooledConnector<TC, K, IO> {
@@ -211,6 +609,7 @@ impl<TC: Clone, K, IO: AsyncWriteRent> Clone for PooledConnector<TC, K, IO> {
             transport_connector: self.transport_connector.clone(),
             http_connector: self.http_connector.clone(),
             pool: self.pool.clone(),
+            listener: self.listener.clone(),
         }
     }
 }
@@ -231,6 +630,7 @@ where
             transport_connector: Default::default(),
             http_connector: HttpConnector::new(c_config),
             pool: ConnectionPool::default(),
+            listener: None,
         }
     }
 }
@@ -246,15 +646,172 @@ impl<TC, K: 'static, IO: AsyncWriteRent + 'static> PooledConnector<TC, K, IO> {
             transport_connector: connector,
             http_connector: HttpConnector::new(c_config),
             pool: ConnectionPool::default(),
+            listener: None,
+        }
+    }
+}
+
+impl<TC, K, IO: AsyncWriteRent> PooledConnector<TC, K, IO> {
+    /// The connector of raw transport, connections made by it bypass the pool.
+    #[inline]
+    pub fn transport_connector(&self) -> &TC {
+        &self.transport_connector
+    }
+
+    /// Set the listener of connection lifecycle events.
+    pub fn set_listener(&mut self, listener: Rc<dyn ConnectionListener<K>>) {
+        self.pool.set_listener(listener.clone());
+        self.listener = Some(listener);
+    }
+
+    /// Shut down the pool, see `ConnectionPool::shutdown`.
+    pub async fn shutdown(&self) {
+        self.pool.shutdown().await
+    }
+}
+
+impl<TC, K, IO> PooledConnector<TC, K, IO>
+where
+    K: Hash + Eq + ToOwned<Owned = K> + 'static,
+    IO: AsyncWriteRent + 'static,
+{
+    /// See `ConnectionPool::set_dialer`.
+    pub fn set_dialer(&self, dialer: Dialer<K, IO>, min_idle: HashMap<K, usize>) {
+        self.pool.set_dialer(dialer, min_idle);
+    }
+
+    /// See `ConnectionPool::preconnect`.
+    pub async fn preconnect(&self, key: K, n: usize) -> crate::Result<()> {
+        self.pool.preconnect(key, n).await
+    }
+}
+
+impl<TC, K, IO> PooledConnector<TC, K, IO>
+where
+    K: ToSocketAddrs
+        + Hash
+        + Eq
+        + ToOwned<Owned = K>
+        + Display
+        + HttpVersion
+        + ParamRef<Option<ServerName>>
+        + 'static,
+    TC: Connector<K, Connection = IO>,
+    IO: AsyncReadRent + AsyncWriteRent + Split + Unpin + TransportInfo + 'static,
+    crate::Error: From<<TC as Connector<K>>::Error>,
+{
+    /// Establish a new connection, bypassing the idle ones in the pool.
+    pub(crate) async fn dial(&self, key: K) -> crate::Result<(HttpConnection<IO>, ConnectionInfo)> {
+        if self.pool.is_closed() {
+            return Err(crate::ErrorKind::Shutdown.into());
         }
+        let key_owned = key.to_owned();
+        let listener = match self.listener.as_ref() {
+            Some(listener) => listener,
+            None => {
+                let (io, dns, total) = timed(self.transport_connector.connect(key)).await;
+                let io = io?;
+                let mut info = io.connection_info();
+                info.set_connect_timings(dns, total);
+                let conn = self
+                    .http_connector
+                    .connect::<IO, K>(io, key_owned.get_version(), None, self.pool.drain())
+                    .await?;
+                return Ok(with_version(conn, info));
+            }
+        };
+
+        listener.on_connect_start(&key_owned);
+        let tls = key_owned.param_ref().is_some();
+        let result = match timed(self.transport_connector.connect(key)).await {
+            (Ok(io), dns, total) => {
+                if tls {
+                    listener.on_tls_handshake(&key_owned);
+                }
+                let mut info = io.connection_info();
+                info.set_connect_timings(dns, total);
+                let driver = (key_owned.to_owned(), listener.clone());
+                self.http_connector
+                    .connect(io, key_owned.get_version(), Some(driver), self.pool.drain())
+                    .await
+                    .map(|conn| with_version(conn, info))
+            }
+            (Err(e), ..) => Err(e.into()),
+        };
+        listener.on_connect_end(&key_owned, result.as_ref().map(|_| ()));
+        result
+    }
+}
+
+impl<TC, K, IO> PooledConnector<TC, K, IO>
+where
+    K: Hash + Eq + ToOwned<Owned = K> + Display + 'static,
+    IO: AsyncReadRent + AsyncWriteRent + Split + 'static,
+{
+    /// See `ConnectionPool::get_excluding`.
+    pub(crate) async fn connect_excluding(
+        &self,
+        key: K,
+        excluded: &[u64],
+    ) -> Option<crate::Result<PooledConnection<K, IO>>> {
+        self.pool.get_excluding(key, excluded).await
+    }
+
+    /// See `ConnectionPool::coalesce`.
+    pub(crate) fn coalesce(
+        &self,
+        key: &K,
+        covers: impl FnMut(&K, &ConnectionInfo) -> bool,
+    ) -> Option<PooledConnection<K, IO>> {
+        self.pool.coalesce(key, covers)
     }
 }
 
+/// Returns the output with the time spent in the first poll and the total
+/// time. Address resolution is blocking in monoio, the first poll of a
+/// transport connector is dominated by it.
+async fn timed<F: Future>(fut: F) -> (F::Output, Duration, Duration) {
+    let start = Instant::now();
+    let mut first_poll = None;
+    let mut fut = std::pin::pin!(fut);
+    let output = poll_fn(|cx| {
+        let poll = fut.as_mut().poll(cx);
+        if first_poll.is_none() {
+            // Nothing is resolved if the connector finishes at once.
+            first_poll = Some(match poll {
+                std::task::Poll::Ready(_) => Duration::ZERO,
+                std::task::Poll::Pending => start.elapsed(),
+            });
+        }
+        poll
+    })
+    .await;
+    (output, first_poll.unwrap_or_default(), start.elapsed())
+}
+
+fn with_version<IO: AsyncWriteRent>(
+    conn: HttpConnection<IO>,
+    mut info: ConnectionInfo,
+) -> (HttpConnection<IO>, ConnectionInfo) {
+    info.set_version(match conn {
+        HttpConnection::H1(_) => Version::HTTP_11,
+        HttpConnection::H2(_) => Version::HTTP_2,
+    });
+    (conn, info)
+}
+
 impl<TC, K, IO> Connector<K> for PooledConnector<TC, K, IO>
 where
-    K: ToSocketAddrs + Hash + Eq + ToOwned<Owned = K> + Display + HttpVersion + 'static,
+    K: ToSocketAddrs
+        + Hash
+        + Eq
+        + ToOwned<Owned = K>
+        + Display
+        + HttpVersion
+        + ParamRef<Option<ServerName>>
+        + 'static,
     TC: Connector<K, Connection = IO>,
-    IO: AsyncReadRent + AsyncWriteRent + Split + Unpin + 'static,
+    IO: AsyncReadRent + AsyncWriteRent + Split + Unpin + TransportInfo + 'static,
     crate::Error: From<<TC as Connector<K>>::Error>,
 {
     type Connection = PooledConnection<K, IO>;
@@ -265,12 +822,135 @@ where
             return Ok(conn);
         }
         let key_owned = key.to_owned();
-        let io = self.transport_connector.connect(key).await?;
+        let (conn, info) = self.dial(key).await?;
+        Ok(self.pool.link(key_owned, conn, info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[cfg(not(feature = "native-tls"))]
+    use monoio::io::{AsyncReadRentExt, AsyncWriteRentExt};
+    use monoio::net::TcpListener;
+
+    use super::*;
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = SocketOptions::new()
+            .local_address(IpAddr::V4(Ipv4Addr::LOCALHOST))
+            .keepalive_idle(Duration::from_secs(30))
+            .keepalive_retries(3)
+            .recv_buffer_size(64 * 1024)
+            .user_timeout(Duration::from_secs(5));
+        let connector = TcpConnector::new(options);
+
+        let (client, server) = monoio::join!(connector.connect(addr), listener.accept());
+        let client = client.unwrap();
+        let (_server, peer) = server.unwrap();
+        assert_eq!(client.local_addr().unwrap(), peer);
+        assert!(client.nodelay().unwrap());
+
+        let fd = unsafe { BorrowedFd::borrow_raw(client.as_raw_fd()) };
+        let sock = SockRef::from(&fd);
+        assert!(sock.keepalive().unwrap());
+        assert_eq!(sock.keepalive_time().unwrap(), Duration::from_secs(30));
+        assert_eq!(sock.keepalive_retries().unwrap(), 3);
+        assert!(sock.recv_buffer_size().unwrap() >= 64 * 1024);
+        assert_eq!(
+            sock.tcp_user_timeout().unwrap(),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn socket_options_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let v6: SocketAddr = (Ipv6Addr::LOCALHOST, addr.port()).into();
+
+        // The next address is tried after a failure.
+        let connector = TcpConnector::new(SocketOptions::new().recv_buffer_size(64 * 1024));
+        let addrs = [closed, addr];
+        let (client, server) = monoio::join!(connector.connect(&addrs[..]), listener.accept());
+        assert_eq!(client.unwrap().local_addr().unwrap(), server.unwrap().1);
+        let err = connector.connect(closed).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+        // Addresses of another family than the local address are skipped.
+        let connector =
+            TcpConnector::new(SocketOptions::new().local_address(Ipv4Addr::LOCALHOST.into()));
+        let addrs = [v6, addr];
+        let (client, server) = monoio::join!(connector.connect(&addrs[..]), listener.accept());
+        assert_eq!(client.unwrap().local_addr().unwrap(), server.unwrap().1);
+        let err = connector.connect(v6).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(not(feature = "native-tls"))]
+    #[monoio::test_all(enable_timer = true)]
+    async fn rustls_tls_info() {
+        const CERT: &[u8] = include_bytes!("../../tests/certs/localhost.crt.der");
+        const KEY: &[u8] = include_bytes!("../../tests/certs/localhost.key.der");
+
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(CERT.to_vec())],
+                rustls::PrivateKey(KEY.to_vec()),
+            )
+            .unwrap();
+        let acceptor = monoio_rustls::TlsAcceptor::from(server_config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (io, _) = listener.accept().await.unwrap();
+            let mut io = acceptor.accept(io).await.unwrap();
+            // Written right after the handshake, it may arrive along with
+            // the last handshake records.
+            io.write_all("hello").await.0.unwrap();
+            io.flush().await.unwrap();
+            let _ = io.read(Vec::with_capacity(1)).await;
+        });
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&rustls::Certificate(CERT.to_vec())).unwrap();
+        let client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let io = TcpStream::connect(addr).await.unwrap();
+        let server_name = "localhost".try_into().unwrap();
+        let (mut stream, tls) =
+            rustls_handshake(std::sync::Arc::new(client_config), server_name, io)
+                .await
+                .unwrap();
+        assert_eq!(tls.version(), Some("TLSv1_3"));
+        assert!(tls.cipher_suite().unwrap().starts_with("TLS13_"));
+        assert_eq!(tls.peer_certificates(), [CERT]);
+
+        let (r, buf) = stream.read_exact(vec![0; 5]).await;
+        r.unwrap();
+        assert_eq!(buf, b"hello");
+    }
 
-        let pipe = self
-            .http_connector
-            .connect(io, key_owned.get_version())
-            .await?;
-        Ok(self.pool.link(key_owned, pipe))
+    #[monoio::test_all(enable_timer = true)]
+    async fn socket_options_refused() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let connector = TcpConnector::new(SocketOptions::new().send_buffer_size(64 * 1024));
+        let err = connector.connect(addr).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
     }
 }
@@ -0,0 +1,250 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::{poll_fn, Future},
+    task::Poll,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use monoio_http::common::{
+    body::{Body, HttpBody},
+    error::HttpError,
+    request::Request,
+    response::Response,
+};
+
+use super::{
+    abort::AbortHandle, connector::PooledConnector, key::Key, pool::PooledConnection,
+    unified::UnifiedTransportConnection, Client, Connector,
+};
+
+const DEFAULT_RATIO: f64 = 0.1;
+const BURST: f64 = 10.0;
+
+/// Tokens for hedged attempts, each hedged request deposits `ratio` of a
+/// token and each extra attempt takes a whole one.
+#[derive(Debug)]
+pub(crate) struct HedgeBudget {
+    ratio: f64,
+    tokens: Cell<f64>,
+}
+
+impl HedgeBudget {
+    pub(crate) fn new(ratio: Option<f64>) -> Self {
+        Self {
+            ratio: ratio.unwrap_or(DEFAULT_RATIO).max(0.0),
+            tokens: Cell::new(BURST),
+        }
+    }
+
+    fn deposit(&self) {
+        self.tokens.set((self.tokens.get() + self.ratio).min(BURST));
+    }
+
+    fn withdraw(&self) -> bool {
+        let tokens = self.tokens.get();
+        if tokens < 1.0 {
+            return false;
+        }
+        self.tokens.set(tokens - 1.0);
+        true
+    }
+}
+
+impl<C> Client<C>
+where
+    PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+        Key,
+        Connection = PooledConnection<Key, UnifiedTransportConnection>,
+        Error = crate::Error,
+    >,
+{
+    /// Send the request made by `make`, and once more on another connection
+    /// each time no response head arrives within `after`, up to `max_extra`
+    /// times as the budget allows. The first response head wins and the
+    /// other attempts are aborted.
+    pub(crate) async fn execute_hedged<B, F>(
+        &self,
+        make: F,
+        abort: Option<AbortHandle>,
+        body_limit: Option<usize>,
+        after: Duration,
+        max_extra: usize,
+    ) -> crate::Result<Response<HttpBody>>
+    where
+        B: Body<Data = Bytes, Error = HttpError> + 'static,
+        F: Fn() -> Request<B>,
+    {
+        let budget = &self.shared.hedge_budget;
+        budget.deposit();
+        let abort = abort.unwrap_or_default();
+        let used = RefCell::new(Vec::new());
+        let attempt =
+            |abort| Box::pin(self.execute_excluding(make(), Some(abort), body_limit, Some(&used)));
+
+        let child = abort.child();
+        let mut attempts = vec![(child.clone(), attempt(child))];
+        let mut timer = std::pin::pin!(monoio::time::sleep(after));
+        let mut extra = 0;
+        loop {
+            // Some((index, result)) once an attempt finishes, None if it is
+            // time to hedge.
+            let event = poll_fn(|cx| {
+                for (i, (_, fut)) in attempts.iter_mut().enumerate() {
+                    if let Poll::Ready(result) = fut.as_mut().poll(cx) {
+                        return Poll::Ready(Some((i, result)));
+                    }
+                }
+                if extra < max_extra && timer.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+                Poll::Pending
+            })
+            .await;
+
+            match event {
+                Some((i, Ok(resp))) => {
+                    drop(attempts.swap_remove(i));
+                    for (abort, _) in attempts.iter() {
+                        abort.abort();
+                    }
+                    return Ok(resp);
+                }
+                Some((i, Err(e))) => {
+                    drop(attempts.swap_remove(i));
+                    if attempts.is_empty() {
+                        return Err(e);
+                    }
+                }
+                None if !abort.is_aborted() && budget.withdraw() => {
+                    extra += 1;
+                    let child = abort.child();
+                    attempts.push((child.clone(), attempt(child)));
+                    timer.as_mut().reset(monoio::time::Instant::now() + after);
+                }
+                None => extra = max_extra,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Instant};
+
+    use monoio::{
+        io::{sink::SinkExt, stream::Stream},
+        net::TcpListener,
+    };
+    use monoio_http::{
+        common::body::{FixedBody, HttpBody},
+        h1::codec::ServerCodec,
+        h2::{self, Reason},
+    };
+
+    use super::*;
+    use crate::Builder;
+
+    #[test]
+    fn budget() {
+        let budget = HedgeBudget::new(Some(0.5));
+        for _ in 0..10 {
+            assert!(budget.withdraw());
+        }
+        assert!(!budget.withdraw());
+        budget.deposit();
+        assert!(!budget.withdraw());
+        budget.deposit();
+        assert!(budget.withdraw());
+    }
+
+    // The first connection answers late, the others at once with their
+    // index.
+    async fn serve_h1(listener: TcpListener) {
+        let mut n = 0;
+        loop {
+            let (io, _) = listener.accept().await.unwrap();
+            monoio::spawn(async move {
+                let mut codec = ServerCodec::new(io);
+                while let Some(Ok(_)) = codec.next().await {
+                    if n == 0 {
+                        monoio::time::sleep(Duration::from_millis(300)).await;
+                    }
+                    let body = HttpBody::fixed_body(Some(n.to_string().into()));
+                    if codec
+                        .send_and_flush(http::Response::new(body))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            });
+            n += 1;
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn hedge_h1() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap());
+        monoio::spawn(serve_h1(listener));
+        let client = Builder::new().build();
+
+        let start = Instant::now();
+        let resp = client
+            .get(&uri)
+            .hedge(Duration::from_millis(20), 1)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "1");
+        assert!(start.elapsed() < Duration::from_millis(300));
+
+        // The slow connection is discarded, the fast one is reused.
+        let resp = client.get(&uri).send().await.unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "1");
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn hedge_h2() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap());
+        let reset = Rc::new(Cell::new(None));
+        let reset_ = reset.clone();
+        monoio::spawn(async move {
+            for n in 0.. {
+                let (io, _) = listener.accept().await.unwrap();
+                let reset = reset_.clone();
+                monoio::spawn(async move {
+                    let mut conn = h2::server::handshake(io).await.unwrap();
+                    while let Some(Ok((_, mut respond))) = conn.accept().await {
+                        let reset = reset.clone();
+                        monoio::spawn(async move {
+                            if n == 0 {
+                                let reason = poll_fn(|cx| respond.poll_reset(cx)).await;
+                                reset.set(reason.ok());
+                                return;
+                            }
+                            let mut send = respond
+                                .send_response(http::Response::new(()), false)
+                                .unwrap();
+                            send.send_data(n.to_string().into(), true).unwrap();
+                        });
+                    }
+                });
+            }
+        });
+        let client = Builder::new().http2_client().build();
+
+        let resp = client
+            .get(&uri)
+            .hedge(Duration::from_millis(20), 2)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "1");
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(reset.get(), Some(Reason::CANCEL));
+    }
+}
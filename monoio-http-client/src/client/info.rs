@@ -0,0 +1,274 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use http::Version;
+use monoio::net::{TcpStream, UnixStream};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Details of the connection which served a response, it can be found in
+/// `ClientResponse::extensions()`.
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    id: u64,
+    local_addr: Option<SocketAddr>,
+    peer_addr: Option<SocketAddr>,
+    reused: bool,
+    version: Version,
+    tls: Option<Arc<TlsInfo>>,
+    timings: ConnectTimings,
+}
+
+impl ConnectionInfo {
+    /// Addresses are None for unix domain sockets.
+    pub fn new(local_addr: Option<SocketAddr>, peer_addr: Option<SocketAddr>) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            local_addr,
+            peer_addr,
+            reused: false,
+            version: Version::HTTP_11,
+            tls: None,
+            timings: ConnectTimings::default(),
+        }
+    }
+
+    pub fn with_tls(mut self, tls: TlsInfo) -> Self {
+        self.tls = Some(Arc::new(tls));
+        self
+    }
+
+    /// Identifies the connection, clones of the info share it.
+    #[inline]
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    pub(crate) fn set_reused(&mut self, reused: bool) {
+        self.reused = reused;
+    }
+
+    pub(crate) fn set_tls_handshake(&mut self, elapsed: Duration) {
+        self.timings.tls_handshake = elapsed;
+    }
+
+    /// The tls handshake is part of the total, it is subtracted from the
+    /// connect phase.
+    pub(crate) fn set_connect_timings(&mut self, dns: Duration, total: Duration) {
+        self.timings.dns = dns;
+        self.timings.connect = total
+            .saturating_sub(dns)
+            .saturating_sub(self.timings.tls_handshake);
+    }
+
+    pub(crate) fn connect_timings(&self) -> ConnectTimings {
+        self.timings
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    #[inline]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Returns true if the connection is got from the pool rather than
+    /// newly established.
+    #[inline]
+    pub fn is_reused(&self) -> bool {
+        self.reused
+    }
+
+    /// The negotiated http version of the connection.
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns None if the connection is not encrypted.
+    #[inline]
+    pub fn tls(&self) -> Option<&TlsInfo> {
+        self.tls.as_deref()
+    }
+}
+
+/// Details of the tls session.
+///
+/// With `native-tls` only the presence of tls is known, the details are
+/// left empty.
+#[derive(Clone, Debug, Default)]
+pub struct TlsInfo {
+    version: Option<&'static str>,
+    cipher_suite: Option<&'static str>,
+    alpn_protocol: Option<Vec<u8>>,
+    peer_certificates: Vec<Bytes>,
+}
+
+impl TlsInfo {
+    #[cfg(not(feature = "native-tls"))]
+    pub(crate) fn from_rustls(conn: &rustls::ClientConnection) -> Self {
+        Self {
+            version: conn.protocol_version().and_then(|v| v.as_str()),
+            cipher_suite: conn
+                .negotiated_cipher_suite()
+                .and_then(|s| s.suite().as_str()),
+            alpn_protocol: conn.alpn_protocol().map(<[u8]>::to_vec),
+            peer_certificates: conn
+                .peer_certificates()
+                .unwrap_or_default()
+                .iter()
+                .map(|cert| Bytes::copy_from_slice(&cert.0))
+                .collect(),
+        }
+    }
+
+    /// The protocol version, e.g. `TLSv1_3`.
+    #[inline]
+    pub fn version(&self) -> Option<&'static str> {
+        self.version
+    }
+
+    /// The negotiated cipher suite, e.g. `TLS13_AES_128_GCM_SHA256`.
+    #[inline]
+    pub fn cipher_suite(&self) -> Option<&'static str> {
+        self.cipher_suite
+    }
+
+    #[inline]
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
+
+    /// DER encoded certificate chain of the server, the end entity comes
+    /// first.
+    #[inline]
+    pub fn peer_certificates(&self) -> &[Bytes] {
+        &self.peer_certificates
+    }
+
+    /// Returns true if the end entity certificate is valid for the name, a
+    /// dns name or an ip address. The chain is not verified again.
+    pub(crate) fn covers(&self, name: &str) -> bool {
+        let cert = match self.peer_certificates.first() {
+            Some(cert) => webpki::EndEntityCert::try_from(cert.as_ref()),
+            None => return false,
+        };
+        match (cert, webpki::SubjectNameRef::try_from_ascii_str(name)) {
+            (Ok(cert), Ok(name)) => cert.verify_is_valid_for_subject_name(name).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Transports which can describe the connection they carry.
+pub trait TransportInfo {
+    fn connection_info(&self) -> ConnectionInfo;
+}
+
+impl TransportInfo for TcpStream {
+    fn connection_info(&self) -> ConnectionInfo {
+        ConnectionInfo::new(self.local_addr().ok(), self.peer_addr().ok())
+    }
+}
+
+impl TransportInfo for UnixStream {
+    fn connection_info(&self) -> ConnectionInfo {
+        ConnectionInfo::new(None, None)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ConnectTimings {
+    dns: Duration,
+    connect: Duration,
+    tls_handshake: Duration,
+}
+
+/// Durations of the phases of a request, it can be found in
+/// `ClientResponse::extensions()`. The phases of establishing the connection
+/// are zero if the connection is reused.
+#[derive(Clone, Debug)]
+pub struct Timings {
+    connect: ConnectTimings,
+    send: Duration,
+    first_byte: Duration,
+    head_at: Instant,
+    // Set by the task reading the body. Extensions only take `Send + Sync`
+    // values, so it is not an `Rc<Cell<_>>`.
+    body: Arc<OnceLock<Duration>>,
+}
+
+impl Timings {
+    /// Created once the response head is received.
+    pub(crate) fn new(send: Duration, first_byte: Duration) -> Self {
+        Self {
+            connect: ConnectTimings::default(),
+            send,
+            first_byte,
+            head_at: Instant::now(),
+            body: Default::default(),
+        }
+    }
+
+    pub(crate) fn set_connect(&mut self, connect: ConnectTimings) {
+        self.connect = connect;
+    }
+
+    /// Record the completion of the body, only the first call counts.
+    pub(crate) fn finish_body(&self) {
+        let _ = self.body.set(self.head_at.elapsed());
+    }
+
+    /// Resolving the address. Resolution is blocking in monoio, it is
+    /// measured as the time the transport connector takes before it first
+    /// waits.
+    #[inline]
+    pub fn dns(&self) -> Duration {
+        self.connect.dns
+    }
+
+    /// Establishing the transport connection.
+    #[inline]
+    pub fn connect(&self) -> Duration {
+        self.connect.connect
+    }
+
+    #[inline]
+    pub fn tls_handshake(&self) -> Duration {
+        self.connect.tls_handshake
+    }
+
+    /// Writing the request. Http2 request bodies are streamed concurrently,
+    /// the phase ends at the response head if the body is not finished yet.
+    #[inline]
+    pub fn send(&self) -> Duration {
+        self.send
+    }
+
+    /// Waiting for the response head after the request is written.
+    #[inline]
+    pub fn first_byte(&self) -> Duration {
+        self.first_byte
+    }
+
+    /// Receiving the body after the response head, None until the body is
+    /// fully read.
+    #[inline]
+    pub fn body(&self) -> Option<Duration> {
+        self.body.get().copied()
+    }
+}
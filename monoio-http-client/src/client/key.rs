@@ -136,6 +136,8 @@ impl TryFrom<&Uri> for Key {
         let (tls, default_port) = match uri.scheme() {
             Some(scheme) if scheme == &http::uri::Scheme::HTTP => (false, 80),
             Some(scheme) if scheme == &http::uri::Scheme::HTTPS => (true, 443),
+            Some(scheme) if scheme.as_str() == "ws" => (false, 80),
+            Some(scheme) if scheme.as_str() == "wss" => (true, 443),
             _ => (false, 0),
         };
         let port = uri.port_u16().unwrap_or(default_port);
@@ -235,6 +237,16 @@ mod tests {
         assert_eq!(key.server_name, None);
     }
 
+    #[test]
+    fn key_ws_scheme() {
+        let key: Key = Uri::from_static("ws://1.1.1.1/chat").try_into().unwrap();
+        assert_eq!(key.port, 80);
+        assert_eq!(key.server_name, None);
+        let key: Key = Uri::from_static("wss://bytedance.com").try_into().unwrap();
+        assert_eq!(key.port, 443);
+        assert!(key.server_name.is_some());
+    }
+
     #[test]
     fn key_uri() {
         let uri = Uri::try_from("https://bytedance.com").unwrap();
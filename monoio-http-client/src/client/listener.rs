@@ -0,0 +1,154 @@
+use monoio_http::h2::{self, Reason};
+
+use super::{breaker::CircuitState, key::Key};
+
+/// Why a connection is closed or evicted from the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The http1 connection can not be reused, e.g. `Connection: close` or
+    /// the response body is not fully read.
+    NotReusable,
+    /// The connection is idle longer than the idle timeout.
+    IdleTimeout,
+    /// The pool of the key is full.
+    PoolFull,
+    /// The http2 connection is removed from the pool after an error.
+    Http2Error,
+    /// The driver of the http2 connection is finished.
+    Http2Closed,
+    /// The client is shut down.
+    Shutdown,
+}
+
+/// Callbacks of connection lifecycle events, set with
+/// `Builder::connection_listener`. All methods do nothing by default.
+///
+/// Callbacks are invoked inline, they should be cheap and must not block.
+pub trait ConnectionListener<K = Key> {
+    /// A new connection is being established as there is no idle one.
+    fn on_connect_start(&self, _key: &K) {}
+
+    /// The connection is established, including the http2 handshake.
+    fn on_connect_end(&self, _key: &K, _result: Result<(), &crate::Error>) {}
+
+    /// The transport of a key with a TLS server name is established, which
+    /// includes the TLS handshake.
+    fn on_tls_handshake(&self, _key: &K) {}
+
+    /// An idle connection is got from the pool.
+    fn on_connection_reused(&self, _key: &K) {}
+
+    /// The connection is closed or evicted from the pool.
+    fn on_connection_closed(&self, _key: &K, _reason: CloseReason) {}
+
+    /// The peer closed the http2 connection with an error code in GOAWAY.
+    fn on_goaway(&self, _key: &K, _reason: Reason) {}
+
+    /// The driver of the http2 connection failed.
+    fn on_h2_conn_error(&self, _key: &K, _error: &h2::Error) {}
+
+    /// The circuit of the key changed its state.
+    fn on_circuit_state_change(&self, _key: &K, _state: CircuitState) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use monoio::{
+        io::{AsyncReadRent, AsyncWriteRentExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::Builder;
+
+    #[derive(Clone, Default)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl ConnectionListener for Recorder {
+        fn on_connect_start(&self, _key: &Key) {
+            self.0.borrow_mut().push("start".to_string());
+        }
+
+        fn on_connect_end(&self, _key: &Key, result: Result<(), &crate::Error>) {
+            self.0.borrow_mut().push(format!("end {}", result.is_ok()));
+        }
+
+        fn on_connection_reused(&self, _key: &Key) {
+            self.0.borrow_mut().push("reused".to_string());
+        }
+
+        fn on_connection_closed(&self, _key: &Key, reason: CloseReason) {
+            self.0.borrow_mut().push(format!("closed {reason:?}"));
+        }
+    }
+
+    async fn serve(listener: TcpListener) {
+        loop {
+            let (mut io, _) = listener.accept().await.unwrap();
+            monoio::spawn(async move {
+                let mut buf = Vec::with_capacity(4096);
+                loop {
+                    let (r, b) = io.read(buf).await;
+                    buf = b;
+                    if !matches!(r, Ok(n) if n > 0) {
+                        return;
+                    }
+                    let close = buf.starts_with(b"GET /close");
+                    let response = if close {
+                        "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 2\r\n\r\nok"
+                    } else {
+                        "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok"
+                    };
+                    buf.clear();
+                    let (r, _) = io.write_all(response).await;
+                    r.unwrap();
+                    if close {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn connection_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve(listener));
+
+        let recorder = Recorder::default();
+        let client = Builder::new().connection_listener(recorder.clone()).build();
+        for path in ["keep", "keep", "close"] {
+            let resp = client
+                .get(format!("http://{addr}/{path}"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.bytes().await.unwrap(), "ok");
+            // Wait for the body task to return the connection.
+            monoio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // A port refusing connections.
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        assert!(client.get(format!("http://{addr}/")).send().await.is_err());
+
+        assert_eq!(
+            *recorder.0.borrow(),
+            [
+                "start",
+                "end true",
+                "reused",
+                "reused",
+                "closed NotReusable",
+                "start",
+                "end false"
+            ]
+        );
+    }
+}
@@ -1,32 +1,66 @@
+pub mod abort;
+pub mod balance;
+pub mod breaker;
+pub mod cache;
+mod coalesce;
 pub mod connection;
 pub mod connector;
+mod date;
+mod hedge;
+pub mod info;
 pub mod key;
+pub mod listener;
 pub mod pool;
+pub mod rate_limit;
+pub mod single_flight;
 pub mod unified;
 
-use std::rc::Rc;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use bytes::Bytes;
 use http::HeaderMap;
-use monoio_http::common::{
-    body::{Body, HttpBody},
-    error::HttpError,
-    request::Request,
-    response::Response,
+use monoio_http::{
+    common::{
+        body::{Body, HttpBody, StreamHint},
+        error::HttpError,
+        request::Request,
+        response::Response,
+    },
+    h1::codec::decoder::DecoderConfig,
 };
+use smol_str::SmolStr;
 
 use self::{
-    connector::{Connector, PooledConnector},
+    abort::AbortHandle,
+    balance::EndpointSet,
+    breaker::{CircuitBreaker, CircuitState, Circuits},
+    cache::HttpCache,
+    connection::or_abort,
+    connector::{Connector, PooledConnector, SocketOptions},
+    hedge::HedgeBudget,
     key::Key,
-    pool::PooledConnection,
+    listener::ConnectionListener,
+    pool::{Dialer, PooledConnection},
+    rate_limit::{HostPattern, RateLimit, RateLimits},
+    single_flight::{Flights, SingleFlight},
     unified::{UnifiedTransportConnection, UnifiedTransportConnector},
 };
-use crate::request::ClientRequest;
+#[cfg(feature = "download")]
+use crate::download::Download;
+#[cfg(feature = "grpc")]
+use crate::grpc::Channel;
+#[cfg(feature = "ws")]
+use crate::ws::WebSocketRequest;
+use crate::{error::Stage, request::ClientRequest, sse::EventSource};
 
 #[derive(Debug)]
 pub struct ClientInner<C> {
     cfg: ClientConfig,
     connector: PooledConnector<C, Key, UnifiedTransportConnection>,
+    hedge_budget: HedgeBudget,
+    circuits: Option<Circuits>,
+    rate_limits: Option<RateLimits>,
+    flights: Option<Flights>,
 }
 
 pub struct Client<C = UnifiedTransportConnector> {
@@ -54,6 +88,7 @@ pub enum Proto {
 #[derive(Default, Clone)]
 pub struct ConnectionConfig {
     pub proto: Proto,
+    h1_decoder: DecoderConfig,
     h2_builder: monoio_http::h2::client::Builder,
 }
 
@@ -62,12 +97,16 @@ pub struct ConnectionConfig {
 #[derive(Default, Clone)]
 pub struct ClientGlobalConfig {
     max_idle_connections: usize,
+    min_idle_connections: HashMap<Key, usize>,
+    listener: Option<Rc<dyn ConnectionListener>>,
+    socket_options: Option<SocketOptions>,
 }
 
 #[derive(Default, Clone)]
 pub struct Builder {
     connection_config: ConnectionConfig,
     global_config: ClientGlobalConfig,
+    client_config: ClientConfig,
 }
 
 impl Builder {
@@ -95,6 +134,81 @@ impl Builder {
         self
     }
 
+    /// Keep at least `conns` idle connections of the key, they are dialed
+    /// in background when the client is built, and redialed once they
+    /// expire or die.
+    pub fn min_idle_connections(mut self, key: Key, conns: usize) -> Self {
+        self.global_config.min_idle_connections.insert(key, conns);
+        self
+    }
+
+    /// Listen to connection lifecycle events.
+    pub fn connection_listener(mut self, listener: impl ConnectionListener + 'static) -> Self {
+        self.global_config.listener = Some(Rc::new(listener));
+        self
+    }
+
+    /// Options of tcp sockets, they are not used by `build_with_connector`.
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.global_config.socket_options = Some(options);
+        self
+    }
+
+    /// Limit of response bodies, it can be overridden per request.
+    /// Reading a larger body fails with `ErrorKind::BodyTooLarge`.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.client_config.max_body_size = Some(limit);
+        self
+    }
+
+    /// Balance requests to the host `name` across the endpoints of the set,
+    /// whatever the port of the uri is. With tls the name is still used as
+    /// the server name.
+    pub fn service(mut self, name: impl Into<SmolStr>, endpoints: EndpointSet) -> Self {
+        self.client_config.services.insert(name.into(), endpoints);
+        self
+    }
+
+    /// Limit the requests to the keys matching the pattern, the first
+    /// matching limit applies. Requests to a service of `Builder::service`
+    /// are matched by the service name.
+    pub fn rate_limit(mut self, pattern: impl Into<HostPattern>, limit: RateLimit) -> Self {
+        self.client_config.rate_limits.push((pattern.into(), limit));
+        self
+    }
+
+    /// Cache responses of GET requests, see `HttpCache`.
+    pub fn cache(mut self, cache: HttpCache) -> Self {
+        self.client_config.cache = Some(cache);
+        self
+    }
+
+    /// Coalesce identical concurrent GET requests, see `SingleFlight`.
+    pub fn single_flight(mut self, config: SingleFlight) -> Self {
+        self.client_config.single_flight = Some(config);
+        self
+    }
+
+    /// Fail fast on keys which keep failing, see `CircuitBreaker`.
+    pub fn circuit_breaker(mut self, config: CircuitBreaker) -> Self {
+        self.client_config.circuit_breaker = Some(config);
+        self
+    }
+
+    /// Hedged attempts allowed per request sent with `ClientRequest::hedge`,
+    /// 0.1 by default. Unused allowance is saved up to a burst of 10
+    /// attempts, so hedging cannot amplify load when a backend is down.
+    pub fn hedge_budget(mut self, ratio: f64) -> Self {
+        self.client_config.hedge_budget = Some(ratio);
+        self
+    }
+
+    /// Limits and leniency of the http1 response parser.
+    pub fn http1_decoder_config(mut self, config: DecoderConfig) -> Self {
+        self.connection_config.h1_decoder = config;
+        self
+    }
+
     pub fn http2_max_frame_size(mut self, sz: u32) -> Self {
         self.connection_config.h2_builder.max_frame_size(sz);
         self
@@ -129,15 +243,46 @@ impl Builder {
         self
     }
 
+    /// Send requests to a https host over an idle http2 connection of
+    /// another host, if the peer ip is among the addresses of the host and
+    /// the certificate covers it(RFC 9113 section 9.1.1). A request answered
+    /// with `421 Misdirected Request` is sent again on a connection of its
+    /// own.
+    pub fn http2_coalescing(mut self) -> Self {
+        self.client_config.http2_coalescing = true;
+        self
+    }
+
     pub fn build(self) -> Client {
-        Client::new(self.global_config, self.connection_config)
+        Client::new(
+            self.client_config,
+            self.global_config,
+            self.connection_config,
+        )
     }
 
-    pub fn build_with_connector<C>(self, connector: C) -> Client<C> {
+    pub fn build_with_connector<C>(self, connector: C) -> Client<C>
+    where
+        C: Connector<Key, Connection = UnifiedTransportConnection> + 'static,
+        crate::Error: From<C::Error>,
+    {
+        let listener = self.global_config.listener.clone();
+        let min_idle = self.global_config.min_idle_connections.clone();
+        let mut connector =
+            PooledConnector::new(self.global_config, self.connection_config, connector);
+        if let Some(listener) = listener.clone() {
+            connector.set_listener(listener);
+        }
+        let circuits = self.client_config.circuit_breaker.clone();
         let shared = Rc::new(ClientInner {
-            cfg: ClientConfig::default(),
-            connector: PooledConnector::new(self.global_config, self.connection_config, connector),
+            hedge_budget: HedgeBudget::new(self.client_config.hedge_budget),
+            circuits: circuits.map(|config| Circuits::new(config, listener)),
+            rate_limits: RateLimits::new(self.client_config.rate_limits.clone()),
+            flights: self.client_config.single_flight.clone().map(Flights::new),
+            cfg: self.client_config,
+            connector,
         });
+        set_dialer(&shared, min_idle);
         Client { shared }
     }
 }
@@ -145,6 +290,14 @@ impl Builder {
 #[derive(Default, Clone, Debug)]
 pub struct ClientConfig {
     default_headers: Rc<HeaderMap>,
+    max_body_size: Option<usize>,
+    hedge_budget: Option<f64>,
+    services: HashMap<SmolStr, EndpointSet>,
+    circuit_breaker: Option<CircuitBreaker>,
+    rate_limits: Vec<(HostPattern, RateLimit)>,
+    cache: Option<HttpCache>,
+    single_flight: Option<SingleFlight>,
+    http2_coalescing: bool,
 }
 
 impl Default for Client {
@@ -154,11 +307,30 @@ impl Default for Client {
 }
 
 impl Client {
-    fn new(g_config: ClientGlobalConfig, c_config: ConnectionConfig) -> Client {
+    fn new(cfg: ClientConfig, g_config: ClientGlobalConfig, c_config: ConnectionConfig) -> Client {
+        let listener = g_config.listener.clone();
+        let min_idle = g_config.min_idle_connections.clone();
+        let mut connector = match g_config.socket_options.clone() {
+            Some(options) => {
+                PooledConnector::new(g_config, c_config, UnifiedTransportConnector::new(options))
+            }
+            None => PooledConnector::new_default(g_config, c_config),
+        };
+        if let Some(listener) = listener.clone() {
+            connector.set_listener(listener);
+        }
         let shared = Rc::new(ClientInner {
-            cfg: ClientConfig::default(),
-            connector: PooledConnector::new_default(g_config, c_config),
+            hedge_budget: HedgeBudget::new(cfg.hedge_budget),
+            circuits: cfg
+                .circuit_breaker
+                .clone()
+                .map(|config| Circuits::new(config, listener)),
+            rate_limits: RateLimits::new(cfg.rate_limits.clone()),
+            flights: cfg.single_flight.clone().map(Flights::new),
+            cfg,
+            connector,
         });
+        set_dialer(&shared, min_idle);
         Client { shared }
     }
 
@@ -167,6 +339,26 @@ impl Client {
     }
 }
 
+/// Let the pool dial through the client, the client is not kept alive by
+/// the pool.
+fn set_dialer<C>(shared: &Rc<ClientInner<C>>, min_idle: HashMap<Key, usize>)
+where
+    C: Connector<Key, Connection = UnifiedTransportConnection> + 'static,
+    crate::Error: From<C::Error>,
+{
+    let weak = Rc::downgrade(shared);
+    let dialer: Dialer<Key, UnifiedTransportConnection> = Rc::new(move |key| {
+        let weak = weak.clone();
+        Box::pin(async move {
+            match weak.upgrade() {
+                Some(shared) => shared.connector.dial(key).await,
+                None => Err(crate::ErrorKind::Shutdown.into()),
+            }
+        })
+    });
+    shared.connector.set_dialer(dialer, min_idle);
+}
+
 macro_rules! http_method {
     ($fn: ident, $method: expr) => {
         pub fn $fn<U>(&self, uri: U) -> ClientRequest<C>
@@ -203,6 +395,85 @@ impl<C> Client<C> {
         req
     }
 
+    /// Connect to a Server-Sent Events stream.
+    pub fn event_source(&self, uri: http::Uri) -> EventSource<C> {
+        EventSource::new(self.clone(), uri)
+    }
+
+    /// Download the response body of `uri` to the file at `path`.
+    #[cfg(feature = "download")]
+    pub fn download(&self, uri: http::Uri, path: impl Into<std::path::PathBuf>) -> Download<C> {
+        Download::new(self.clone(), uri, path)
+    }
+
+    /// Start a WebSocket connection.
+    #[cfg(feature = "ws")]
+    pub fn websocket(&self, uri: http::Uri) -> WebSocketRequest<C> {
+        WebSocketRequest::new(self.clone(), uri)
+    }
+
+    /// Create a gRPC channel to the origin, calls share the pooled http2
+    /// connections of the client.
+    #[cfg(feature = "grpc")]
+    pub fn grpc(&self, origin: http::Uri) -> Channel<C> {
+        Channel::new(self.clone(), origin)
+    }
+
+    /// Establish `n` connections to the uri ahead of traffic, they are kept
+    /// in the pool as idle. Returns the first error if any of them fails.
+    pub async fn preconnect<U>(&self, uri: U, n: usize) -> crate::Result<()>
+    where
+        http::Uri: TryFrom<U>,
+        <http::Uri as TryFrom<U>>::Error: Into<http::Error>,
+    {
+        let uri = http::Uri::try_from(uri)
+            .map_err(|e| crate::Error::from(e.into()).stage(Stage::Build))?;
+        let key = Key::try_from(&uri).map_err(|e| crate::Error::from(e).stage(Stage::Build))?;
+        self.shared
+            .connector
+            .preconnect(key, n)
+            .await
+            .map_err(|e| e.stage(Stage::Connect))
+    }
+
+    /// Shut down the client gracefully. New requests fail with
+    /// `ErrorKind::Shutdown`, idle connections are closed and GOAWAY is sent
+    /// on http2 connections.
+    ///
+    /// Resolves once in-flight requests finish and all connections are
+    /// closed, returns false if the deadline is hit first.
+    pub async fn shutdown(&self, deadline: monoio::time::Instant) -> bool {
+        monoio::time::timeout_at(deadline, self.shared.connector.shutdown())
+            .await
+            .is_ok()
+    }
+
+    /// State of the circuit of the key, None if the circuit breaker is not
+    /// enabled.
+    pub fn circuit_state(&self, key: &Key) -> Option<CircuitState> {
+        self.shared
+            .circuits
+            .as_ref()
+            .map(|circuits| circuits.state(key))
+    }
+
+    #[inline]
+    pub(crate) fn max_body_size(&self) -> Option<usize> {
+        self.shared.cfg.max_body_size
+    }
+
+    #[cfg(any(feature = "ws", feature = "grpc"))]
+    #[inline]
+    pub(crate) fn default_headers(&self) -> &HeaderMap {
+        &self.shared.cfg.default_headers
+    }
+
+    #[cfg(feature = "ws")]
+    #[inline]
+    pub(crate) fn pooled_connector(&self) -> &PooledConnector<C, Key, UnifiedTransportConnection> {
+        &self.shared.connector
+    }
+
     pub async fn send_request<B: Body<Data = Bytes, Error = HttpError> + 'static>(
         &self,
         req: Request<B>,
@@ -214,9 +485,233 @@ impl<C> Client<C> {
             Error = crate::Error,
         >,
     {
-        let mut key: Key = req.uri().try_into()?;
+        self.send_request_with_abort(req, None).await
+    }
+
+    /// Send the request, it can be cancelled with the given `AbortHandle`.
+    pub async fn send_request_with_abort<B: Body<Data = Bytes, Error = HttpError> + 'static>(
+        &self,
+        req: Request<B>,
+        abort: Option<AbortHandle>,
+    ) -> crate::Result<Response<HttpBody>>
+    where
+        PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+            Key,
+            Connection = PooledConnection<Key, UnifiedTransportConnection>,
+            Error = crate::Error,
+        >,
+    {
+        let body_limit = self.shared.cfg.max_body_size;
+        self.send_cached(req, body_limit, |req| {
+            self.send_coalesced(req, body_limit, |req| self.execute(req, abort, body_limit))
+        })
+        .await
+    }
+
+    pub(crate) async fn execute<B: Body<Data = Bytes, Error = HttpError> + 'static>(
+        &self,
+        req: Request<B>,
+        abort: Option<AbortHandle>,
+        body_limit: Option<usize>,
+    ) -> crate::Result<Response<HttpBody>>
+    where
+        PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+            Key,
+            Connection = PooledConnection<Key, UnifiedTransportConnection>,
+            Error = crate::Error,
+        >,
+    {
+        self.execute_excluding(req, abort, body_limit, None).await
+    }
+
+    /// Connections whose ids are in `used` are avoided, the id of the one
+    /// taken is added to it.
+    async fn execute_excluding<B: Body<Data = Bytes, Error = HttpError> + 'static>(
+        &self,
+        req: Request<B>,
+        abort: Option<AbortHandle>,
+        body_limit: Option<usize>,
+        used: Option<&RefCell<Vec<u64>>>,
+    ) -> crate::Result<Response<HttpBody>>
+    where
+        PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+            Key,
+            Connection = PooledConnection<Key, UnifiedTransportConnection>,
+            Error = crate::Error,
+        >,
+    {
+        let context = |key: Option<Key>, reused| {
+            let (method, uri) = (req.method().clone(), req.uri().clone());
+            move || crate::error::Context {
+                method,
+                uri,
+                key,
+                reused,
+            }
+        };
+        if matches!(abort, Some(ref abort) if abort.is_aborted()) {
+            return Err(crate::Error::new(crate::ErrorKind::Aborted, Stage::Build)
+                .context(context(None, None)));
+        }
+        let mut key: Key = match req.uri().try_into() {
+            Ok(key) => key,
+            Err(e) => {
+                return Err(crate::Error::from(e)
+                    .stage(Stage::Build)
+                    .context(context(None, None)))
+            }
+        };
         key.version = req.version();
-        let conn = self.shared.connector.connect(key).await?;
-        conn.send_request(req).await
+        let limited = match self.shared.rate_limits.as_ref() {
+            Some(limits) => {
+                let delay = match limits.acquire(&key) {
+                    Ok(delay) => delay,
+                    Err(delay) => {
+                        return Err(crate::Error::new(
+                            crate::ErrorKind::RateLimited(delay),
+                            Stage::Build,
+                        )
+                        .context(context(Some(key), None)))
+                    }
+                };
+                if !delay.is_zero()
+                    && or_abort(monoio::time::sleep(delay), abort.as_ref())
+                        .await
+                        .is_none()
+                {
+                    return Err(crate::Error::new(crate::ErrorKind::Aborted, Stage::Build)
+                        .context(context(Some(key), None)));
+                }
+                Some((limits, key.clone()))
+            }
+            None => None,
+        };
+        let pick = match self.shared.cfg.services.get(&key.host) {
+            Some(endpoints) => match endpoints.pick(req.headers()) {
+                Some(pick) => {
+                    key.host = pick.addr().ip().to_string().into();
+                    key.port = pick.addr().port();
+                    Some(pick)
+                }
+                None => {
+                    return Err(crate::Error::new(
+                        crate::ErrorKind::NoEndpoint(key.host.clone()),
+                        Stage::Connect,
+                    )
+                    .context(context(Some(key), None)))
+                }
+            },
+            None => None,
+        };
+        let attempt = match self.shared.circuits.as_ref() {
+            Some(circuits) => match circuits.attempt(&key) {
+                Some(attempt) => Some(attempt),
+                None => {
+                    return Err(
+                        crate::Error::new(crate::ErrorKind::CircuitOpen, Stage::Connect)
+                            .context(context(Some(key), None)),
+                    )
+                }
+            },
+            None => None,
+        };
+        let connector = &self.shared.connector;
+        // Only bodies which can be sent again ride on connections of other
+        // hosts.
+        let coalesced = match (used, &pick) {
+            (None, None) if req.body().stream_hint() != StreamHint::Stream => {
+                self.coalesced_connection(&key)
+            }
+            _ => None,
+        };
+        let is_coalesced = coalesced.is_some();
+        let conn = match (coalesced, used) {
+            (Some(conn), _) => Ok(conn),
+            (None, Some(used)) => {
+                let excluded = used.borrow().clone();
+                let conn = match connector.connect_excluding(key.clone(), &excluded).await {
+                    Some(conn) => conn,
+                    None => connector.connect(key.clone()).await,
+                };
+                if let Ok(conn) = conn.as_ref() {
+                    used.borrow_mut().push(conn.info().id());
+                }
+                conn
+            }
+            (None, None) => connector.connect(key.clone()).await,
+        };
+        let conn = match conn {
+            Ok(conn) => conn,
+            Err(e) => {
+                if let Some(pick) = pick {
+                    pick.connect_failed();
+                }
+                if let Some(attempt) = attempt {
+                    attempt.failure();
+                }
+                return Err(e.stage(Stage::Connect).context(context(Some(key), None)));
+            }
+        };
+        let context = context(Some(key.clone()), Some(conn.is_reused()));
+        if let Some(pick) = pick.as_ref() {
+            pick.connected();
+        }
+        let sent = match is_coalesced {
+            true => {
+                self.send_on_coalesced(conn, &key, req, abort, body_limit)
+                    .await
+            }
+            false => conn.send_request(req, abort, body_limit).await,
+        };
+        let resp = match sent {
+            Ok(resp) => resp,
+            Err(e) => {
+                if let (Some(attempt), false) =
+                    (attempt, matches!(e.kind(), crate::ErrorKind::Aborted))
+                {
+                    attempt.failure();
+                }
+                return Err(e.context(context));
+            }
+        };
+        if let Some(pick) = pick {
+            pick.responded(resp.status());
+        }
+        if let Some((limits, key)) = limited {
+            limits.observe(&key, resp.status(), resp.headers());
+        }
+        if let Some(attempt) = attempt {
+            match resp.status().is_server_error() {
+                true => attempt.failure(),
+                false => attempt.success(),
+            }
+        }
+        Ok(resp)
+    }
+
+    /// Get a request handle of a pooled http2 connection to the uri, streams
+    /// opened with it are multiplexed on the connection.
+    #[cfg(any(feature = "ws", feature = "grpc"))]
+    pub(crate) async fn http2_send_request(
+        &self,
+        uri: &http::Uri,
+    ) -> crate::Result<monoio_http::h2::client::SendRequest<Bytes>>
+    where
+        PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+            Key,
+            Connection = PooledConnection<Key, UnifiedTransportConnection>,
+            Error = crate::Error,
+        >,
+    {
+        let mut key: Key = uri.try_into()?;
+        key.version = http::Version::HTTP_2;
+        let conn = self
+            .shared
+            .connector
+            .connect(key)
+            .await
+            .map_err(|e| e.stage(Stage::Connect))?;
+        conn.http2_send_request()
+            .ok_or(crate::ErrorKind::NotHttp2.into())
     }
 }
@@ -1,14 +1,17 @@
 use std::{
-    cell::UnsafeCell,
+    cell::{RefCell, UnsafeCell},
     collections::{HashMap, VecDeque},
     fmt::{Debug, Display},
     future::Future,
     hash::Hash,
+    pin::Pin,
     rc::{Rc, Weak},
     task::ready,
     time::{Duration, Instant},
 };
 
+use local_sync::{mpsc::unbounded, oneshot};
+
 #[cfg(feature = "time")]
 const DEFAULT_IDLE_INTERVAL: Duration = Duration::from_secs(60);
 const DEFAULT_KEEPALIVE_CONNS: usize = 256;
@@ -18,35 +21,50 @@ const MAX_KEEPALIVE_CONNS: usize = 16384;
 
 use bytes::Bytes;
 use monoio::io::{AsyncReadRent, AsyncWriteRent, Split};
-use monoio_http::common::{
-    body::{Body, HttpBody},
-    error::HttpError,
-    request::Request,
-    response::Response,
+use monoio_http::{
+    common::{
+        body::{Body, HttpBody},
+        error::HttpError,
+        request::Request,
+        response::Response,
+    },
+    h2::client::SendRequest,
 };
 
-use super::connection::HttpConnection;
+use super::{
+    abort::AbortHandle,
+    connection::{or_abort, relay_h2_body, HttpConnection},
+    info::{ConnectionInfo, Timings},
+    listener::{CloseReason, ConnectionListener},
+};
+use crate::error::Stage;
 
 const CONN_CLOSE: &[u8] = b"close";
 
 type Conns<K, IO> = Rc<UnsafeCell<SharedInner<K, IO>>>;
 type WeakConns<K, IO> = Weak<UnsafeCell<SharedInner<K, IO>>>;
 
+/// Establishes a new connection of the key, used to warm up the pool.
+pub type Dialer<K, IO> = Rc<
+    dyn Fn(K) -> Pin<Box<dyn Future<Output = crate::Result<(HttpConnection<IO>, ConnectionInfo)>>>>,
+>;
+
 struct IdleConnection<IO: AsyncWriteRent> {
     conn: HttpConnection<IO>,
+    info: ConnectionInfo,
     idle_at: Instant,
 }
 
 impl<IO: AsyncWriteRent + AsyncReadRent + Split> IdleConnection<IO> {
-    fn reserve(mut self) -> (HttpConnection<IO>, Option<Self>) {
+    fn reserve(mut self) -> (HttpConnection<IO>, ConnectionInfo, Option<Self>) {
         let is_h2 = self.conn.is_http2();
 
         if is_h2 {
             let conn = self.conn.http2_conn_clone();
             self.idle_at = Instant::now();
-            (conn, Some(self))
+            (conn, self.info.clone(), Some(self))
         } else {
-            (self.conn, None)
+            (self.conn, self.info, None)
         }
     }
 }
@@ -54,6 +72,15 @@ impl<IO: AsyncWriteRent + AsyncReadRent + Split> IdleConnection<IO> {
 struct SharedInner<K, IO: AsyncWriteRent> {
     mapping: HashMap<K, VecDeque<IdleConnection<IO>>>,
     max_idle: usize,
+    listener: Option<Rc<dyn ConnectionListener<K>>>,
+    closed: bool,
+    // Every checked out connection and http2 driver holds a clone of the
+    // sender, the receiver is closed once all of them are dropped.
+    drain_tx: Option<unbounded::Tx<()>>,
+    drain_rx: Option<unbounded::Rx<()>>,
+    h2_shutdown: Vec<oneshot::Sender<()>>,
+    dialer: Option<Dialer<K, IO>>,
+    refill: Option<Rc<dyn Refill<K>>>,
     #[cfg(feature = "time")]
     _drop: local_sync::oneshot::Receiver<()>,
 }
@@ -67,12 +94,20 @@ impl<K, IO: AsyncWriteRent> SharedInner<K, IO> {
             .unwrap_or(DEFAULT_KEEPALIVE_CONNS);
 
         let (tx, _drop) = local_sync::oneshot::channel();
+        let (drain_tx, drain_rx) = unbounded::channel();
         (
             tx,
             Self {
                 mapping,
                 _drop,
                 max_idle,
+                listener: None,
+                closed: false,
+                drain_tx: Some(drain_tx),
+                drain_rx: Some(drain_rx),
+                h2_shutdown: Vec::new(),
+                dialer: None,
+                refill: None,
             },
         )
     }
@@ -83,15 +118,129 @@ impl<K, IO: AsyncWriteRent> SharedInner<K, IO> {
         let max_idle = max_idle
             .map(|n| n.min(MAX_KEEPALIVE_CONNS))
             .unwrap_or(DEFAULT_KEEPALIVE_CONNS);
-        Self { mapping, max_idle }
+        let (drain_tx, drain_rx) = unbounded::channel();
+        Self {
+            mapping,
+            max_idle,
+            listener: None,
+            closed: false,
+            drain_tx: Some(drain_tx),
+            drain_rx: Some(drain_rx),
+            h2_shutdown: Vec::new(),
+            dialer: None,
+            refill: None,
+        }
     }
 
     fn clear_expired(&mut self, dur: Duration) {
-        self.mapping.retain(|_, values| {
-            values.retain(|entry| entry.idle_at.elapsed() <= dur);
+        let listener = self.listener.as_deref();
+        self.mapping.retain(|key, values| {
+            values.retain(|entry| {
+                let expired = entry.idle_at.elapsed() > dur;
+                if let (true, Some(listener)) = (expired, listener) {
+                    listener.on_connection_closed(key, CloseReason::IdleTimeout);
+                }
+                !expired
+            });
             !values.is_empty()
         });
     }
+
+    fn on_closed(&self, key: &K, reason: CloseReason) {
+        if let Some(listener) = self.listener.as_ref() {
+            listener.on_connection_closed(key, reason);
+        }
+    }
+}
+
+impl<K: Hash + Eq, IO: AsyncWriteRent> SharedInner<K, IO> {
+    /// Put a new connection into the pool as idle, it is closed if the pool
+    /// is shut down or full.
+    fn park(&mut self, key: K, conn: HttpConnection<IO>, info: ConnectionInfo) {
+        let idle = self.mapping.get(&key).map_or(0, VecDeque::len);
+        let reason = if self.closed {
+            CloseReason::Shutdown
+        } else if idle >= self.max_idle {
+            CloseReason::PoolFull
+        } else {
+            let max_idle = self.max_idle;
+            self.mapping
+                .entry(key)
+                .or_insert_with(|| VecDeque::with_capacity(max_idle))
+                .push_back(IdleConnection {
+                    conn,
+                    info,
+                    idle_at: Instant::now(),
+                });
+            return;
+        };
+        // Http2 connections are reported by their drivers once dropped.
+        if let HttpConnection::H1(_) = conn {
+            self.on_closed(&key, reason);
+        }
+    }
+}
+
+/// Dials in background to keep the minimum idle connections.
+trait Refill<K> {
+    fn refill(&self, key: &K);
+
+    fn refill_all(&self);
+}
+
+struct MinIdle<K, IO: AsyncWriteRent> {
+    pool: WeakConns<K, IO>,
+    dialer: Dialer<K, IO>,
+    min_idle: HashMap<K, usize>,
+    // Number of connections being dialed for each key.
+    dialing: Rc<RefCell<HashMap<K, usize>>>,
+}
+
+impl<K, IO> Refill<K> for MinIdle<K, IO>
+where
+    K: Hash + Eq + ToOwned<Owned = K> + 'static,
+    IO: AsyncWriteRent + 'static,
+{
+    fn refill(&self, key: &K) {
+        let (pool, min) = match (self.pool.upgrade(), self.min_idle.get(key)) {
+            (Some(pool), Some(min)) => (pool, *min),
+            _ => return,
+        };
+        let conns = unsafe { &*pool.get() };
+        if conns.closed {
+            return;
+        }
+        let idle = conns.mapping.get(key).map_or(0, VecDeque::len);
+        let mut dialing = self.dialing.borrow_mut();
+        let dialing = dialing.entry(key.to_owned()).or_default();
+        while idle + *dialing < min {
+            *dialing += 1;
+            let dial = (self.dialer)(key.to_owned());
+            let (key, pool, counter) = (key.to_owned(), self.pool.clone(), self.dialing.clone());
+            monoio::spawn(async move {
+                let result = dial.await;
+                if let Some(n) = counter.borrow_mut().get_mut(&key) {
+                    *n -= 1;
+                }
+                match (result, pool.upgrade()) {
+                    (Ok((conn, info)), Some(pool)) => {
+                        unsafe { &mut *pool.get() }.park(key, conn, info)
+                    }
+                    (Err(_e), _) => {
+                        #[cfg(feature = "logging")]
+                        tracing::debug!("dial for min idle connections failed: {_e}");
+                    }
+                    _ => {}
+                }
+            });
+        }
+    }
+
+    fn refill_all(&self) {
+        for key in self.min_idle.keys() {
+            self.refill(key);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -130,6 +279,138 @@ impl<K: 'static, IO: AsyncWriteRent + 'static> ConnectionPool<K, IO> {
     }
 }
 
+impl<K, IO> ConnectionPool<K, IO>
+where
+    K: Hash + Eq + ToOwned<Owned = K> + 'static,
+    IO: AsyncWriteRent + 'static,
+{
+    /// Set how to establish new connections for `preconnect`, and keep at
+    /// least `min_idle` idle connections of the keys in background. Dead and
+    /// expired connections are redialed.
+    pub fn set_dialer(&self, dialer: Dialer<K, IO>, min_idle: HashMap<K, usize>) {
+        let conns = unsafe { &mut *self.conns.get() };
+        conns.dialer = Some(dialer.clone());
+        if min_idle.is_empty() {
+            return;
+        }
+        let refill = Rc::new(MinIdle {
+            pool: Rc::downgrade(&self.conns),
+            dialer,
+            min_idle,
+            dialing: Default::default(),
+        });
+        conns.refill = Some(refill.clone());
+        refill.refill_all();
+    }
+
+    /// Establish `n` connections of the key concurrently and put them into
+    /// the pool. Returns the first error if any of them fails.
+    pub async fn preconnect(&self, key: K, n: usize) -> crate::Result<()> {
+        let dialer = match unsafe { &*self.conns.get() }.dialer.clone() {
+            Some(dialer) => dialer,
+            None => return Ok(()),
+        };
+        let tasks: Vec<_> = (0..n)
+            .map(|_| monoio::spawn(dialer(key.to_owned())))
+            .collect();
+        let mut result = Ok(());
+        for task in tasks {
+            match task.await {
+                Ok((conn, info)) => {
+                    unsafe { &mut *self.conns.get() }.park(key.to_owned(), conn, info)
+                }
+                Err(e) if result.is_ok() => result = Err(e),
+                Err(_) => {}
+            }
+        }
+        result
+    }
+}
+
+/// Held by the driver of a http2 connection, the pool waits for it to be
+/// dropped on shutdown.
+pub struct Drain {
+    _token: unbounded::Tx<()>,
+    shutdown: Option<oneshot::Receiver<()>>,
+}
+
+impl Drain {
+    /// Returns true once the pool is shut down, the connection should send
+    /// GOAWAY then.
+    pub(crate) fn poll_shutdown(&mut self, cx: &mut std::task::Context<'_>) -> bool {
+        let rx = match self.shutdown.as_mut() {
+            Some(rx) => rx,
+            None => return false,
+        };
+        match std::pin::Pin::new(rx).poll(cx) {
+            std::task::Poll::Ready(signal) => {
+                // The pool is dropped without shutdown if it is an error.
+                self.shutdown = None;
+                signal.is_ok()
+            }
+            std::task::Poll::Pending => false,
+        }
+    }
+}
+
+impl<K, IO: AsyncWriteRent> ConnectionPool<K, IO> {
+    /// Set the listener of connection lifecycle events.
+    pub fn set_listener(&self, listener: Rc<dyn ConnectionListener<K>>) {
+        let conns = unsafe { &mut *self.conns.get() };
+        conns.listener = Some(listener);
+    }
+
+    /// Returns true if the pool is shut down.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        unsafe { &*self.conns.get() }.closed
+    }
+
+    /// Register the driver of a new http2 connection. Returns None if the
+    /// pool is shut down.
+    pub fn drain(&self) -> Option<Drain> {
+        let conns = unsafe { &mut *self.conns.get() };
+        let token = conns.drain_tx.clone()?;
+        let (tx, shutdown) = oneshot::channel();
+        conns.h2_shutdown.retain(|tx| !tx.is_closed());
+        conns.h2_shutdown.push(tx);
+        Some(Drain {
+            _token: token,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Stop handing out connections, close the idle ones and send GOAWAY on
+    /// http2 connections. Resolves once all connections are closed.
+    ///
+    /// Calling it again resolves immediately.
+    pub async fn shutdown(&self) {
+        let mut rx = {
+            let conns = unsafe { &mut *self.conns.get() };
+            conns.closed = true;
+            for (key, queue) in conns.mapping.drain() {
+                if let Some(listener) = conns.listener.as_ref() {
+                    // Http2 connections are closed by their drivers.
+                    for idle in queue {
+                        if let HttpConnection::H1(_) = idle.conn {
+                            listener.on_connection_closed(&key, CloseReason::Shutdown);
+                        }
+                    }
+                }
+            }
+            for tx in conns.h2_shutdown.drain(..) {
+                let _ = tx.send(());
+            }
+            conns.drain_tx = None;
+            match conns.drain_rx.take() {
+                Some(rx) => rx,
+                None => return,
+            }
+        };
+        while rx.recv().await.is_some() {}
+    }
+}
+
 impl<K: 'static, IO: AsyncWriteRent + 'static> Default for ConnectionPool<K, IO> {
     #[cfg(feature = "time")]
     fn default() -> Self {
@@ -146,50 +427,148 @@ pub struct PooledConnection<K: Hash + Eq + Display, IO: AsyncWriteRent> {
     // option is for take when drop
     key: Option<K>,
     conn: Option<HttpConnection<IO>>,
+    info: ConnectionInfo,
     pool: WeakConns<K, IO>,
     reusable: bool,
     remove_h2: bool, // Remove H2 Connection
+    reused: bool,
+    _drain: Option<unbounded::Tx<()>>,
 }
 
 impl<K, IO> PooledConnection<K, IO>
 where
-    K: Hash + Eq + Display,
-    IO: AsyncWriteRent + AsyncReadRent + Split,
+    K: Hash + Eq + Display + 'static,
+    IO: AsyncWriteRent + AsyncReadRent + Split + 'static,
 {
+    /// Clone the request handle if it is a http2 connection, streams opened
+    /// with it are multiplexed on the pooled connection.
+    pub fn http2_send_request(&self) -> Option<SendRequest<Bytes>> {
+        self.conn
+            .as_ref()
+            .and_then(|conn| conn.http2_send_request())
+            .cloned()
+    }
+
     pub async fn send_request<B: Body<Data = Bytes, Error = HttpError> + 'static>(
         mut self,
         req: Request<B>,
+        abort: Option<AbortHandle>,
+        body_limit: Option<usize>,
     ) -> Result<Response<HttpBody>, crate::Error> {
-        match self.conn.as_mut() {
-            Some(conn) => {
-                let (result, remove) = conn.send_request(req).await;
-                self.remove_h2 = remove;
-
-                match result {
-                    Ok(resp) => {
-                        let header_value = resp.headers().get(http::header::CONNECTION);
-                        if let Some(v) = header_value {
-                            self.set_reusable(!v.as_bytes().eq_ignore_ascii_case(CONN_CLOSE))
+        let conn = match self.conn.as_mut() {
+            Some(conn) => conn,
+            None => {
+                return Err(crate::Error::new(
+                    crate::ErrorKind::MissingCodec,
+                    Stage::Queue,
+                ))
+            }
+        };
+        // A http1 connection must not be recycled if the request does not
+        // finish, so it is marked as not reusable until the body is read.
+        let reusable = self.reusable;
+        let is_h2 = conn.is_http2();
+        if !is_h2 {
+            self.reusable = false;
+        }
+
+        let (result, remove) =
+            match or_abort(conn.send_request(req, abort.as_ref()), abort.as_ref()).await {
+                Some(r) => r,
+                None => (
+                    Err(crate::Error::new(crate::ErrorKind::Aborted, Stage::Send)),
+                    false,
+                ),
+            };
+        self.remove_h2 = remove;
+
+        let (mut resp, pending) = result?;
+        let mut info = self.info.clone();
+        info.set_reused(self.reused);
+        let timings = resp.extensions_mut().get_mut::<Timings>().map(|timings| {
+            if !self.reused {
+                timings.set_connect(info.connect_timings());
+            }
+            timings.clone()
+        });
+        resp.extensions_mut().insert(info);
+        let keep_alive = !matches!(
+            resp.headers().get(http::header::CONNECTION),
+            Some(v) if v.as_bytes().eq_ignore_ascii_case(CONN_CLOSE)
+        );
+        match pending {
+            None => {
+                if is_h2 {
+                    // Http2 bodies are still to be received.
+                    resp = relay_h2_body(resp, body_limit, self._drain.clone());
+                } else if let Some(timings) = timings {
+                    timings.finish_body();
+                }
+                self.set_reusable(reusable && keep_alive)
+            }
+            Some(pending) => {
+                // Read the body in background, the connection is returned to
+                // the pool once the body is fully read.
+                monoio::spawn(async move {
+                    if let Some(HttpConnection::H1(codec)) = self.conn.as_mut() {
+                        let clean = pending.read_from(codec, abort.as_ref(), body_limit).await;
+                        if let (true, Some(timings)) = (clean, timings) {
+                            timings.finish_body();
                         }
-                        Ok(resp)
+                        self.set_reusable(reusable && keep_alive && clean);
                     }
-                    Err(e) => Err(e),
-                }
+                });
             }
-            None => Err(crate::Error::MissingCodec),
         }
+        Ok(resp)
     }
+}
 
+impl<K: Hash + Eq + Display, IO: AsyncWriteRent> PooledConnection<K, IO> {
     pub fn set_reusable(&mut self, set: bool) {
         self.reusable = set;
     }
+
+    /// Returns true if the connection is got from the pool rather than
+    /// newly established.
+    #[inline]
+    pub fn is_reused(&self) -> bool {
+        self.reused
+    }
+
+    /// Details of the underlying connection.
+    #[inline]
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
 }
 
 impl<K: Hash + Eq + Display, IO: AsyncWriteRent> Drop for PooledConnection<K, IO> {
     fn drop(&mut self) {
+        if let (Some(pool), Some(key)) = (self.pool.upgrade(), self.key.as_ref()) {
+            let conns = unsafe { &*pool.get() };
+            if conns.closed {
+                // Http2 connections are closed by their drivers.
+                if let Some(HttpConnection::H1(_)) = self.conn.as_ref() {
+                    conns.on_closed(key, CloseReason::Shutdown);
+                }
+                return;
+            }
+        }
         if !self.reusable && !self.remove_h2 {
             #[cfg(feature = "logging")]
             tracing::debug!("connection dropped");
+            // Clones of a http2 connection are not pooled, but the connection
+            // is still alive.
+            if let (Some(pool), Some(key), Some(HttpConnection::H1(_))) =
+                (self.pool.upgrade(), self.key.as_ref(), self.conn.as_ref())
+            {
+                let conns = unsafe { &*pool.get() };
+                conns.on_closed(key, CloseReason::NotReusable);
+                if let Some(refill) = conns.refill.clone() {
+                    refill.refill(key);
+                }
+            }
             return;
         }
 
@@ -198,6 +577,7 @@ impl<K: Hash + Eq + Display, IO: AsyncWriteRent> Drop for PooledConnection<K, IO
             let conn = self.conn.take().expect("unable to take connection");
             let idle = IdleConnection {
                 conn,
+                info: self.info.clone(),
                 idle_at: Instant::now(),
             };
 
@@ -207,13 +587,24 @@ impl<K: Hash + Eq + Display, IO: AsyncWriteRent> Drop for PooledConnection<K, IO
 
             if self.remove_h2 {
                 // There should be only one H2 connection per host
-                let _queue = conns.mapping.remove(&key);
+                if conns.mapping.remove(&key).is_some() {
+                    conns.on_closed(&key, CloseReason::Http2Error);
+                }
+                if let Some(refill) = conns.refill.clone() {
+                    refill.refill(&key);
+                }
 
                 #[cfg(feature = "logging")]
                 tracing::debug!("Removed H2 connection for key: {:?}", key_str);
             }
 
             if self.reusable {
+                // The key is moved into the pool, so it is checked ahead.
+                if conns.listener.is_some()
+                    && matches!(conns.mapping.get(&key), Some(q) if q.len() > conns.max_idle)
+                {
+                    conns.on_closed(&key, CloseReason::PoolFull);
+                }
                 let queue = conns
                     .mapping
                     .entry(key)
@@ -246,22 +637,58 @@ where
     IO: AsyncWriteRent + AsyncReadRent + Split,
 {
     pub fn get(&self, key: &K) -> Option<PooledConnection<K, IO>> {
+        self.checkout(key, &[])
+    }
+
+    /// Like `get`, but the connections of the given ids are skipped. A new
+    /// connection is established with the dialer if no other is idle, None
+    /// if there is no dialer.
+    pub(crate) async fn get_excluding(
+        &self,
+        key: K,
+        excluded: &[u64],
+    ) -> Option<crate::Result<PooledConnection<K, IO>>> {
+        if let Some(conn) = self.checkout(&key, excluded) {
+            return Some(Ok(conn));
+        }
+        let dialer = unsafe { &*self.conns.get() }.dialer.clone()?;
+        Some(
+            dialer(key.to_owned())
+                .await
+                .map(|(conn, info)| self.link(key, conn, info)),
+        )
+    }
+
+    fn checkout(&self, key: &K, excluded: &[u64]) -> Option<PooledConnection<K, IO>> {
         let conns = unsafe { &mut *self.conns.get() };
+        if conns.closed {
+            return None;
+        }
 
         match conns.mapping.get_mut(key) {
             Some(v) => {
-                match v.pop_front() {
+                let idle = v
+                    .iter()
+                    .position(|idle| !excluded.contains(&idle.info.id()))
+                    .and_then(|idx| v.remove(idx));
+                match idle {
                     Some(idle) => {
-                        let (checkout_conn, readd_conn) = idle.reserve();
+                        let (checkout_conn, info, readd_conn) = idle.reserve();
 
                         #[cfg(feature = "logging")]
                         tracing::debug!("connection got from pool for key: {:?} ", key.to_string());
+                        if let Some(listener) = conns.listener.as_ref() {
+                            listener.on_connection_reused(key);
+                        }
                         let mut pooled_conn = PooledConnection {
                             key: Some(key.to_owned()),
                             conn: Some(checkout_conn),
+                            info,
                             pool: Rc::downgrade(&self.conns),
                             reusable: true,
                             remove_h2: false,
+                            reused: true,
+                            _drain: conns.drain_tx.clone(),
                         };
 
                         // Add back the H2Connection so other request's can clone it
@@ -283,16 +710,73 @@ where
         }
     }
 
-    pub fn link(&self, key: K, conn: HttpConnection<IO>) -> PooledConnection<K, IO> {
+    /// Take a shared http2 connection of another key for the key, if it has
+    /// no idle connection of its own. `covers` tells if the connection of
+    /// the other key may serve the key too.
+    pub(crate) fn coalesce(
+        &self,
+        key: &K,
+        mut covers: impl FnMut(&K, &ConnectionInfo) -> bool,
+    ) -> Option<PooledConnection<K, IO>> {
+        let conns = unsafe { &mut *self.conns.get() };
+        if conns.closed || conns.mapping.get(key).is_some_and(|v| !v.is_empty()) {
+            return None;
+        }
+        let (owner, idx) = conns.mapping.iter().find_map(|(owner, v)| {
+            if owner == key {
+                return None;
+            }
+            v.iter()
+                .position(|idle| idle.conn.is_http2() && covers(owner, &idle.info))
+                .map(|idx| (owner.to_owned(), idx))
+        })?;
+        let v = conns.mapping.get_mut(&owner)?;
+        let (checkout_conn, info, readd_conn) = v.remove(idx)?.reserve();
+        if let Some(idle) = readd_conn {
+            v.push_back(idle);
+        }
+
+        #[cfg(feature = "logging")]
+        tracing::debug!(
+            "connection of {:?} coalesced for key: {:?}",
+            owner.to_string(),
+            key.to_string()
+        );
+        if let Some(listener) = conns.listener.as_ref() {
+            listener.on_connection_reused(&owner);
+        }
+        // The connection is kept under the key of its own.
+        Some(PooledConnection {
+            key: Some(owner),
+            conn: Some(checkout_conn),
+            info,
+            pool: Rc::downgrade(&self.conns),
+            reusable: false,
+            remove_h2: false,
+            reused: true,
+            _drain: conns.drain_tx.clone(),
+        })
+    }
+
+    pub fn link(
+        &self,
+        key: K,
+        conn: HttpConnection<IO>,
+        info: ConnectionInfo,
+    ) -> PooledConnection<K, IO> {
         #[cfg(feature = "logging")]
         tracing::debug!("linked new connection to the pool");
 
+        let conns = unsafe { &*self.conns.get() };
         PooledConnection {
             key: Some(key),
             conn: Some(conn),
+            info,
             pool: Rc::downgrade(&self.conns),
             reusable: true,
             remove_h2: false,
+            reused: false,
+            _drain: conns.drain_tx.clone(),
         }
     }
 }
@@ -329,6 +813,9 @@ impl<K, IO: AsyncWriteRent> Future for IdleTask<K, IO> {
                 inner_mut.clear_expired(this.idle_dur);
                 #[cfg(feature = "logging")]
                 tracing::debug!("pool clear expired");
+                if let Some(refill) = inner_mut.refill.clone() {
+                    refill.refill_all();
+                }
                 continue;
             }
             #[cfg(feature = "logging")]
@@ -337,3 +824,338 @@ impl<K, IO: AsyncWriteRent> Future for IdleTask<K, IO> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use monoio::{
+        io::{AsyncReadRent, AsyncWriteRentExt},
+        net::{TcpListener, TcpStream},
+    };
+    use monoio_http::{common::body::Body, h1::codec::ClientCodec, h2};
+
+    use super::{CloseReason, ConnectionListener, ConnectionPool, Dialer, HttpConnection};
+    use crate::{Builder, ConnectionInfo, ErrorKind, Key, Timings};
+
+    fn deadline() -> monoio::time::Instant {
+        monoio::time::Instant::now() + Duration::from_secs(5)
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn shutdown_http1() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let closed = Rc::new(Cell::new(false));
+        let server_closed = closed.clone();
+        monoio::spawn(async move {
+            let (mut io, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::with_capacity(4096);
+            loop {
+                let (r, b) = io.read(buf).await;
+                buf = b;
+                if !matches!(r, Ok(n) if n > 0) {
+                    server_closed.set(true);
+                    return;
+                }
+                buf.clear();
+                let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                let (r, _) = io.write_all(response).await;
+                r.unwrap();
+            }
+        });
+
+        let client = Builder::new().build();
+        let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "ok");
+        // Wait for the body task to return the connection.
+        monoio::time::sleep(Duration::from_millis(10)).await;
+
+        assert!(client.shutdown(deadline()).await);
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        assert!(closed.get());
+
+        let err = match client.get(format!("http://{addr}/")).send().await {
+            Ok(_) => panic!("sent after shutdown"),
+            Err(e) => e,
+        };
+        assert!(matches!(err.kind(), ErrorKind::Shutdown), "{err:?}");
+        assert!(!err.request_may_have_been_sent());
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn shutdown_http2() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let closed = Rc::new(Cell::new(false));
+        let server_closed = closed.clone();
+        monoio::spawn(async move {
+            let (io, _) = listener.accept().await.unwrap();
+            let mut conn = h2::server::handshake(io).await.unwrap();
+            while let Some(Ok((_, mut respond))) = conn.accept().await {
+                monoio::spawn(async move {
+                    let mut send = respond
+                        .send_response(http::Response::new(()), false)
+                        .unwrap();
+                    monoio::time::sleep(Duration::from_millis(50)).await;
+                    send.send_data("ok".into(), true).unwrap();
+                });
+            }
+            server_closed.set(true);
+        });
+
+        let client = Builder::new().http2_client().build();
+        let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+
+        // The in-flight response is finished before the connection closes.
+        let body_done = Rc::new(Cell::new(false));
+        let done = body_done.clone();
+        monoio::spawn(async move {
+            assert_eq!(resp.bytes().await.unwrap(), "ok");
+            done.set(true);
+        });
+        assert!(client.shutdown(deadline()).await);
+        assert!(body_done.get());
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        assert!(closed.get());
+    }
+
+    // Count accepted connections, `/close` closes the connection.
+    async fn serve_counted(listener: TcpListener, accepted: Rc<Cell<usize>>) {
+        loop {
+            let (mut io, _) = listener.accept().await.unwrap();
+            accepted.set(accepted.get() + 1);
+            monoio::spawn(async move {
+                let mut buf = Vec::with_capacity(4096);
+                loop {
+                    let (r, b) = io.read(buf).await;
+                    buf = b;
+                    if !matches!(r, Ok(n) if n > 0) {
+                        return;
+                    }
+                    let close = buf.starts_with(b"GET /close");
+                    let response = if close {
+                        "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 2\r\n\r\nok"
+                    } else {
+                        "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok"
+                    };
+                    buf.clear();
+                    let (r, _) = io.write_all(response).await;
+                    r.unwrap();
+                    if close {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn preconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        monoio::spawn(serve_counted(listener, accepted.clone()));
+
+        let client = Builder::new().build();
+        client
+            .preconnect(format!("http://{addr}/"), 3)
+            .await
+            .unwrap();
+        // Wait for the server to accept them.
+        monoio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(accepted.get(), 3);
+        for _ in 0..3 {
+            let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+            assert_eq!(resp.bytes().await.unwrap(), "ok");
+        }
+        assert_eq!(accepted.get(), 3);
+
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let err = client
+            .preconnect(format!("http://{closed}/"), 2)
+            .await
+            .unwrap_err();
+        assert!(err.is_connect(), "{err:?}");
+    }
+
+    #[derive(Default)]
+    struct Closed(std::cell::RefCell<Vec<CloseReason>>);
+
+    impl ConnectionListener<String> for Closed {
+        fn on_connection_closed(&self, _key: &String, reason: CloseReason) {
+            self.0.borrow_mut().push(reason);
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn preconnect_pool_full() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve_counted(listener, Default::default()));
+
+        #[cfg(feature = "time")]
+        let pool = ConnectionPool::<String, TcpStream>::new(None, Some(1));
+        #[cfg(not(feature = "time"))]
+        let pool = ConnectionPool::<String, TcpStream>::new(Some(1));
+        let closed = Rc::new(Closed::default());
+        pool.set_listener(closed.clone());
+        let dialer: Dialer<String, TcpStream> = Rc::new(move |_| {
+            Box::pin(async move {
+                let io = TcpStream::connect(addr).await?;
+                let info = ConnectionInfo::new(None, None);
+                Ok((HttpConnection::H1(ClientCodec::new(io)), info))
+            })
+        });
+        pool.set_dialer(dialer, Default::default());
+
+        // The connections not fitting in the pool are reported as closed.
+        pool.preconnect("key".to_string(), 2).await.unwrap();
+        assert_eq!(*closed.0.borrow(), [CloseReason::PoolFull]);
+        pool.shutdown().await;
+        assert_eq!(
+            *closed.0.borrow(),
+            [CloseReason::PoolFull, CloseReason::Shutdown]
+        );
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn connection_info() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve_counted(listener, Default::default()));
+
+        let client = Builder::new().build();
+        let mut local = None;
+        for reused in [false, true] {
+            let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+            let info = resp.extensions().get::<ConnectionInfo>().unwrap().clone();
+            assert_eq!(resp.bytes().await.unwrap(), "ok");
+            assert_eq!(info.is_reused(), reused);
+            assert_eq!(info.peer_addr(), Some(addr));
+            assert_eq!(info.version(), http::Version::HTTP_11);
+            assert!(info.tls().is_none());
+            // The same connection serves both requests.
+            assert!(info.local_addr().is_some());
+            assert_eq!(*local.get_or_insert(info.local_addr()), info.local_addr());
+            monoio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn timings() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut io, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::with_capacity(4096);
+            loop {
+                let (r, b) = io.read(buf).await;
+                buf = b;
+                if !matches!(r, Ok(n) if n > 0) {
+                    return;
+                }
+                buf.clear();
+                monoio::time::sleep(Duration::from_millis(20)).await;
+                let head = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n";
+                io.write_all(head).await.0.unwrap();
+                monoio::time::sleep(Duration::from_millis(20)).await;
+                io.write_all("2\r\nok\r\n0\r\n\r\n").await.0.unwrap();
+            }
+        });
+
+        let client = Builder::new().build();
+        for reused in [false, true] {
+            let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+            let timings = resp.extensions().get::<Timings>().unwrap().clone();
+            assert!(timings.first_byte() >= Duration::from_millis(20));
+            assert_eq!(timings.tls_handshake(), Duration::ZERO);
+            if reused {
+                assert_eq!(timings.dns(), Duration::ZERO);
+                assert_eq!(timings.connect(), Duration::ZERO);
+            } else {
+                assert!(timings.connect() > Duration::ZERO);
+            }
+            assert_eq!(timings.body(), None);
+            assert_eq!(resp.bytes().await.unwrap(), "ok");
+            assert!(timings.body().unwrap() >= Duration::from_millis(20));
+            monoio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn timings_h2() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (io, _) = listener.accept().await.unwrap();
+            let mut conn = h2::server::handshake(io).await.unwrap();
+            while let Some(Ok((_, mut respond))) = conn.accept().await {
+                monoio::spawn(async move {
+                    let mut send = respond
+                        .send_response(http::Response::new(()), false)
+                        .unwrap();
+                    monoio::time::sleep(Duration::from_millis(20)).await;
+                    send.send_data("ok".into(), true).unwrap();
+                });
+            }
+        });
+
+        // Recorded even if the body is not read by `bytes`.
+        let client = Builder::new().http2_client().build();
+        let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+        let timings = resp.extensions().get::<Timings>().unwrap().clone();
+        assert_eq!(timings.body(), None);
+        let mut body = resp.raw_body();
+        assert_eq!(body.next_data().await.unwrap().unwrap(), "ok");
+        assert!(body.next_data().await.is_none());
+        assert!(timings.body().is_some());
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn min_idle() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        monoio::spawn(serve_counted(listener, accepted.clone()));
+
+        let uri: http::Uri = format!("http://{addr}/").parse().unwrap();
+        let client = Builder::new()
+            .min_idle_connections(Key::try_from(&uri).unwrap(), 2)
+            .build();
+        monoio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(accepted.get(), 2);
+
+        // The closed connection is redialed.
+        let resp = client
+            .get(format!("http://{addr}/close"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "ok");
+        monoio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(accepted.get(), 3);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn shutdown_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            // Accept and never respond.
+            let (_io, _) = listener.accept().await.unwrap();
+            monoio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let client = Builder::new().build();
+        let pending = client.get(format!("http://{addr}/")).send();
+        monoio::spawn(pending);
+        monoio::time::sleep(Duration::from_millis(10)).await;
+
+        let deadline = monoio::time::Instant::now() + Duration::from_millis(50);
+        assert!(!client.shutdown(deadline).await);
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use http::{HeaderMap, StatusCode};
+use smol_str::SmolStr;
+
+use super::{date::http_date, key::Key};
+
+/// Token bucket of the requests to a key, set with `Builder::rate_limit`.
+///
+/// Requests over the limit are delayed until a token is available, or
+/// rejected with `ErrorKind::RateLimited` if the delay is longer than
+/// `max_delay`.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    // Tokens per second.
+    rate: f64,
+    burst: f64,
+    max_delay: Option<Duration>,
+    adaptive: bool,
+}
+
+impl RateLimit {
+    /// Allow `requests` per `period`, the burst is `requests` too.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self {
+            rate: requests as f64 / period.as_secs_f64().max(f64::EPSILON),
+            burst: requests.max(1) as f64,
+            max_delay: None,
+            adaptive: false,
+        }
+    }
+
+    /// Requests which can be sent at once after being idle.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1) as f64;
+        self
+    }
+
+    /// Reject requests which would be delayed longer, they are delayed as
+    /// long as needed by default.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = Some(delay);
+        self
+    }
+
+    /// Reject requests over the limit at once.
+    pub fn reject(self) -> Self {
+        self.max_delay(Duration::ZERO)
+    }
+
+    /// Also hold requests back as told by the server, with `429` and
+    /// `Retry-After`, or `RateLimit-Remaining: 0` and `RateLimit-Reset`
+    /// (or the `X-RateLimit-*` ones).
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+}
+
+/// Which keys a `RateLimit` applies to, each matching key has its own
+/// bucket.
+///
+/// Converted from a `Key`, or from a host where `*.example.com` matches the
+/// subdomains of `example.com` and `*` matches every host.
+#[derive(Clone, Debug)]
+pub enum HostPattern {
+    /// The host and port of the key.
+    Key(Key),
+    Host(SmolStr),
+    /// Hosts ending with the suffix, e.g. `.example.com`.
+    Suffix(SmolStr),
+    Any,
+}
+
+impl HostPattern {
+    fn matches(&self, key: &Key) -> bool {
+        match self {
+            HostPattern::Key(k) => k.host == key.host && k.port == key.port,
+            HostPattern::Host(host) => host.eq_ignore_ascii_case(&key.host),
+            HostPattern::Suffix(suffix) => {
+                key.host.len() > suffix.len()
+                    && key.host[key.host.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            }
+            HostPattern::Any => true,
+        }
+    }
+}
+
+impl From<Key> for HostPattern {
+    fn from(key: Key) -> Self {
+        HostPattern::Key(key)
+    }
+}
+
+impl From<&str> for HostPattern {
+    fn from(pattern: &str) -> Self {
+        match pattern {
+            "*" => HostPattern::Any,
+            _ => match pattern.strip_prefix('*') {
+                Some(suffix) => HostPattern::Suffix(suffix.into()),
+                None => HostPattern::Host(pattern.into()),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    // Set by the server.
+    blocked_until: Option<Instant>,
+}
+
+/// Buckets of the keys of a client.
+#[derive(Debug)]
+pub(crate) struct RateLimits {
+    rules: Vec<(HostPattern, RateLimit)>,
+    buckets: RefCell<HashMap<Key, Bucket>>,
+}
+
+impl RateLimits {
+    pub(crate) fn new(rules: Vec<(HostPattern, RateLimit)>) -> Option<Self> {
+        if rules.is_empty() {
+            return None;
+        }
+        Some(Self {
+            rules,
+            buckets: Default::default(),
+        })
+    }
+
+    /// The first matching rule applies.
+    fn rule(&self, key: &Key) -> Option<&RateLimit> {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| pattern.matches(key))
+            .map(|(_, limit)| limit)
+    }
+
+    /// Take a token, returns how long to wait before sending. Returns
+    /// Err with the delay if the request is rejected.
+    pub(crate) fn acquire(&self, key: &Key) -> Result<Duration, Duration> {
+        let limit = match self.rule(key) {
+            Some(limit) => limit,
+            None => return Ok(Duration::ZERO),
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.borrow_mut();
+        let bucket = buckets.entry(key.clone()).or_insert_with(|| Bucket {
+            tokens: limit.burst,
+            refilled_at: now,
+            blocked_until: None,
+        });
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.rate).min(limit.burst);
+        bucket.refilled_at = now;
+
+        // Tokens go negative to queue the delayed requests.
+        let mut delay = match bucket.tokens >= 1.0 {
+            true => Duration::ZERO,
+            false => Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate.max(f64::EPSILON)),
+        };
+        if let Some(until) = bucket.blocked_until {
+            delay = delay.max(until.saturating_duration_since(now));
+        }
+        if matches!(limit.max_delay, Some(max) if delay > max) {
+            return Err(delay);
+        }
+        bucket.tokens -= 1.0;
+        Ok(delay)
+    }
+
+    /// Hold the bucket back as told by the response.
+    pub(crate) fn observe(&self, key: &Key, status: StatusCode, headers: &HeaderMap) {
+        if !matches!(self.rule(key), Some(limit) if limit.adaptive) {
+            return;
+        }
+        let wait = match status {
+            StatusCode::TOO_MANY_REQUESTS => {
+                header(headers, &["retry-after"]).and_then(retry_after)
+            }
+            _ => None,
+        }
+        .or_else(|| {
+            let remaining = header(headers, &["ratelimit-remaining", "x-ratelimit-remaining"])?;
+            if remaining.trim().parse::<u64>().ok()? > 0 {
+                return None;
+            }
+            header(headers, &["ratelimit-reset", "x-ratelimit-reset"]).and_then(reset)
+        });
+        let wait = match wait {
+            Some(wait) => wait,
+            None => return,
+        };
+        if let Some(bucket) = self.buckets.borrow_mut().get_mut(key) {
+            let until = Instant::now() + wait;
+            bucket.blocked_until = Some(bucket.blocked_until.map_or(until, |u| u.max(until)));
+        }
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, names: &[&str]) -> Option<&'a str> {
+    names
+        .iter()
+        .find_map(|name| headers.get(*name))
+        .and_then(|v| v.to_str().ok())
+}
+
+/// Delay seconds or an IMF-fixdate.
+fn retry_after(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = http_date(value.trim())?;
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Delay seconds, large values are taken as unix timestamps as some
+/// servers send in `X-RateLimit-Reset`.
+fn reset(value: &str) -> Option<Duration> {
+    let secs = value.trim().parse::<u64>().ok()?;
+    if secs < 1_000_000_000 {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(secs);
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::{
+        io::{sink::SinkExt, stream::Stream},
+        net::TcpListener,
+    };
+    use monoio_http::{
+        common::body::{FixedBody, HttpBody},
+        h1::codec::ServerCodec,
+    };
+
+    use super::*;
+    use crate::Builder;
+
+    fn key(host: &str) -> Key {
+        Key::try_from(&format!("http://{host}/").parse::<http::Uri>().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn patterns() {
+        let cases = [
+            ("api.example.com", "API.example.com", true),
+            ("api.example.com", "example.com", false),
+            ("*.example.com", "api.example.com", true),
+            ("*.example.com", "example.com", false),
+            ("*", "example.com", true),
+        ];
+        for (pattern, host, matches) in cases {
+            assert_eq!(HostPattern::from(pattern).matches(&key(host)), matches);
+        }
+        assert!(HostPattern::from(key("a:8080")).matches(&key("a:8080")));
+        assert!(!HostPattern::from(key("a:8080")).matches(&key("a")));
+    }
+
+    #[test]
+    fn bucket() {
+        let limits = RateLimits::new(vec![
+            (
+                "a".into(),
+                RateLimit::new(10, Duration::from_secs(1)).burst(2),
+            ),
+            (
+                "b".into(),
+                RateLimit::new(10, Duration::from_secs(1)).reject(),
+            ),
+        ])
+        .unwrap();
+        let (a, b) = (key("a"), key("b"));
+        assert_eq!(limits.acquire(&a), Ok(Duration::ZERO));
+        assert_eq!(limits.acquire(&a), Ok(Duration::ZERO));
+        let delay = limits.acquire(&a).unwrap();
+        assert!(delay > Duration::from_millis(90) && delay <= Duration::from_millis(100));
+        // Queued behind the delayed one.
+        assert!(limits.acquire(&a).unwrap() > Duration::from_millis(190));
+
+        for _ in 0..10 {
+            assert_eq!(limits.acquire(&b), Ok(Duration::ZERO));
+        }
+        assert!(limits.acquire(&b).is_err());
+        assert_eq!(limits.acquire(&key("c")), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn adaptive() {
+        let limit = RateLimit::new(100, Duration::from_secs(1)).adaptive(true);
+        let limits = RateLimits::new(vec![("*".into(), limit)]).unwrap();
+        let (a, b) = (key("a"), key("b"));
+        limits.acquire(&a).unwrap();
+        limits.acquire(&b).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "3".parse().unwrap());
+        limits.observe(&a, StatusCode::TOO_MANY_REQUESTS, &headers);
+        let delay = limits.acquire(&a).unwrap();
+        assert!(delay > Duration::from_secs(2) && delay <= Duration::from_secs(3));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", "0".parse().unwrap());
+        headers.insert("x-ratelimit-reset", "5".parse().unwrap());
+        limits.observe(&b, StatusCode::OK, &headers);
+        assert!(limits.acquire(&b).unwrap() > Duration::from_secs(4));
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn rate_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            loop {
+                let (io, _) = listener.accept().await.unwrap();
+                monoio::spawn(async move {
+                    let mut codec = ServerCodec::new(io);
+                    while let Some(Ok(_)) = codec.next().await {
+                        let resp = http::Response::new(HttpBody::fixed_body(None));
+                        if codec.send_and_flush(resp).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        let uri = format!("http://{addr}/");
+        let period = Duration::from_millis(50);
+
+        let client = Builder::new()
+            .rate_limit("127.0.0.1", RateLimit::new(1, period).reject())
+            .build();
+        client.get(&uri).send().await.unwrap();
+        let err = match client.get(&uri).send().await {
+            Ok(_) => panic!("over the limit"),
+            Err(e) => e,
+        };
+        assert!(matches!(err.kind(), crate::ErrorKind::RateLimited(_)));
+        assert!(!err.request_may_have_been_sent());
+
+        let client = Builder::new()
+            .rate_limit("127.0.0.1", RateLimit::new(1, period))
+            .build();
+        let start = Instant::now();
+        for _ in 0..3 {
+            client.get(&uri).send().await.unwrap();
+        }
+        assert!(start.elapsed() >= period * 2);
+    }
+}
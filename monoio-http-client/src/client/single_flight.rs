@@ -0,0 +1,466 @@
+use std::{cell::RefCell, collections::HashMap, future::Future, rc::Rc};
+
+use bytes::Bytes;
+use http::{header, header::HeaderName, HeaderMap, HeaderValue, Method, StatusCode, Version};
+use local_sync::oneshot;
+use monoio_http::common::{
+    body::{BodyExt, FixedBody, HttpBody},
+    request::Request,
+    response::Response,
+};
+
+use super::{info::Timings, Client};
+use crate::{error::Stage, ErrorKind};
+
+/// Settings of request coalescing, set with `Builder::single_flight`.
+///
+/// Concurrent GET requests with the same uri and values of the selected
+/// headers share one request to the server. If others wait for it once the
+/// response head arrives, the body is buffered and handed to every waiter,
+/// otherwise it is streamed as usual. An error is handed to the waiters as
+/// `ErrorKind::Coalesced`. The headers are `Accept`, `Accept-Encoding`,
+/// `Accept-Language`, `Authorization` and `Cookie` by default.
+///
+/// Requests with `Range` or `If-Range` are not coalesced, and a request can
+/// opt out with `ClientRequest::single_flight`.
+#[derive(Clone, Debug)]
+pub struct SingleFlight {
+    headers: Vec<HeaderName>,
+}
+
+impl Default for SingleFlight {
+    fn default() -> Self {
+        Self {
+            headers: vec![
+                header::ACCEPT,
+                header::ACCEPT_ENCODING,
+                header::ACCEPT_LANGUAGE,
+                header::AUTHORIZATION,
+                header::COOKIE,
+            ],
+        }
+    }
+}
+
+impl SingleFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only coalesce requests with the same values of the header too.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        if !self.headers.contains(&name) {
+            self.headers.push(name);
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct FlightKey {
+    uri: String,
+    headers: Vec<Vec<HeaderValue>>,
+}
+
+/// The buffered response of a flight.
+struct Shared {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Shared {
+    fn response(&self, body_limit: Option<usize>) -> crate::Result<Response<HttpBody>> {
+        if let Some(limit) = body_limit.filter(|limit| self.body.len() > *limit) {
+            return Err(crate::Error::new(
+                ErrorKind::BodyTooLarge(limit),
+                Stage::Body,
+            ));
+        }
+        let mut response = Response::new(HttpBody::fixed_body(Some(self.body.clone())));
+        *response.status_mut() = self.status;
+        *response.version_mut() = self.version;
+        *response.headers_mut() = self.headers.clone();
+        Ok(response)
+    }
+}
+
+type Waiter = oneshot::Sender<crate::Result<Rc<Shared>>>;
+
+/// Requests in flight of a client.
+pub(crate) struct Flights {
+    config: SingleFlight,
+    flights: RefCell<HashMap<FlightKey, Vec<Waiter>>>,
+}
+
+impl std::fmt::Debug for Flights {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Flights")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl Flights {
+    pub(crate) fn new(config: SingleFlight) -> Self {
+        Self {
+            config,
+            flights: Default::default(),
+        }
+    }
+
+    fn key(&self, headers: &HeaderMap, uri: &http::Uri) -> FlightKey {
+        FlightKey {
+            uri: uri.to_string(),
+            headers: self
+                .config
+                .headers
+                .iter()
+                .map(|name| headers.get_all(name).iter().cloned().collect())
+                .collect(),
+        }
+    }
+
+    /// Returns a receiver if a request with the key is in flight, or starts
+    /// a flight.
+    fn join(&self, key: &FlightKey) -> Option<oneshot::Receiver<crate::Result<Rc<Shared>>>> {
+        let mut flights = self.flights.borrow_mut();
+        match flights.get_mut(key) {
+            Some(waiters) => {
+                let (tx, rx) = oneshot::channel();
+                waiters.push(tx);
+                Some(rx)
+            }
+            None => {
+                flights.insert(key.clone(), Vec::new());
+                None
+            }
+        }
+    }
+}
+
+/// Ends the flight of the leading request. If it is dropped before, the
+/// waiters are woken up to send the request again.
+struct Flight<'a> {
+    flights: &'a Flights,
+    key: FlightKey,
+}
+
+impl Flight<'_> {
+    /// Returns true if no other request waits for the flight.
+    fn is_alone(&self) -> bool {
+        self.flights
+            .flights
+            .borrow()
+            .get(&self.key)
+            .is_none_or(Vec::is_empty)
+    }
+
+    fn land(self, result: Result<&Rc<Shared>, &crate::Error>) {
+        let waiters = self.flights.flights.borrow_mut().remove(&self.key);
+        for waiter in waiters.into_iter().flatten() {
+            let _ = waiter.send(match result {
+                Ok(shared) => Ok(shared.clone()),
+                Err(e) => Err(e.coalesced()),
+            });
+        }
+    }
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        self.flights.flights.borrow_mut().remove(&self.key);
+    }
+}
+
+impl<C> Client<C> {
+    /// Send the request with `send`, or wait for the response of the same
+    /// request in flight if coalescing is enabled.
+    pub(crate) async fn send_coalesced<B, F, Fut>(
+        &self,
+        req: Request<B>,
+        body_limit: Option<usize>,
+        send: F,
+    ) -> crate::Result<Response<HttpBody>>
+    where
+        F: FnOnce(Request<B>) -> Fut,
+        Fut: Future<Output = crate::Result<Response<HttpBody>>>,
+    {
+        let flights = match self.shared.flights.as_ref() {
+            Some(flights)
+                if req.method() == Method::GET
+                    && !req.headers().contains_key(header::RANGE)
+                    && !req.headers().contains_key(header::IF_RANGE) =>
+            {
+                flights
+            }
+            _ => return send(req).await,
+        };
+        let key = flights.key(req.headers(), req.uri());
+        while let Some(rx) = flights.join(&key) {
+            match rx.await {
+                Ok(Ok(shared)) => return shared.response(body_limit),
+                Ok(Err(e)) => return Err(e),
+                // The leading request is cancelled, take the lead.
+                Err(_) => continue,
+            }
+        }
+
+        let flight = Flight { flights, key };
+        let (parts, body) = match send(req).await {
+            Ok(resp) => resp.into_parts(),
+            Err(e) => {
+                flight.land(Err(&e));
+                return Err(e);
+            }
+        };
+        // Nobody joined, the body is not buffered so it can be streamed.
+        if flight.is_alone() {
+            drop(flight);
+            return Ok(Response::from_parts(parts, body));
+        }
+        let body = match body_limit {
+            Some(limit) => body.bytes_limited(limit).await,
+            None => body.bytes().await,
+        }
+        .map_err(|e| crate::Error::from(e).stage(Stage::Body));
+        if let Some(timings) = parts.extensions.get::<Timings>() {
+            timings.finish_body();
+        }
+        let result = body.map(|body| {
+            Rc::new(Shared {
+                status: parts.status,
+                version: parts.version,
+                headers: parts.headers.clone(),
+                body,
+            })
+        });
+        flight.land(result.as_ref());
+        let body = result?.body.clone();
+        Ok(Response::from_parts(
+            parts,
+            HttpBody::fixed_body(Some(body)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use monoio::{
+        io::{sink::SinkExt, stream::Stream, AsyncReadRent, AsyncWriteRentExt},
+        net::{TcpListener, TcpStream},
+    };
+    use monoio_http::h1::codec::ServerCodec;
+
+    use super::*;
+    use crate::Builder;
+
+    // Respond the Accept and Range headers after a while, or close the
+    // connection for `/close`.
+    async fn serve(listener: TcpListener, served: Rc<Cell<usize>>) {
+        loop {
+            let (io, _) = listener.accept().await.unwrap();
+            let served = served.clone();
+            monoio::spawn(async move {
+                let mut codec = ServerCodec::new(io);
+                while let Some(Ok(req)) = codec.next().await {
+                    served.set(served.get() + 1);
+                    monoio::time::sleep(Duration::from_millis(50)).await;
+                    if req.uri().path() == "/close" {
+                        return;
+                    }
+                    let body = [header::ACCEPT, header::RANGE]
+                        .iter()
+                        .filter_map(|name| req.headers().get(name))
+                        .map(|v| v.to_str().unwrap())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    let resp = http::Response::new(HttpBody::fixed_body(Some(body.into())));
+                    if codec.send_and_flush(resp).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+
+    fn start() -> (String, Rc<Cell<usize>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let served = Rc::new(Cell::new(0));
+        monoio::spawn(serve(listener, served.clone()));
+        (uri, served)
+    }
+
+    // Send GET requests with the headers at once, returns the bodies.
+    async fn send_all(
+        client: &Client,
+        uri: &str,
+        headers: &[(HeaderName, &'static str)],
+    ) -> Vec<Bytes> {
+        let tasks: Vec<_> = headers
+            .iter()
+            .cloned()
+            .map(|(name, value)| {
+                let (client, uri) = (client.clone(), uri.to_string());
+                monoio::spawn(async move {
+                    let resp = client.get(uri).header(name, value).send().await.unwrap();
+                    resp.bytes().await.unwrap()
+                })
+            })
+            .collect();
+        let mut bodies = Vec::new();
+        for task in tasks {
+            bodies.push(task.await);
+        }
+        bodies
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn single_flight() {
+        let (uri, served) = start();
+        let client = Builder::new().single_flight(SingleFlight::new()).build();
+
+        let bodies = send_all(&client, &format!("{uri}/"), &vec![(header::ACCEPT, "a"); 3]).await;
+        assert_eq!(bodies, ["a"; 3]);
+        assert_eq!(served.get(), 1);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn key_headers() {
+        let (uri, served) = start();
+        let client = Builder::new().single_flight(SingleFlight::new()).build();
+
+        let accepts = [(header::ACCEPT, "a"), (header::ACCEPT, "b")];
+        let bodies = send_all(&client, &format!("{uri}/"), &accepts).await;
+        assert_eq!(bodies, ["a", "b"]);
+        assert_eq!(served.get(), 2);
+
+        // A header added to the key.
+        let client = Builder::new()
+            .single_flight(SingleFlight::new().header(HeaderName::from_static("x-tenant")))
+            .build();
+        let tenants = [
+            (HeaderName::from_static("x-tenant"), "1"),
+            (HeaderName::from_static("x-tenant"), "2"),
+        ];
+        send_all(&client, &format!("{uri}/"), &tenants).await;
+        assert_eq!(served.get(), 4);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn ranges() {
+        let (uri, served) = start();
+        let client = Builder::new().single_flight(SingleFlight::new()).build();
+
+        let ranges = [(header::RANGE, "bytes=0-9"), (header::RANGE, "bytes=0-9")];
+        let bodies = send_all(&client, &format!("{uri}/"), &ranges).await;
+        assert_eq!(bodies, ["bytes=0-9"; 2]);
+        assert_eq!(served.get(), 2);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn error_fan_out() {
+        let (uri, served) = start();
+        let client = Builder::new().single_flight(SingleFlight::new()).build();
+
+        let tasks: Vec<_> = (0..3)
+            .map(|_| {
+                let (client, uri) = (client.clone(), uri.clone());
+                monoio::spawn(async move {
+                    match client.get(format!("{uri}/close")).send().await {
+                        Ok(_) => panic!("connection is closed"),
+                        Err(e) => matches!(e.kind(), ErrorKind::Coalesced(_)),
+                    }
+                })
+            })
+            .collect();
+        let mut coalesced = 0;
+        for task in tasks {
+            coalesced += task.await as usize;
+        }
+        assert_eq!(coalesced, 2);
+        assert_eq!(served.get(), 1);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn leader_cancelled() {
+        let (uri, served) = start();
+        let client = Builder::new().single_flight(SingleFlight::new()).build();
+
+        // A waiter takes the lead if the leading request is cancelled.
+        let waiter = {
+            let (client, uri) = (client.clone(), uri.clone());
+            monoio::spawn(async move {
+                monoio::time::sleep(Duration::from_millis(10)).await;
+                client.get(format!("{uri}/")).send().await.unwrap()
+            })
+        };
+        let leader = client.get(format!("{uri}/")).send();
+        assert!(monoio::time::timeout(Duration::from_millis(20), leader)
+            .await
+            .is_err());
+        assert_eq!(waiter.await.status(), StatusCode::OK);
+        assert_eq!(served.get(), 2);
+        assert!(client
+            .shared
+            .flights
+            .as_ref()
+            .unwrap()
+            .flights
+            .borrow()
+            .is_empty());
+    }
+
+    // Writes the head and the first chunk of the body, the body never ends.
+    async fn serve_stream(listener: TcpListener, content_type: &'static str, chunk: &'static str) {
+        loop {
+            let (mut io, _): (TcpStream, _) = listener.accept().await.unwrap();
+            monoio::spawn(async move {
+                let (res, _) = io.read(Vec::with_capacity(4096)).await;
+                if !matches!(res, Ok(n) if n > 0) {
+                    return;
+                }
+                let resp = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: {content_type}\r\ntransfer-encoding: \
+                     chunked\r\n\r\n{:x}\r\n{chunk}\r\n",
+                    chunk.len()
+                );
+                io.write_all(resp.into_bytes()).await.0.unwrap();
+                let _ = io.read(Vec::with_capacity(1)).await;
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn streams() {
+        let client = Builder::new().single_flight(SingleFlight::new()).build();
+        let timeout = Duration::from_secs(1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap());
+        monoio::spawn(serve_stream(listener, "text/event-stream", "data: a\n\n"));
+        let mut events = client.event_source(uri.parse().unwrap());
+        let event = monoio::time::timeout(timeout, events.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.data, "a");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap());
+        monoio::spawn(serve_stream(listener, "application/x-ndjson", "[1]\n"));
+        let resp = client.get(uri).send().await.unwrap();
+        let mut values = resp.json_stream::<Vec<u32>>();
+        let value = monoio::time::timeout(timeout, values.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, [1]);
+    }
+}
@@ -13,7 +13,10 @@ use monoio::{
 use service_async::Param;
 use smol_str::SmolStr;
 
-use super::connector::{TcpConnector, TlsConnector, TlsStream, UnixConnector};
+use super::{
+    connector::{SocketOptions, TcpConnector, TlsConnector, TlsStream, UnixConnector},
+    info::{ConnectionInfo, TransportInfo},
+};
 use crate::Connector;
 
 // TODO: make its PathBuf and SmolStr to ref
@@ -57,6 +60,28 @@ pub struct UnifiedTransportConnector {
     unix_tls: TlsConnector<UnixConnector>,
 }
 
+impl UnifiedTransportConnector {
+    /// Apply the socket options to tcp connections, with or without tls.
+    pub fn new(options: SocketOptions) -> Self {
+        Self {
+            raw_tcp: TcpConnector::new(options.clone()),
+            tcp_tls: TlsConnector::with_inner_connector(TcpConnector::new(options)),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "native-tls")))]
+impl UnifiedTransportConnector {
+    /// Establish tls with the config, e.g. to trust a test certificate.
+    pub(crate) fn with_tls_config(config: std::sync::Arc<rustls::ClientConfig>) -> Self {
+        Self {
+            tcp_tls: TlsConnector::with_tls_config(config),
+            ..Default::default()
+        }
+    }
+}
+
 pub enum UnifiedTransportConnection {
     Tcp(TcpStream),
     Unix(UnixStream),
@@ -115,6 +140,17 @@ where
     }
 }
 
+impl TransportInfo for UnifiedTransportConnection {
+    fn connection_info(&self) -> ConnectionInfo {
+        match self {
+            UnifiedTransportConnection::Tcp(s) => s.connection_info(),
+            UnifiedTransportConnection::Unix(s) => s.connection_info(),
+            UnifiedTransportConnection::TcpTls(s) => s.connection_info(),
+            UnifiedTransportConnection::UnixTls(s) => s.connection_info(),
+        }
+    }
+}
+
 impl AsyncReadRent for UnifiedTransportConnection {
     async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
         match self {
@@ -0,0 +1,820 @@
+//! Resumable downloads to disk.
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use bytes::{BufMut, BytesMut};
+use http::{header, header::HeaderName, HeaderMap, HeaderValue, StatusCode, Uri};
+use monoio::fs::{File, OpenOptions};
+use monoio_http::common::body::{Body, HttpBody};
+
+use crate::{
+    client::{
+        connector::PooledConnector, key::Key, pool::PooledConnection,
+        unified::UnifiedTransportConnector, Client,
+    },
+    error::Stage,
+    request::ClientRequest,
+    response::ClientResponse,
+    unified::UnifiedTransportConnection,
+    Connector, ErrorKind,
+};
+
+const DEFAULT_CHUNK_SIZE: u64 = 8 << 20;
+
+/// Downloads the response body of a GET request to a file, created with
+/// `Client::download`.
+///
+/// The body is written to `<path>.part`, which is renamed to `path` once its
+/// length is verified. If the server sends `Accept-Ranges: bytes` with a
+/// strong `ETag` or a `Last-Modified`, the progress is kept in
+/// `<path>.part.meta`, and a failed download resumes with `Range` and
+/// `If-Range` the next time it is sent.
+///
+/// The body is written in pieces as it arrives, it is never buffered whole.
+pub struct Download<C = UnifiedTransportConnector> {
+    client: Client<C>,
+    uri: Uri,
+    path: PathBuf,
+    headers: HeaderMap,
+    ranges: usize,
+    chunk_size: u64,
+}
+
+impl<C> Download<C> {
+    pub fn new(client: Client<C>, uri: Uri, path: impl Into<PathBuf>) -> Self {
+        Self {
+            client,
+            uri,
+            path: path.into(),
+            headers: HeaderMap::new(),
+            ranges: 1,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Add a header to the requests.
+    pub fn header(mut self, key: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(key, value);
+        self
+    }
+
+    /// Fetch the body in byte ranges on up to `n` connections of the pool
+    /// in parallel. The whole body is fetched with a single request if `n` is
+    /// 1, which is the default, or if the server does not support ranges.
+    pub fn ranges(mut self, n: usize) -> Self {
+        self.ranges = n.max(1);
+        self
+    }
+
+    /// Size of the ranges fetched in parallel, 8 MiB by default. A failed
+    /// download resumes from the ranges finished.
+    pub fn chunk_size(mut self, bytes: u64) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+
+    fn request(&self) -> ClientRequest<C> {
+        // The body is streamed to the file.
+        let mut req = self
+            .client
+            .get(self.uri.clone())
+            .max_body_size(None)
+            .single_flight(false);
+        for (key, value) in self.headers.iter() {
+            req = req.header(key, value);
+        }
+        req
+    }
+}
+
+/// What is downloaded to the part file.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Progress {
+    validator: Option<HeaderValue>,
+    length: Option<u64>,
+    // Sorted and merged ranges written, the end is exclusive.
+    done: Vec<(u64, u64)>,
+}
+
+impl Progress {
+    fn encode(&self) -> BytesMut {
+        let mut buf = BytesMut::new();
+        if let Some(validator) = self.validator.as_ref() {
+            buf.put_slice(validator.as_bytes());
+        }
+        buf.put_u8(b'\n');
+        if let Some(length) = self.length {
+            buf.put_slice(length.to_string().as_bytes());
+        }
+        buf.put_u8(b'\n');
+        for (start, end) in self.done.iter() {
+            buf.put_slice(format!("{start}-{end}\n").as_bytes());
+        }
+        buf
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let data = std::str::from_utf8(data).ok()?;
+        let mut lines = data.lines();
+        let validator = match lines.next()? {
+            "" => None,
+            v => Some(HeaderValue::from_str(v).ok()?),
+        };
+        let length = match lines.next()? {
+            "" => None,
+            v => Some(v.parse().ok()?),
+        };
+        let mut progress = Progress {
+            validator,
+            length,
+            done: Vec::new(),
+        };
+        for line in lines {
+            let (start, end) = line.split_once('-')?;
+            progress.finish((start.parse().ok()?, end.parse().ok()?));
+        }
+        Some(progress)
+    }
+
+    fn finish(&mut self, (start, end): (u64, u64)) {
+        if start >= end {
+            return;
+        }
+        self.done.push((start, end));
+        self.done.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.done.len());
+        for (start, end) in self.done.drain(..) {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.done = merged;
+    }
+
+    /// Where a sequential download continues.
+    fn offset(&self) -> u64 {
+        match self.done.first() {
+            Some((0, end)) => *end,
+            _ => 0,
+        }
+    }
+
+    /// The ranges left, in pieces of at most `chunk` bytes.
+    fn pending(&self, length: u64, chunk: u64) -> Vec<(u64, u64)> {
+        let chunk = chunk.max(1);
+        let mut pending = Vec::new();
+        let mut start = 0;
+        while start < length {
+            let end = (start + chunk).min(length);
+            let mut from = start;
+            for (done_start, done_end) in self.done.iter() {
+                if *done_end <= from || *done_start >= end {
+                    continue;
+                }
+                if *done_start > from {
+                    pending.push((from, *done_start));
+                }
+                from = from.max(*done_end);
+            }
+            if from < end {
+                pending.push((from, end));
+            }
+            start = end;
+        }
+        pending
+    }
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+/// A validator usable in `If-Range`, if the server accepts ranges.
+fn validator(headers: &HeaderMap, partial: bool) -> Option<HeaderValue> {
+    let accepts = partial
+        || headers
+            .get(header::ACCEPT_RANGES)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.split(',').any(|v| v.trim().eq_ignore_ascii_case("bytes")));
+    if !accepts {
+        return None;
+    }
+    // Weak ETags can not be used in If-Range.
+    match headers.get(header::ETAG) {
+        Some(etag) if !etag.as_bytes().starts_with(b"W/") => Some(etag.clone()),
+        _ => headers.get(header::LAST_MODIFIED).cloned(),
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// The range and the complete length of `Content-Range: bytes a-b/len`, the
+/// end is exclusive.
+fn content_range(headers: &HeaderMap) -> Option<(u64, u64, Option<u64>)> {
+    let value = headers.get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let (range, length) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+    let length = match length {
+        "*" => None,
+        length => Some(length.parse().ok()?),
+    };
+    (start <= end).then_some((start, end + 1, length))
+}
+
+async fn save(meta: &Path, progress: &Progress) -> crate::Result<()> {
+    monoio::fs::write(meta, progress.encode().freeze())
+        .await
+        .0?;
+    Ok(())
+}
+
+/// Write the body at `offset`, returns the end.
+async fn write_body(file: &File, mut body: HttpBody, mut offset: u64) -> crate::Result<u64> {
+    while let Some(data) = body.next_data().await {
+        let data = data.map_err(|e| crate::Error::from(e).stage(Stage::Body))?;
+        let len = data.len() as u64;
+        file.write_all_at(data, offset).await.0?;
+        offset += len;
+    }
+    Ok(offset)
+}
+
+impl<C: 'static> Download<C>
+where
+    PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+        Key,
+        Connection = PooledConnection<Key, UnifiedTransportConnection>,
+        Error = crate::Error,
+    >,
+{
+    /// Download the file, returns its length.
+    pub async fn send(self) -> crate::Result<u64> {
+        let part = suffixed(&self.path, ".part");
+        let meta = suffixed(&self.path, ".part.meta");
+        let mut progress = match monoio::fs::read(&meta).await {
+            Ok(data) => Progress::decode(&data).unwrap_or_default(),
+            Err(_) => Progress::default(),
+        };
+        if progress.validator.is_none() {
+            progress = Progress::default();
+        }
+        // Written sequentially if no range is recorded.
+        if progress.done.is_empty() {
+            let written = match monoio::fs::metadata(&part).await {
+                Ok(metadata) => metadata.len(),
+                Err(_) => 0,
+            };
+            progress.finish((0, written));
+        }
+
+        let length = match self.ranges {
+            1 => self.fetch(&part, &meta, progress).await?,
+            _ => self.fetch_ranges(&part, &meta, progress).await?,
+        };
+        monoio::fs::rename(&part, &self.path).await?;
+        let _ = monoio::fs::remove_file(&meta).await;
+        Ok(length)
+    }
+
+    async fn fetch(&self, part: &Path, meta: &Path, progress: Progress) -> crate::Result<u64> {
+        let mut req = self.request();
+        let offset = progress.offset();
+        let resuming = offset > 0 && progress.validator.is_some();
+        if let (true, Some(validator)) = (resuming, progress.validator.as_ref()) {
+            req = req
+                .header(header::RANGE, format!("bytes={offset}-"))
+                .header(header::IF_RANGE, validator.clone());
+        }
+        let resp = req.send().await?;
+        match resp.status() {
+            StatusCode::PARTIAL_CONTENT if resuming => match content_range(resp.headers()) {
+                Some((start, _, length)) if start == offset => {
+                    self.write(resp, part, meta, offset, length).await
+                }
+                _ => Err(ErrorKind::DownloadChanged.into()),
+            },
+            // Complete but not renamed.
+            StatusCode::RANGE_NOT_SATISFIABLE if resuming && progress.length == Some(offset) => {
+                Ok(offset)
+            }
+            status if status.is_success() && status != StatusCode::PARTIAL_CONTENT => {
+                let length = content_length(resp.headers());
+                self.write(resp, part, meta, 0, length).await
+            }
+            status => Err(ErrorKind::DownloadStatus(status).into()),
+        }
+    }
+
+    /// Write the response to the part file from `offset`.
+    async fn write(
+        &self,
+        resp: ClientResponse,
+        part: &Path,
+        meta: &Path,
+        offset: u64,
+        length: Option<u64>,
+    ) -> crate::Result<u64> {
+        let progress = Progress {
+            validator: validator(resp.headers(), offset > 0),
+            length,
+            done: Vec::new(),
+        };
+        match progress.validator.is_some() {
+            true => save(meta, &progress).await?,
+            false => {
+                let _ = monoio::fs::remove_file(meta).await;
+            }
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(offset == 0)
+            .open(part)
+            .await?;
+        let end = write_body(&file, resp.raw_body(), offset).await?;
+        if let Some(length) = length.filter(|length| *length != end) {
+            return Err(ErrorKind::DownloadLength {
+                expected: length,
+                actual: end,
+            }
+            .into());
+        }
+        file.sync_all().await?;
+        file.close().await?;
+        Ok(end)
+    }
+
+    async fn fetch_ranges(
+        &self,
+        part: &Path,
+        meta: &Path,
+        mut progress: Progress,
+    ) -> crate::Result<u64> {
+        // Probe the length and the validator with the first byte.
+        let resp = self
+            .request()
+            .header(header::RANGE, "bytes=0-0")
+            .send()
+            .await?;
+        let (length, validator) = match (
+            resp.status(),
+            content_range(resp.headers()),
+            validator(resp.headers(), true),
+        ) {
+            (StatusCode::PARTIAL_CONTENT, Some((_, _, Some(length))), Some(validator)) => {
+                (length, validator)
+            }
+            // Ranges are not supported, the whole body is sent.
+            (status, _, _) if status.is_success() && status != StatusCode::PARTIAL_CONTENT => {
+                let length = content_length(resp.headers());
+                return self.write(resp, part, meta, 0, length).await;
+            }
+            (status, _, _) if status.is_success() => {
+                drop(resp);
+                return self.fetch(part, meta, Progress::default()).await;
+            }
+            (status, _, _) => return Err(ErrorKind::DownloadStatus(status).into()),
+        };
+        drop(resp);
+
+        let fresh = progress.validator.as_ref() != Some(&validator)
+            || progress.length.is_some_and(|l| l != length);
+        if fresh {
+            progress.done.clear();
+        }
+        progress.validator = Some(validator.clone());
+        progress.length = Some(length);
+        save(meta, &progress).await?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(fresh)
+            .open(part)
+            .await?;
+        let file = Rc::new(file);
+
+        // Pieces are fetched by up to `ranges` workers, which stop taking
+        // pieces once one of them fails.
+        let chunk = self.chunk_size.min(length.div_ceil(self.ranges as u64));
+        let pieces: VecDeque<_> = progress
+            .pending(length, chunk)
+            .into_iter()
+            .map(|(start, end)| {
+                let req = self
+                    .request()
+                    .header(header::RANGE, format!("bytes={start}-{}", end - 1))
+                    .header(header::IF_RANGE, validator.clone());
+                (req, start, end)
+            })
+            .collect();
+        let workers = self.ranges.min(pieces.len());
+        let pieces = Rc::new(RefCell::new(pieces));
+        let (tx, mut rx) = local_sync::mpsc::unbounded::channel();
+        for _ in 0..workers {
+            monoio::spawn(fetch_pieces(pieces.clone(), file.clone(), tx.clone()));
+        }
+        drop(tx);
+        let mut result = Ok(());
+        while let Some(piece) = rx.recv().await {
+            match piece {
+                Ok(range) => {
+                    progress.finish(range);
+                    save(meta, &progress).await?;
+                }
+                Err(e) => result = result.and(Err(e)),
+            }
+        }
+        result?;
+
+        let written = file.metadata().await?.len();
+        if progress.done != [(0, length)] || written != length {
+            return Err(ErrorKind::DownloadLength {
+                expected: length,
+                actual: written,
+            }
+            .into());
+        }
+        file.sync_all().await?;
+        Ok(length)
+    }
+}
+
+type Pieces<C> = Rc<RefCell<VecDeque<(ClientRequest<C>, u64, u64)>>>;
+
+async fn fetch_pieces<C>(
+    pieces: Pieces<C>,
+    file: Rc<File>,
+    tx: local_sync::mpsc::unbounded::Tx<crate::Result<(u64, u64)>>,
+) where
+    PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+        Key,
+        Connection = PooledConnection<Key, UnifiedTransportConnection>,
+        Error = crate::Error,
+    >,
+{
+    loop {
+        let piece = pieces.borrow_mut().pop_front();
+        let (req, start, end) = match piece {
+            Some(piece) => piece,
+            None => return,
+        };
+        let result = fetch_range(req, &file, start, end).await;
+        if result.is_err() {
+            pieces.borrow_mut().clear();
+        }
+        let _ = tx.send(result);
+    }
+}
+
+async fn fetch_range<C>(
+    req: ClientRequest<C>,
+    file: &File,
+    start: u64,
+    end: u64,
+) -> crate::Result<(u64, u64)>
+where
+    PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+        Key,
+        Connection = PooledConnection<Key, UnifiedTransportConnection>,
+        Error = crate::Error,
+    >,
+{
+    let resp = req.send().await?;
+    match resp.status() {
+        StatusCode::PARTIAL_CONTENT => {}
+        // The validator does not match, the whole body is sent.
+        StatusCode::OK => return Err(ErrorKind::DownloadChanged.into()),
+        status => return Err(ErrorKind::DownloadStatus(status).into()),
+    }
+    if !matches!(content_range(resp.headers()), Some((s, e, _)) if s == start && e == end) {
+        return Err(ErrorKind::DownloadChanged.into());
+    }
+    let written = write_body(file, resp.raw_body(), start).await?;
+    if written != end {
+        return Err(ErrorKind::DownloadLength {
+            expected: end - start,
+            actual: written - start,
+        }
+        .into());
+    }
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use bytes::Bytes;
+    use monoio::{
+        io::{AsyncReadRent, AsyncWriteRentExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::Builder;
+
+    #[test]
+    fn progress() {
+        let mut progress = Progress {
+            validator: Some(HeaderValue::from_static("\"v1\"")),
+            length: Some(100),
+            done: Vec::new(),
+        };
+        progress.finish((50, 60));
+        progress.finish((0, 10));
+        progress.finish((10, 20));
+        assert_eq!(progress.done, [(0, 20), (50, 60)]);
+        assert_eq!(progress.offset(), 20);
+        assert_eq!(Progress::decode(&progress.encode()), Some(progress.clone()));
+        assert_eq!(Progress::decode(b"\n\n"), Some(Progress::default()));
+        assert_eq!(Progress::decode(b"\"v1\"\nx\n"), None);
+
+        assert_eq!(
+            progress.pending(100, 25),
+            [(20, 25), (25, 50), (60, 75), (75, 100)]
+        );
+        assert_eq!(Progress::default().pending(3, 1), [(0, 1), (1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_RANGE, "bytes 0-9/100".parse().unwrap());
+        headers.insert(header::ETAG, "W/\"v1\"".parse().unwrap());
+        headers.insert(
+            header::LAST_MODIFIED,
+            "Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap(),
+        );
+        assert_eq!(content_range(&headers), Some((0, 10, Some(100))));
+        assert_eq!(validator(&headers, false), None);
+        assert_eq!(
+            validator(&headers, true),
+            headers.get(header::LAST_MODIFIED).cloned()
+        );
+        headers.insert(header::CONTENT_RANGE, "bytes 5-9/*".parse().unwrap());
+        headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+        headers.insert(header::ETAG, "\"v1\"".parse().unwrap());
+        assert_eq!(content_range(&headers), Some((5, 10, None)));
+        assert_eq!(
+            validator(&headers, false),
+            headers.get(header::ETAG).cloned()
+        );
+    }
+
+    struct Server {
+        data: Bytes,
+        ranged: bool,
+        // Responses to cut in the middle of the body.
+        truncate: Cell<usize>,
+        ranges: RefCell<Vec<String>>,
+    }
+
+    impl Server {
+        fn new(ranged: bool) -> Rc<Self> {
+            let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+            Rc::new(Self {
+                data: data.into(),
+                ranged,
+                truncate: Cell::new(0),
+                ranges: Default::default(),
+            })
+        }
+
+        fn start(self: &Rc<Self>) -> Uri {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let uri = format!("http://{}/file", listener.local_addr().unwrap());
+            let server = self.clone();
+            monoio::spawn(async move {
+                loop {
+                    let (io, _) = listener.accept().await.unwrap();
+                    monoio::spawn(server.clone().serve(io));
+                }
+            });
+            uri.parse().unwrap()
+        }
+
+        async fn serve(self: Rc<Self>, mut io: TcpStream) {
+            let mut head = Vec::new();
+            loop {
+                let end = match head.windows(4).position(|w| w == b"\r\n\r\n") {
+                    Some(end) => end,
+                    None => {
+                        let (res, buf) = io.read(Vec::with_capacity(4096)).await;
+                        if !matches!(res, Ok(n) if n > 0) {
+                            return;
+                        }
+                        head.extend_from_slice(&buf);
+                        continue;
+                    }
+                };
+                let request = String::from_utf8(head.drain(..end + 4).collect()).unwrap();
+                let header = |name: &str| {
+                    request.lines().find_map(|line| {
+                        let (key, value) = line.split_once(':')?;
+                        key.eq_ignore_ascii_case(name)
+                            .then(|| value.trim().to_string())
+                    })
+                };
+                let range = header("range").filter(|_| self.ranged);
+                let if_range = header("if-range");
+                let len = self.data.len();
+                let (status, start, end) = match range {
+                    Some(range) if if_range.as_deref().is_none_or(|v| v == "\"v1\"") => {
+                        self.ranges.borrow_mut().push(range.clone());
+                        let (start, end) = range["bytes=".len()..].split_once('-').unwrap();
+                        let end = match end {
+                            "" => len,
+                            end => end.parse::<usize>().unwrap() + 1,
+                        };
+                        ("206 Partial Content", start.parse().unwrap(), end)
+                    }
+                    _ => ("200 OK", 0, len),
+                };
+                // Full bodies are chunked unless ranges are supported, both
+                // are streamed.
+                let mut resp = format!("HTTP/1.1 {status}\r\netag: \"v1\"\r\n");
+                if self.ranged {
+                    resp.push_str("accept-ranges: bytes\r\n");
+                }
+                let mut body = BytesMut::new();
+                match (status.starts_with("206"), self.ranged) {
+                    (true, _) => {
+                        resp.push_str(&format!(
+                            "content-length: {}\r\ncontent-range: bytes {start}-{}/{len}\r\n",
+                            end - start,
+                            end - 1
+                        ));
+                        body.extend_from_slice(&self.data[start..end]);
+                    }
+                    (false, true) => {
+                        resp.push_str(&format!("content-length: {len}\r\n"));
+                        body.extend_from_slice(&self.data);
+                    }
+                    (false, false) => {
+                        resp.push_str("transfer-encoding: chunked\r\n");
+                        for chunk in self.data.chunks(10_000) {
+                            body.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                            body.extend_from_slice(chunk);
+                            body.extend_from_slice(b"\r\n");
+                        }
+                        body.extend_from_slice(b"0\r\n\r\n");
+                    }
+                }
+                resp.push_str("\r\n");
+                let truncate = self.truncate.get();
+                if truncate > 0 {
+                    self.truncate.set(truncate - 1);
+                    body.truncate(body.len() / 2);
+                }
+                if io.write_all(resp.into_bytes()).await.0.is_err()
+                    || io.write_all(body).await.0.is_err()
+                    || truncate > 0
+                {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        // Each runtime of a test gets its own file.
+        static RUN: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let run = RUN.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        std::env::temp_dir().join(format!("monoio-http-download-{}-{run}", std::process::id()))
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn resume() {
+        let server = Server::new(true);
+        server.truncate.set(1);
+        let uri = server.start();
+        let client = Builder::new().build();
+        let path = temp_path();
+
+        let err = client
+            .download(uri.clone(), &path)
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_body());
+        let written = std::fs::metadata(suffixed(&path, ".part")).unwrap().len();
+        assert!(written > 0);
+        assert!(!path.exists());
+
+        let len = client.download(uri, &path).send().await.unwrap();
+        assert_eq!(len, server.data.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), server.data);
+        assert_eq!(*server.ranges.borrow(), [format!("bytes={written}-")]);
+        assert!(!suffixed(&path, ".part.meta").exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn parallel() {
+        let server = Server::new(true);
+        let uri = server.start();
+        let client = Builder::new().build();
+        let path = temp_path();
+
+        let download = || {
+            client
+                .download(uri.clone(), &path)
+                .ranges(4)
+                .chunk_size(30_000)
+        };
+        let len = download().send().await.unwrap();
+        assert_eq!(len, server.data.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), server.data);
+        let mut ranges = server.ranges.borrow().clone();
+        ranges.sort();
+        assert_eq!(
+            ranges,
+            [
+                "bytes=0-0",
+                "bytes=0-29999",
+                "bytes=120000-149999",
+                "bytes=150000-179999",
+                "bytes=180000-199999",
+                "bytes=30000-59999",
+                "bytes=60000-89999",
+                "bytes=90000-119999",
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        // Resumed with the ranges left.
+        server.ranges.borrow_mut().clear();
+        let meta = Progress {
+            validator: Some(HeaderValue::from_static("\"v1\"")),
+            length: Some(server.data.len() as u64),
+            done: vec![(0, 120_000)],
+        };
+        std::fs::write(suffixed(&path, ".part.meta"), meta.encode()).unwrap();
+        std::fs::write(suffixed(&path, ".part"), &server.data[..120_000]).unwrap();
+        download().send().await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), server.data);
+        let mut ranges = server.ranges.borrow().clone();
+        ranges.sort();
+        assert_eq!(
+            ranges,
+            [
+                "bytes=0-0",
+                "bytes=120000-149999",
+                "bytes=150000-179999",
+                "bytes=180000-199999"
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn parallel_single_flight() {
+        let server = Server::new(true);
+        let uri = server.start();
+        let client = Builder::new()
+            .single_flight(crate::SingleFlight::new())
+            .build();
+        let path = temp_path();
+
+        // The pieces are requested at once, each gets its own range.
+        let len = client
+            .download(uri, &path)
+            .ranges(4)
+            .chunk_size(30_000)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(len, server.data.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), server.data);
+        assert_eq!(server.ranges.borrow().len(), 8);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn unranged() {
+        let server = Server::new(false);
+        let uri = server.start();
+        let client = Builder::new().build();
+        let path = temp_path();
+
+        let len = client.download(uri, &path).ranges(4).send().await.unwrap();
+        assert_eq!(len, server.data.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), server.data);
+        assert!(!suffixed(&path, ".part.meta").exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,27 @@
+use std::{error::Error as StdError, fmt, io};
+
+use http::{Method, Uri};
+use monoio_http::common::error::HttpError;
 use thiserror::Error as ThisError;
 
+use crate::Key;
+
+/// Error of the client.
+///
+/// The source is in `kind()`. Errors raised while sending a request carry
+/// its context, and can be classified for retry and circuit breaking.
+pub struct Error {
+    inner: Box<Inner>,
+}
+
+struct Inner {
+    kind: ErrorKind,
+    stage: Stage,
+    context: Option<Context>,
+}
+
 #[derive(ThisError, Debug)]
-pub enum Error {
+pub enum ErrorKind {
     #[error("convert from uri error {0}")]
     FromUri(#[from] crate::client::key::FromUriError),
     #[error("http header error")]
@@ -27,9 +47,348 @@ pub enum Error {
     #[error("Conn Manager marked this conn for close")]
     ClosePooledConnection,
     #[error("Http crate error {0}")]
-    HttpError(#[from] monoio_http::common::error::HttpError),
+    HttpError(HttpError),
     #[error("Codec missing from PooledConnection")]
     MissingCodec,
+    #[error("Request aborted")]
+    Aborted,
+    #[error("Client is shut down")]
+    Shutdown,
+    #[error("Body exceeds the limit of {0} bytes")]
+    BodyTooLarge(usize),
+    #[error("Record exceeds the limit of {0} bytes")]
+    RecordTooLarge(usize),
+    #[error("Connection is not http2")]
+    NotHttp2,
+    #[error("Rate limited, a token is available in {0:?}")]
+    RateLimited(std::time::Duration),
+    #[error("Coalesced request failed: {0}")]
+    Coalesced(String),
+    #[error("Circuit is open")]
+    CircuitOpen,
+    #[error("No endpoint of service {0}")]
+    NoEndpoint(smol_str::SmolStr),
+    #[error("Event stream response status {0}")]
+    EventStreamStatus(http::StatusCode),
+    #[error("Event stream response is not text/event-stream")]
+    EventStreamContentType,
+    #[error("Event exceeds the limit of {0} bytes")]
+    EventTooLarge(usize),
+    #[cfg(feature = "download")]
+    #[error("Download response status {0}")]
+    DownloadStatus(http::StatusCode),
+    #[cfg(feature = "download")]
+    #[error("Resource changed during the download")]
+    DownloadChanged,
+    #[cfg(feature = "download")]
+    #[error("Downloaded {actual} bytes, expected {expected}")]
+    DownloadLength { expected: u64, actual: u64 },
+    #[cfg(feature = "ws")]
+    #[error("WebSocket error {0}")]
+    WebSocket(#[from] monoio_http::ws::WsError),
+    #[cfg(feature = "ws")]
+    #[error("WebSocket handshake response status {0}")]
+    WebSocketStatus(http::StatusCode),
+    #[cfg(feature = "grpc")]
+    #[error("gRPC status {0}")]
+    Grpc(#[from] crate::grpc::Status),
+}
+
+impl From<HttpError> for ErrorKind {
+    fn from(e: HttpError) -> Self {
+        match e {
+            HttpError::BodyTooLarge(e) => ErrorKind::BodyTooLarge(e.limit),
+            e => ErrorKind::HttpError(e),
+        }
+    }
+}
+
+/// Where the error is raised in the lifetime of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Unknown,
+    /// Building the request, e.g. invalid uri.
+    Build,
+    /// Connecting to the server, including TLS and http2 handshakes.
+    Connect,
+    /// The connection failed before the request is written.
+    Queue,
+    /// Writing the request.
+    Send,
+    /// Waiting for the response head.
+    Receive,
+    /// Reading the response body.
+    Body,
+}
+
+/// The request that failed.
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub method: Method,
+    pub uri: Uri,
+    /// None if the uri can not be converted to a key.
+    pub key: Option<Key>,
+    /// None if the error is raised before a connection is got.
+    pub reused: Option<bool>,
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind, stage: Stage) -> Self {
+        Self {
+            inner: Box::new(Inner {
+                kind,
+                stage,
+                context: None,
+            }),
+        }
+    }
+
+    /// Set the stage if it is unknown.
+    pub(crate) fn stage(mut self, stage: Stage) -> Self {
+        if self.inner.stage == Stage::Unknown {
+            self.inner.stage = stage;
+        }
+        self
+    }
+
+    /// A copy for the requests coalesced with the failed one.
+    pub(crate) fn coalesced(&self) -> Self {
+        Self {
+            inner: Box::new(Inner {
+                kind: ErrorKind::Coalesced(self.to_string()),
+                stage: self.inner.stage,
+                context: self.inner.context.clone(),
+            }),
+        }
+    }
+
+    /// Attach the context if there is none.
+    pub(crate) fn context(mut self, context: impl FnOnce() -> Context) -> Self {
+        if self.inner.context.is_none() {
+            self.inner.context = Some(context());
+        }
+        self
+    }
+
+    #[inline]
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner.kind
+    }
+
+    #[inline]
+    pub fn into_kind(self) -> ErrorKind {
+        self.inner.kind
+    }
+
+    /// The request that failed, if the error is raised while sending it.
+    #[inline]
+    pub fn request_context(&self) -> Option<&Context> {
+        self.inner.context.as_ref()
+    }
+
+    #[inline]
+    pub fn uri(&self) -> Option<&Uri> {
+        self.inner.context.as_ref().map(|c| &c.uri)
+    }
+
+    #[inline]
+    pub fn method(&self) -> Option<&Method> {
+        self.inner.context.as_ref().map(|c| &c.method)
+    }
+
+    #[inline]
+    pub fn key(&self) -> Option<&Key> {
+        self.inner.context.as_ref().and_then(|c| c.key.as_ref())
+    }
+
+    /// Whether the request was sent on a connection from the pool.
+    #[inline]
+    pub fn is_connection_reused(&self) -> Option<bool> {
+        self.inner.context.as_ref().and_then(|c| c.reused)
+    }
+
+    /// Failed to connect to the server, the request is not sent.
+    pub fn is_connect(&self) -> bool {
+        self.inner.stage == Stage::Connect
+    }
+
+    /// An io operation timed out, or a gRPC deadline exceeded.
+    pub fn is_timeout(&self) -> bool {
+        #[cfg(feature = "grpc")]
+        if let ErrorKind::Grpc(status) = &self.inner.kind {
+            return status.code() == crate::grpc::Code::DeadlineExceeded;
+        }
+        let mut source: Option<&(dyn StdError + 'static)> = Some(&self.inner.kind);
+        while let Some(e) = source {
+            if matches!(e.downcast_ref::<io::Error>(), Some(e) if e.kind() == io::ErrorKind::TimedOut)
+            {
+                return true;
+            }
+            source = e.source();
+        }
+        false
+    }
+
+    /// Failed in the TLS handshake.
+    pub fn is_tls(&self) -> bool {
+        #[cfg(not(feature = "native-tls"))]
+        let tls = matches!(self.inner.kind, ErrorKind::Rustls(_));
+        #[cfg(feature = "native-tls")]
+        let tls = matches!(self.inner.kind, ErrorKind::NativeTls(_));
+        tls
+    }
+
+    /// Failed to read the response body, or it is too large.
+    pub fn is_body(&self) -> bool {
+        self.inner.stage == Stage::Body || matches!(self.inner.kind, ErrorKind::BodyTooLarge(_))
+    }
+
+    /// Failed to follow a redirect. The client does not follow redirects, a
+    /// 3xx response is returned to the caller as is, so it is always false.
+    pub fn is_redirect(&self) -> bool {
+        false
+    }
+
+    /// Returns false only if the request is known not to be processed by
+    /// the server, so it is safe to retry even if it is not idempotent.
+    pub fn request_may_have_been_sent(&self) -> bool {
+        match self.inner.stage {
+            Stage::Build | Stage::Connect | Stage::Queue => false,
+            // The server refused the http2 stream before processing it.
+            Stage::Receive => !matches!(
+                &self.inner.kind,
+                ErrorKind::H2Error(e) if e.reason() == Some(monoio_http::h2::Reason::REFUSED_STREAM)
+            ),
+            Stage::Unknown | Stage::Send | Stage::Body => true,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    #[inline]
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind, Stage::Unknown)
+    }
+}
+
+macro_rules! impl_from {
+    ($($(#[$attr:meta])* $source:ty),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            impl From<$source> for Error {
+                #[inline]
+                fn from(e: $source) -> Self {
+                    ErrorKind::from(e).into()
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(
+    crate::client::key::FromUriError,
+    http::Error,
+    monoio_http::h1::codec::decoder::DecodeError,
+    std::io::Error,
+    #[cfg(not(feature = "native-tls"))]
+    monoio_rustls::TlsError,
+    #[cfg(feature = "native-tls")]
+    monoio_native_tls::TlsError,
+    serde_json::Error,
+    monoio_http::h2::Error,
+    local_sync::oneshot::error::RecvError,
+    HttpError,
+    #[cfg(feature = "ws")]
+    monoio_http::ws::WsError,
+    #[cfg(feature = "grpc")]
+    crate::grpc::Status,
+);
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("Error");
+        f.field("kind", &self.inner.kind)
+            .field("stage", &self.inner.stage);
+        if let Some(context) = self.inner.context.as_ref() {
+            f.field("context", context);
+        }
+        f.finish()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(context) = self.inner.context.as_ref() {
+            write!(f, "{} {}: ", context.method, context.uri)?;
+        }
+        fmt::Display::fmt(&self.inner.kind, f)
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.inner.kind.source()
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify() {
+        let err = Error::from(io::Error::from(io::ErrorKind::TimedOut)).stage(Stage::Connect);
+        assert!(err.is_connect() && err.is_timeout());
+        assert!(!err.request_may_have_been_sent());
+
+        let err = Error::from(monoio_http::h2::Error::from(
+            monoio_http::h2::Reason::REFUSED_STREAM,
+        ))
+        .stage(Stage::Receive);
+        assert!(!err.request_may_have_been_sent());
+
+        let err = Error::from(HttpError::from(monoio_http::common::error::BodyTooLarge {
+            limit: 1,
+        }));
+        assert!(matches!(err.kind(), ErrorKind::BodyTooLarge(1)));
+        assert!(err.is_body() && err.request_may_have_been_sent());
+        assert!(!err.is_redirect());
+
+        let err = err.context(|| Context {
+            method: Method::GET,
+            uri: Uri::from_static("http://example.com/"),
+            key: None,
+            reused: Some(true),
+        });
+        assert_eq!(err.is_connection_reused(), Some(true));
+        assert_eq!(
+            err.to_string(),
+            "GET http://example.com/: Body exceeds the limit of 1 bytes"
+        );
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn connect_error() {
+        // Bind and drop to get a port that refuses connections.
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let uri = format!("http://{addr}/path");
+        let err = match crate::Builder::new().build().post(&uri).send().await {
+            Ok(_) => panic!("connected to a closed port"),
+            Err(e) => e,
+        };
+        assert!(
+            err.is_connect() && !err.is_timeout() && !err.is_tls(),
+            "{err:?}"
+        );
+        assert!(!err.request_may_have_been_sent());
+        assert_eq!(err.method(), Some(&Method::POST));
+        assert_eq!(err.uri().unwrap(), uri.as_str());
+        assert_eq!(err.key().unwrap().port, addr.port());
+        assert_eq!(err.is_connection_reused(), None);
+    }
+}
@@ -0,0 +1,370 @@
+use std::io::{Read, Write};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use http::HeaderValue;
+
+use super::{Code, Status};
+
+// Compressed flag and message length.
+const HEADER_SIZE: usize = 5;
+
+/// Serialize messages to bytes.
+pub trait Encoder {
+    type Item;
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Status>;
+}
+
+/// Deserialize messages from bytes.
+pub trait Decoder {
+    type Item;
+    fn decode(&mut self, src: Bytes) -> Result<Self::Item, Status>;
+}
+
+/// Message codec of a gRPC method.
+pub trait Codec {
+    type Encode;
+    type Decode;
+    type Encoder: Encoder<Item = Self::Encode>;
+    type Decoder: Decoder<Item = Self::Decode>;
+
+    fn encoder(&mut self) -> Self::Encoder;
+    fn decoder(&mut self) -> Self::Decoder;
+}
+
+/// Codec of serialized messages, bytes in and out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BytesCodec;
+
+impl Encoder for BytesCodec {
+    type Item = Bytes;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Status> {
+        dst.extend_from_slice(&item);
+        Ok(())
+    }
+}
+
+impl Decoder for BytesCodec {
+    type Item = Bytes;
+
+    fn decode(&mut self, src: Bytes) -> Result<Bytes, Status> {
+        Ok(src)
+    }
+}
+
+impl Codec for BytesCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+    type Encoder = Self;
+    type Decoder = Self;
+
+    fn encoder(&mut self) -> Self {
+        Self
+    }
+
+    fn decoder(&mut self) -> Self {
+        Self
+    }
+}
+
+/// Codec of prost messages, `E` is the request and `D` is the response.
+#[cfg(feature = "grpc-prost")]
+pub struct ProstCodec<E, D>(std::marker::PhantomData<fn(E) -> D>);
+
+#[cfg(feature = "grpc-prost")]
+impl<E, D> Default for ProstCodec<E, D> {
+    fn default() -> Self {
+        Self(std::marker::PhantomData)
+    }
+}
+
+#[cfg(feature = "grpc-prost")]
+impl<E: prost::Message, D> Encoder for ProstCodec<E, D> {
+    type Item = E;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Status> {
+        item.encode(dst)
+            .map_err(|e| Status::new(Code::Internal, e.to_string()))
+    }
+}
+
+#[cfg(feature = "grpc-prost")]
+impl<E, D: prost::Message + Default> Decoder for ProstCodec<E, D> {
+    type Item = D;
+
+    fn decode(&mut self, src: Bytes) -> Result<D, Status> {
+        D::decode(src).map_err(|e| Status::new(Code::Internal, e.to_string()))
+    }
+}
+
+#[cfg(feature = "grpc-prost")]
+impl<E: prost::Message, D: prost::Message + Default> Codec for ProstCodec<E, D> {
+    type Encode = E;
+    type Decode = D;
+    type Encoder = Self;
+    type Decoder = Self;
+
+    fn encoder(&mut self) -> Self {
+        Self::default()
+    }
+
+    fn decoder(&mut self) -> Self {
+        Self::default()
+    }
+}
+
+/// Message compression of `grpc-encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Returns Ok(None) for identity.
+    pub(crate) fn from_header(value: Option<&HeaderValue>) -> Result<Option<Encoding>, Status> {
+        match value.map(|v| v.as_bytes()) {
+            None | Some(b"identity") => Ok(None),
+            Some(b"gzip") => Ok(Some(Encoding::Gzip)),
+            Some(other) => Err(Status::new(
+                Code::Unimplemented,
+                format!(
+                    "unsupported grpc-encoding {}",
+                    String::from_utf8_lossy(other)
+                ),
+            )),
+        }
+    }
+
+    pub(crate) fn header_value(&self) -> HeaderValue {
+        HeaderValue::from_static(self.as_str())
+    }
+
+    fn compress(&self, src: &[u8], dst: &mut BytesMut) -> Result<(), Status> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(dst.writer(), flate2::Compression::default());
+                encoder
+                    .write_all(src)
+                    .and_then(|_| encoder.finish().map(|_| ()))
+                    .map_err(|e| Status::new(Code::Internal, e.to_string()))
+            }
+        }
+    }
+
+    fn decompress(&self, src: &[u8], max_size: usize) -> Result<Bytes, Status> {
+        match self {
+            Encoding::Gzip => {
+                let mut out = Vec::new();
+                flate2::read::GzDecoder::new(src)
+                    .take(max_size as u64 + 1)
+                    .read_to_end(&mut out)
+                    .map_err(|e| Status::new(Code::Internal, e.to_string()))?;
+                if out.len() > max_size {
+                    return Err(too_large(out.len(), max_size));
+                }
+                Ok(out.into())
+            }
+        }
+    }
+}
+
+fn too_large(size: usize, max_size: usize) -> Status {
+    Status::new(
+        Code::ResourceExhausted,
+        format!("message size {size} exceeds limit {max_size}"),
+    )
+}
+
+/// Serialize the message into a length-prefixed frame. `buf` is a scratch
+/// buffer reused across messages.
+pub(crate) fn encode_frame<E: Encoder>(
+    encoder: &mut E,
+    item: E::Item,
+    compression: Option<Encoding>,
+    max_size: usize,
+    buf: &mut BytesMut,
+) -> Result<Bytes, Status> {
+    buf.clear();
+    encoder.encode(item, buf)?;
+
+    let mut frame = BytesMut::with_capacity(HEADER_SIZE + buf.len());
+    frame.put_slice(&[0; HEADER_SIZE]);
+    match compression {
+        Some(encoding) => {
+            frame[0] = 1;
+            encoding.compress(buf, &mut frame)?;
+        }
+        None => frame.extend_from_slice(buf),
+    }
+    let size = frame.len() - HEADER_SIZE;
+    if size > max_size {
+        return Err(too_large(size, max_size));
+    }
+    frame[1..HEADER_SIZE].copy_from_slice(&(size as u32).to_be_bytes());
+    Ok(frame.freeze())
+}
+
+/// Incremental decoder of length-prefixed messages.
+pub(crate) struct FrameDecoder {
+    buf: BytesMut,
+    encoding: Option<Encoding>,
+    max_size: usize,
+}
+
+impl FrameDecoder {
+    pub(crate) fn new(encoding: Option<Encoding>, max_size: usize) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            encoding,
+            max_size,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Returns true if there is a partial message buffered.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Decode a message payload, decompressed if it is flagged.
+    pub(crate) fn decode(&mut self) -> Result<Option<Bytes>, Status> {
+        if self.buf.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+        let compressed = match self.buf[0] {
+            0 => false,
+            1 => true,
+            flag => {
+                return Err(Status::new(
+                    Code::Internal,
+                    format!("invalid compressed flag {flag}"),
+                ))
+            }
+        };
+        let size = u32::from_be_bytes(self.buf[1..HEADER_SIZE].try_into().unwrap()) as usize;
+        if size > self.max_size {
+            return Err(too_large(size, self.max_size));
+        }
+        if self.buf.len() < HEADER_SIZE + size {
+            self.buf.reserve(HEADER_SIZE + size - self.buf.len());
+            return Ok(None);
+        }
+        self.buf.advance(HEADER_SIZE);
+        let payload = self.buf.split_to(size).freeze();
+        if !compressed {
+            return Ok(Some(payload));
+        }
+        match self.encoding {
+            Some(encoding) => encoding.decompress(&payload, self.max_size).map(Some),
+            None => Err(Status::new(
+                Code::Internal,
+                "compressed message without grpc-encoding",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_round_trip() {
+        let mut buf = BytesMut::new();
+        for compression in [None, Some(Encoding::Gzip)] {
+            let mut decoder = FrameDecoder::new(compression, 1024);
+            let mut wire = BytesMut::new();
+            for msg in [&b"hello"[..], b"", &[7; 512]] {
+                let frame = encode_frame(
+                    &mut BytesCodec,
+                    Bytes::copy_from_slice(msg),
+                    compression,
+                    1024,
+                    &mut buf,
+                )
+                .unwrap();
+                assert_eq!(frame[0], compression.is_some() as u8);
+                wire.extend_from_slice(&frame);
+            }
+            // Feed byte by byte to cover partial frames.
+            let mut messages = Vec::new();
+            for b in wire.iter() {
+                decoder.feed(&[*b]);
+                while let Some(msg) = decoder.decode().unwrap() {
+                    messages.push(msg);
+                }
+            }
+            assert!(decoder.is_empty());
+            assert_eq!(messages.len(), 3);
+            assert_eq!(messages[0], &b"hello"[..]);
+            assert!(messages[1].is_empty());
+            assert_eq!(messages[2], &[7; 512][..]);
+        }
+    }
+
+    #[cfg(feature = "grpc-prost")]
+    #[test]
+    fn prost_codec() {
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct Hello {
+            #[prost(string, tag = "1")]
+            name: String,
+        }
+
+        let mut codec = ProstCodec::<Hello, Hello>::default();
+        let hello = Hello {
+            name: "monoio".to_string(),
+        };
+        let mut buf = BytesMut::new();
+        let frame = encode_frame(&mut codec.encoder(), hello.clone(), None, 64, &mut buf).unwrap();
+        let mut decoder = FrameDecoder::new(None, 64);
+        decoder.feed(&frame);
+        let payload = decoder.decode().unwrap().unwrap();
+        assert_eq!(codec.decoder().decode(payload).unwrap(), hello);
+    }
+
+    #[test]
+    fn frame_limits() {
+        let mut buf = BytesMut::new();
+        let err =
+            encode_frame(&mut BytesCodec, Bytes::from(vec![0; 16]), None, 8, &mut buf).unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+
+        let mut decoder = FrameDecoder::new(None, 8);
+        decoder.feed(&[0, 0, 0, 0, 16]);
+        assert_eq!(
+            decoder.decode().unwrap_err().code(),
+            Code::ResourceExhausted
+        );
+
+        // Compressed message which expands beyond the limit.
+        let frame = encode_frame(
+            &mut BytesCodec,
+            Bytes::from(vec![0; 64]),
+            Some(Encoding::Gzip),
+            64,
+            &mut buf,
+        )
+        .unwrap();
+        let mut decoder = FrameDecoder::new(Some(Encoding::Gzip), 32);
+        decoder.feed(&frame);
+        assert_eq!(
+            decoder.decode().unwrap_err().code(),
+            Code::ResourceExhausted
+        );
+
+        let mut decoder = FrameDecoder::new(None, 64);
+        decoder.feed(&frame);
+        assert_eq!(decoder.decode().unwrap_err().code(), Code::Internal);
+    }
+}
@@ -0,0 +1,717 @@
+//! gRPC client over the pooled http2 connections.
+//!
+//! Messages are length-prefixed and serialized by a pluggable `Codec`, calls
+//! to the same origin are multiplexed on the connections of the `Client`.
+mod codec;
+mod status;
+
+use std::{future::poll_fn, time::Duration};
+
+use bytes::{Bytes, BytesMut};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use monoio::{io::stream::Stream, time::Instant};
+use monoio_http::h2::{self, Reason, RecvStream, SendStream};
+
+#[cfg(feature = "grpc-prost")]
+pub use self::codec::ProstCodec;
+use self::codec::{encode_frame, FrameDecoder};
+pub use self::{
+    codec::{BytesCodec, Codec, Decoder, Encoder, Encoding},
+    status::{Code, Status},
+};
+use crate::{
+    client::{
+        connector::PooledConnector, key::Key, pool::PooledConnection,
+        unified::UnifiedTransportConnector, Client,
+    },
+    unified::UnifiedTransportConnection,
+    Connector,
+};
+
+const GRPC_TIMEOUT: HeaderName = HeaderName::from_static("grpc-timeout");
+const GRPC_ENCODING: HeaderName = HeaderName::from_static("grpc-encoding");
+const GRPC_ACCEPT_ENCODING: HeaderName = HeaderName::from_static("grpc-accept-encoding");
+const GRPC_CONTENT_TYPE: HeaderValue = HeaderValue::from_static("application/grpc");
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Format the duration as `grpc-timeout`, in the finest unit that fits in 8
+/// digits.
+pub fn encode_timeout(timeout: Duration) -> HeaderValue {
+    const MAX: u128 = 99_999_999;
+    let nanos = timeout.as_nanos();
+    let (value, unit) = [
+        (1, 'n'),
+        (1_000, 'u'),
+        (1_000_000, 'm'),
+        (1_000_000_000, 'S'),
+        (60_000_000_000, 'M'),
+        (3_600_000_000_000, 'H'),
+    ]
+    .into_iter()
+    .map(|(scale, unit)| (nanos.div_ceil(scale), unit))
+    .find(|(value, _)| *value <= MAX)
+    .unwrap_or((MAX, 'H'));
+    HeaderValue::try_from(format!("{value}{unit}")).expect("digits are valid header value")
+}
+
+/// Parse a `grpc-timeout` header.
+pub fn decode_timeout(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?;
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    let digits: u64 = digits.parse().ok()?;
+    let timeout = match unit {
+        "n" => Duration::from_nanos(digits),
+        "u" => Duration::from_micros(digits),
+        "m" => Duration::from_millis(digits),
+        "S" => Duration::from_secs(digits),
+        "M" => Duration::from_secs(digits * 60),
+        "H" => Duration::from_secs(digits * 3600),
+        _ => return None,
+    };
+    Some(timeout)
+}
+
+async fn with_deadline<T>(
+    deadline: Option<Instant>,
+    fut: impl std::future::Future<Output = crate::Result<T>>,
+) -> crate::Result<T> {
+    match deadline {
+        Some(deadline) => match monoio::time::timeout_at(deadline, fut).await {
+            Ok(output) => output,
+            Err(_) => Err(Status::new(Code::DeadlineExceeded, "deadline exceeded").into()),
+        },
+        None => fut.await,
+    }
+}
+
+/// A gRPC channel to an origin.
+pub struct Channel<C = UnifiedTransportConnector> {
+    client: Client<C>,
+    origin: Uri,
+    metadata: HeaderMap,
+    timeout: Option<Duration>,
+    send_compression: Option<Encoding>,
+    accept_compression: Option<Encoding>,
+    max_decoding_message_size: usize,
+    max_encoding_message_size: usize,
+}
+
+// Impl Clone manually because C does not have to be Clone
+impl<C> Clone for Channel<C> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            origin: self.origin.clone(),
+            metadata: self.metadata.clone(),
+            timeout: self.timeout,
+            send_compression: self.send_compression,
+            accept_compression: self.accept_compression,
+            max_decoding_message_size: self.max_decoding_message_size,
+            max_encoding_message_size: self.max_encoding_message_size,
+        }
+    }
+}
+
+impl<C> Channel<C> {
+    pub fn new(client: Client<C>, origin: Uri) -> Self {
+        Self {
+            client,
+            origin,
+            metadata: HeaderMap::new(),
+            timeout: None,
+            send_compression: None,
+            accept_compression: None,
+            max_decoding_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_encoding_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Add metadata sent with every call.
+    pub fn metadata(mut self, key: HeaderName, value: HeaderValue) -> Self {
+        self.metadata.append(key, value);
+        self
+    }
+
+    /// Default deadline of calls, it is sent as `grpc-timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Compress request messages.
+    pub fn send_compressed(mut self, encoding: Encoding) -> Self {
+        self.send_compression = Some(encoding);
+        self
+    }
+
+    /// Tell the server that compressed response messages are accepted.
+    pub fn accept_compressed(mut self, encoding: Encoding) -> Self {
+        self.accept_compression = Some(encoding);
+        self
+    }
+
+    /// Limit of a response message after decompression, 4MB by default.
+    pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+        self.max_decoding_message_size = limit;
+        self
+    }
+
+    /// Limit of a request message after compression, 4MB by default.
+    pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+        self.max_encoding_message_size = limit;
+        self
+    }
+
+    /// Start a call to the method, the path is like `/package.Service/Method`.
+    pub fn call(&self, path: &str) -> Call<'_, C> {
+        Call {
+            channel: self,
+            path: path.to_owned(),
+            metadata: HeaderMap::new(),
+            timeout: self.timeout,
+        }
+    }
+}
+
+/// A call with its metadata and deadline.
+pub struct Call<'a, C = UnifiedTransportConnector> {
+    channel: &'a Channel<C>,
+    path: String,
+    metadata: HeaderMap,
+    timeout: Option<Duration>,
+}
+
+impl<C> Call<'_, C> {
+    pub fn metadata(mut self, key: HeaderName, value: HeaderValue) -> Self {
+        self.metadata.append(key, value);
+        self
+    }
+
+    /// Override the deadline of the channel.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn build_request(&self) -> crate::Result<http::Request<()>> {
+        let channel = self.channel;
+        let mut uri = Uri::builder().path_and_query(self.path.as_str());
+        if let Some(scheme) = channel.origin.scheme() {
+            uri = uri.scheme(scheme.clone());
+        }
+        if let Some(authority) = channel.origin.authority() {
+            uri = uri.authority(authority.clone());
+        }
+        let mut req = http::Request::builder()
+            .method(Method::POST)
+            .uri(uri.build()?)
+            .version(http::Version::HTTP_2)
+            .body(())?;
+
+        let headers = req.headers_mut();
+        for (name, value) in channel.client.default_headers().iter() {
+            headers.append(name, value.clone());
+        }
+        for (name, value) in channel.metadata.iter().chain(self.metadata.iter()) {
+            headers.append(name, value.clone());
+        }
+        headers.insert(header::CONTENT_TYPE, GRPC_CONTENT_TYPE);
+        headers.insert(header::TE, HeaderValue::from_static("trailers"));
+        if let Some(timeout) = self.timeout {
+            headers.insert(GRPC_TIMEOUT, encode_timeout(timeout));
+        }
+        if let Some(encoding) = channel.send_compression {
+            headers.insert(GRPC_ENCODING, encoding.header_value());
+        }
+        if let Some(encoding) = channel.accept_compression {
+            headers.insert(GRPC_ACCEPT_ENCODING, encoding.header_value());
+        }
+        Ok(req)
+    }
+}
+
+impl<C> Call<'_, C>
+where
+    PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+        Key,
+        Connection = PooledConnection<Key, UnifiedTransportConnection>,
+        Error = crate::Error,
+    >,
+{
+    async fn start<D: Codec>(
+        self,
+        codec: &mut D,
+        deadline: Option<Instant>,
+    ) -> crate::Result<(Sender<D::Encoder>, ResponseFuture<D::Decoder>)> {
+        let request = self.build_request()?;
+        let send_request = self
+            .channel
+            .client
+            .http2_send_request(request.uri())
+            .await?;
+        let (response, send) = send_request.ready().await?.send_request(request, false)?;
+        let sender = Sender {
+            send,
+            encoder: codec.encoder(),
+            compression: self.channel.send_compression,
+            max_size: self.channel.max_encoding_message_size,
+            buf: BytesMut::new(),
+        };
+        let response = ResponseFuture {
+            inner: response,
+            decoder: codec.decoder(),
+            deadline,
+            max_size: self.channel.max_decoding_message_size,
+        };
+        Ok((sender, response))
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Send a message and receive a message.
+    pub async fn unary<D: Codec>(
+        self,
+        mut codec: D,
+        message: D::Encode,
+    ) -> crate::Result<Response<D::Decode>> {
+        let deadline = self.deadline();
+        with_deadline(deadline, async {
+            let (sender, response) = self.start(&mut codec, deadline).await?;
+            sender.send_last(message).await?;
+            response.unary().await
+        })
+        .await
+    }
+
+    /// Send a message and receive a stream of messages.
+    pub async fn server_streaming<D: Codec>(
+        self,
+        mut codec: D,
+        message: D::Encode,
+    ) -> crate::Result<Response<Streaming<D::Decoder>>> {
+        let deadline = self.deadline();
+        with_deadline(deadline, async {
+            let (sender, response) = self.start(&mut codec, deadline).await?;
+            sender.send_last(message).await?;
+            response.streaming().await
+        })
+        .await
+    }
+
+    /// Send a stream of messages and receive a message.
+    ///
+    /// Call `Sender::finish` after all messages are sent, then wait for the
+    /// response with `ResponseFuture::unary`.
+    pub async fn client_streaming<D: Codec>(
+        self,
+        mut codec: D,
+    ) -> crate::Result<(Sender<D::Encoder>, ResponseFuture<D::Decoder>)> {
+        let deadline = self.deadline();
+        with_deadline(deadline, self.start(&mut codec, deadline)).await
+    }
+
+    /// Send and receive streams of messages at the same time.
+    ///
+    /// Wait for the response with `ResponseFuture::streaming`, it may not be
+    /// sent until some messages are received by the server.
+    pub async fn streaming<D: Codec>(
+        self,
+        mut codec: D,
+    ) -> crate::Result<(Sender<D::Encoder>, ResponseFuture<D::Decoder>)> {
+        let deadline = self.deadline();
+        with_deadline(deadline, self.start(&mut codec, deadline)).await
+    }
+}
+
+/// Response metadata and message(s).
+#[derive(Debug)]
+pub struct Response<T> {
+    metadata: HeaderMap,
+    message: T,
+}
+
+impl<T> Response<T> {
+    /// The response headers.
+    #[inline]
+    pub fn metadata(&self) -> &HeaderMap {
+        &self.metadata
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.message
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.message
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.message
+    }
+
+    #[inline]
+    pub fn into_parts(self) -> (HeaderMap, T) {
+        (self.metadata, self.message)
+    }
+}
+
+/// Sending half of a call.
+pub struct Sender<E> {
+    send: SendStream<Bytes>,
+    encoder: E,
+    compression: Option<Encoding>,
+    max_size: usize,
+    buf: BytesMut,
+}
+
+impl<E: Encoder> Sender<E> {
+    async fn send_frame(&mut self, item: E::Item, eos: bool) -> crate::Result<()> {
+        let mut frame = encode_frame(
+            &mut self.encoder,
+            item,
+            self.compression,
+            self.max_size,
+            &mut self.buf,
+        )?;
+        // Wait for the flow control window instead of buffering unboundedly.
+        while !frame.is_empty() {
+            self.send.reserve_capacity(frame.len());
+            let mut capacity = self.send.capacity();
+            while capacity == 0 {
+                capacity = match poll_fn(|cx| self.send.poll_capacity(cx)).await {
+                    Some(capacity) => capacity?,
+                    None => return Err(Status::new(Code::Unavailable, "stream closed").into()),
+                };
+            }
+            let data = frame.split_to(capacity.min(frame.len()));
+            self.send.send_data(data, eos && frame.is_empty())?;
+        }
+        Ok(())
+    }
+
+    async fn send_last(mut self, item: E::Item) -> crate::Result<()> {
+        self.send_frame(item, true).await
+    }
+
+    /// Send a message.
+    pub async fn send(&mut self, item: E::Item) -> crate::Result<()> {
+        self.send_frame(item, false).await
+    }
+
+    /// Close the sending half, it must be called after the last message.
+    pub fn finish(mut self) -> crate::Result<()> {
+        self.send.send_data(Bytes::new(), true)?;
+        Ok(())
+    }
+
+    /// Cancel the call.
+    pub fn cancel(mut self) {
+        self.send.send_reset(Reason::CANCEL);
+    }
+}
+
+/// Pending response of a call.
+pub struct ResponseFuture<D> {
+    inner: h2::client::ResponseFuture,
+    decoder: D,
+    deadline: Option<Instant>,
+    max_size: usize,
+}
+
+impl<D: Decoder> ResponseFuture<D> {
+    /// Wait for the response headers, messages are received from the
+    /// returned stream.
+    pub async fn streaming(self) -> crate::Result<Response<Streaming<D>>> {
+        let inner = self.inner;
+        let response = with_deadline(self.deadline, async { Ok(inner.await?) }).await?;
+        let (parts, body) = response.into_parts();
+        if parts.status != StatusCode::OK {
+            return Err(Status::from_http_status(parts.status).into());
+        }
+
+        let mut streaming = Streaming {
+            body,
+            decoder: self.decoder,
+            frames: FrameDecoder::new(None, self.max_size),
+            deadline: self.deadline,
+            trailers: None,
+        };
+        // Trailers-only response, usually an error.
+        if let Some(status) = Status::from_header_map(&parts.headers) {
+            if status.code() != Code::Ok {
+                return Err(status.into());
+            }
+            streaming.trailers = Some(parts.headers.clone());
+        }
+        let is_grpc = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.as_bytes().starts_with(b"application/grpc"))
+            .unwrap_or(false);
+        if !is_grpc {
+            return Err(Status::new(Code::Unknown, "invalid content-type").into());
+        }
+        let encoding = Encoding::from_header(parts.headers.get(GRPC_ENCODING))?;
+        streaming.frames = FrameDecoder::new(encoding, self.max_size);
+
+        Ok(Response {
+            metadata: parts.headers,
+            message: streaming,
+        })
+    }
+
+    /// Wait for the single message of a unary or client streaming call.
+    pub async fn unary(self) -> crate::Result<Response<D::Item>> {
+        let (metadata, mut streaming) = self.streaming().await?.into_parts();
+        let message = match streaming.message().await? {
+            Some(message) => message,
+            None => return Err(Status::new(Code::Internal, "missing response message").into()),
+        };
+        // Read to the end to check the status in trailers.
+        if streaming.message().await?.is_some() {
+            return Err(Status::new(Code::Internal, "more than one response message").into());
+        }
+        Ok(Response { metadata, message })
+    }
+}
+
+/// Stream of response messages.
+///
+/// The call fails with `Status` if the trailers has a code other than `Ok`.
+pub struct Streaming<D> {
+    body: RecvStream,
+    decoder: D,
+    frames: FrameDecoder,
+    deadline: Option<Instant>,
+    trailers: Option<HeaderMap>,
+}
+
+impl<D: Decoder> Streaming<D> {
+    /// Receive a message, returns None when the call succeeds.
+    pub async fn message(&mut self) -> crate::Result<Option<D::Item>> {
+        loop {
+            if let Some(payload) = self.frames.decode()? {
+                return Ok(Some(self.decoder.decode(payload)?));
+            }
+            if self.trailers.is_some() {
+                return Ok(None);
+            }
+
+            let body = &mut self.body;
+            match with_deadline(self.deadline, async { Ok(body.data().await) }).await? {
+                Some(Ok(data)) => {
+                    let _ = body.flow_control().release_capacity(data.len());
+                    self.frames.feed(&data);
+                }
+                Some(Err(e)) => return Err(e.into()),
+                None => {
+                    if !self.frames.is_empty() {
+                        return Err(Status::new(Code::Internal, "truncated message").into());
+                    }
+                    let trailers =
+                        with_deadline(self.deadline, async { Ok(body.trailers().await?) })
+                            .await?
+                            .unwrap_or_default();
+                    let status = Status::from_header_map(&trailers)
+                        .unwrap_or_else(|| Status::new(Code::Internal, "missing grpc-status"));
+                    self.trailers = Some(trailers);
+                    if status.code() != Code::Ok {
+                        return Err(status.into());
+                    }
+                }
+            }
+        }
+    }
+
+    /// The trailers, available after all messages are received.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+}
+
+impl<D: Decoder> Stream for Streaming<D> {
+    type Item = crate::Result<D::Item>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        self.message().await.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::net::TcpListener;
+    use monoio_http::h2::server::SendResponse;
+
+    use super::*;
+    use crate::Builder;
+
+    #[test]
+    fn timeout_header() {
+        for (timeout, value) in [
+            (Duration::from_millis(1500), "1500000u"),
+            (Duration::from_secs(100), "100000m"),
+            (Duration::from_secs(1_000_000), "1000000S"),
+            (Duration::from_secs(200_000_000), "3333334M"),
+            (Duration::from_secs(u64::MAX), "99999999H"),
+        ] {
+            assert_eq!(encode_timeout(timeout), value);
+        }
+        assert_eq!(
+            decode_timeout(&HeaderValue::from_static("100m")),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(decode_timeout(&HeaderValue::from_static("100")), None);
+        assert_eq!(
+            decode_timeout(&HeaderValue::from_static("123456789S")),
+            None
+        );
+    }
+
+    fn respond(
+        respond: &mut SendResponse<Bytes>,
+        encoding: Option<&HeaderValue>,
+    ) -> SendStream<Bytes> {
+        let mut response = http::Response::new(());
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, GRPC_CONTENT_TYPE);
+        if let Some(encoding) = encoding {
+            headers.insert(GRPC_ENCODING, encoding.clone());
+        }
+        respond.send_response(response, false).unwrap()
+    }
+
+    fn trailers(code: Code, message: &'static str) -> HeaderMap {
+        let mut trailers = HeaderMap::new();
+        trailers.insert(status::GRPC_STATUS, HeaderValue::from(code as i32));
+        trailers.insert(status::GRPC_MESSAGE, HeaderValue::from_static(message));
+        trailers
+    }
+
+    // Echo every request message, `/test.Echo/Fail` responds trailers-only.
+    async fn serve(listener: TcpListener) {
+        loop {
+            let (io, _) = listener.accept().await.unwrap();
+            monoio::spawn(async move {
+                let mut conn = h2::server::handshake(io).await.unwrap();
+                while let Some(Ok((request, mut sender))) = conn.accept().await {
+                    monoio::spawn(async move {
+                        let (parts, mut body) = request.into_parts();
+                        assert_eq!(parts.headers[header::TE], "trailers");
+                        if parts.uri.path() == "/test.Echo/Hang" {
+                            monoio::time::sleep(Duration::from_secs(1)).await;
+                            return;
+                        }
+                        if parts.uri.path() == "/test.Echo/Fail" {
+                            let mut response = http::Response::new(());
+                            *response.headers_mut() = trailers(Code::NotFound, "not%20found");
+                            sender.send_response(response, true).unwrap();
+                            return;
+                        }
+                        // Echo frames as is, so they are encoded the same.
+                        let mut send = respond(&mut sender, parts.headers.get(GRPC_ENCODING));
+                        while let Some(Ok(data)) = body.data().await {
+                            let _ = body.flow_control().release_capacity(data.len());
+                            send.send_data(data, false).unwrap();
+                        }
+                        let code = match parts.headers.get(GRPC_TIMEOUT) {
+                            Some(_) => Code::Aborted,
+                            None => Code::Ok,
+                        };
+                        send.send_trailers(trailers(code, "")).unwrap();
+                    });
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn grpc_calls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve(listener));
+
+        let client = Builder::new().http2_client().build();
+        let channel = client
+            .grpc(format!("http://{addr}").parse().unwrap())
+            .send_compressed(Encoding::Gzip);
+
+        let response = channel
+            .call("/test.Echo/Unary")
+            .unary(BytesCodec, Bytes::from_static(b"hello"))
+            .await
+            .unwrap();
+        assert_eq!(response.get_ref(), &b"hello"[..]);
+
+        let mut streaming = channel
+            .call("/test.Echo/ServerStreaming")
+            .server_streaming(BytesCodec, Bytes::from_static(b"world"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(streaming.next().await.unwrap().unwrap(), &b"world"[..]);
+        assert!(streaming.next().await.is_none());
+        assert_eq!(streaming.trailers().unwrap()[status::GRPC_STATUS], "0");
+
+        let (mut sender, response) = channel
+            .call("/test.Echo/ClientStreaming")
+            .client_streaming(BytesCodec)
+            .await
+            .unwrap();
+        sender.send(Bytes::from_static(b"one")).await.unwrap();
+        sender.finish().unwrap();
+        assert_eq!(response.unary().await.unwrap().into_inner(), &b"one"[..]);
+
+        let (mut sender, response) = channel
+            .call("/test.Echo/Bidi")
+            .streaming(BytesCodec)
+            .await
+            .unwrap();
+        sender.send(Bytes::from_static(b"ping")).await.unwrap();
+        let mut streaming = response.streaming().await.unwrap().into_inner();
+        assert_eq!(streaming.message().await.unwrap().unwrap(), &b"ping"[..]);
+        sender.send(Bytes::from_static(b"pong")).await.unwrap();
+        assert_eq!(streaming.message().await.unwrap().unwrap(), &b"pong"[..]);
+        sender.finish().unwrap();
+        assert!(streaming.message().await.unwrap().is_none());
+
+        let err = channel
+            .call("/test.Echo/Fail")
+            .unary(BytesCodec, Bytes::new())
+            .await
+            .unwrap_err();
+        match err.kind() {
+            crate::ErrorKind::Grpc(status) => {
+                assert_eq!(status.code(), Code::NotFound);
+                assert_eq!(status.message(), "not found");
+            }
+            e => panic!("unexpected error {e}"),
+        }
+
+        // The status in trailers is checked after the message.
+        let err = channel
+            .call("/test.Echo/Unary")
+            .timeout(Duration::from_secs(10))
+            .unary(BytesCodec, Bytes::from_static(b"hello"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), crate::ErrorKind::Grpc(s) if s.code() == Code::Aborted));
+
+        let err = channel
+            .call("/test.Echo/Hang")
+            .timeout(Duration::from_millis(50))
+            .unary(BytesCodec, Bytes::new())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.kind(), crate::ErrorKind::Grpc(s) if s.code() == Code::DeadlineExceeded)
+        );
+    }
+}
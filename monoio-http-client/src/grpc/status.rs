@@ -0,0 +1,174 @@
+use http::{HeaderMap, HeaderName, StatusCode};
+use thiserror::Error as ThisError;
+
+pub(crate) const GRPC_STATUS: HeaderName = HeaderName::from_static("grpc-status");
+pub(crate) const GRPC_MESSAGE: HeaderName = HeaderName::from_static("grpc-message");
+
+/// gRPC status codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Code {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+impl Code {
+    /// Codes out of range are mapped to `Unknown`.
+    pub fn from_i32(code: i32) -> Code {
+        match code {
+            0 => Code::Ok,
+            1 => Code::Cancelled,
+            2 => Code::Unknown,
+            3 => Code::InvalidArgument,
+            4 => Code::DeadlineExceeded,
+            5 => Code::NotFound,
+            6 => Code::AlreadyExists,
+            7 => Code::PermissionDenied,
+            8 => Code::ResourceExhausted,
+            9 => Code::FailedPrecondition,
+            10 => Code::Aborted,
+            11 => Code::OutOfRange,
+            12 => Code::Unimplemented,
+            13 => Code::Internal,
+            14 => Code::Unavailable,
+            15 => Code::DataLoss,
+            16 => Code::Unauthenticated,
+            _ => Code::Unknown,
+        }
+    }
+}
+
+/// The status of a gRPC call, it is returned as error when the code is not
+/// `Ok`.
+#[derive(ThisError, Debug, Clone)]
+#[error("{code:?}: {message}")]
+pub struct Status {
+    code: Code,
+    message: String,
+    // Boxed to keep the error small.
+    metadata: Box<HeaderMap>,
+}
+
+impl Status {
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            metadata: Box::default(),
+        }
+    }
+
+    #[inline]
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The trailers, or the headers of a trailers-only response.
+    #[inline]
+    pub fn metadata(&self) -> &HeaderMap {
+        &self.metadata
+    }
+
+    /// Parse the status from trailers, or headers of a trailers-only
+    /// response. Returns None if `grpc-status` is missing.
+    pub fn from_header_map(headers: &HeaderMap) -> Option<Status> {
+        let code = headers.get(GRPC_STATUS)?;
+        let code = std::str::from_utf8(code.as_bytes())
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .map(Code::from_i32)
+            .unwrap_or(Code::Unknown);
+        let message = headers
+            .get(GRPC_MESSAGE)
+            .map(|m| percent_decode(m.as_bytes()))
+            .unwrap_or_default();
+        Some(Status {
+            code,
+            message,
+            metadata: Box::new(headers.clone()),
+        })
+    }
+
+    /// Map a non-200 http status as the spec of gRPC over HTTP/2.
+    pub(crate) fn from_http_status(status: StatusCode) -> Status {
+        let code = match status {
+            StatusCode::BAD_REQUEST => Code::Internal,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::FORBIDDEN => Code::PermissionDenied,
+            StatusCode::NOT_FOUND => Code::Unimplemented,
+            StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => Code::Unavailable,
+            _ => Code::Unknown,
+        };
+        Status::new(code, format!("http status {status}"))
+    }
+}
+
+// `grpc-message` is percent-encoded, invalid sequences are kept as is.
+fn percent_decode(src: &[u8]) -> String {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut out = Vec::with_capacity(src.len());
+    let mut i = 0;
+    while i < src.len() {
+        if src[i] == b'%' && i + 2 < src.len() {
+            if let (Some(h), Some(l)) = (hex(src[i + 1]), hex(src[i + 2])) {
+                out.push(h << 4 | l);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(src[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn parse_status() {
+        let mut headers = HeaderMap::new();
+        assert!(Status::from_header_map(&headers).is_none());
+
+        headers.insert(GRPC_STATUS, HeaderValue::from_static("5"));
+        headers.insert(
+            GRPC_MESSAGE,
+            HeaderValue::from_static("no%20such%20key%zz%"),
+        );
+        let status = Status::from_header_map(&headers).unwrap();
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "no such key%zz%");
+
+        headers.insert(GRPC_STATUS, HeaderValue::from_static("99"));
+        let status = Status::from_header_map(&headers).unwrap();
+        assert_eq!(status.code(), Code::Unknown);
+
+        let status = Status::from_http_status(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status.code(), Code::Unavailable);
+    }
+}
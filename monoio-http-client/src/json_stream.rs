@@ -0,0 +1,315 @@
+//! Streaming decode of newline-delimited JSON(application/x-ndjson) and
+//! JSON text sequences(application/json-seq).
+use std::marker::PhantomData;
+
+use bytes::{Bytes, BytesMut};
+use monoio::io::stream::Stream;
+use monoio_http::common::body::{Body, HttpBody};
+use serde::de::{DeserializeOwned, IgnoredAny};
+
+use crate::{error::Stage, ErrorKind};
+
+const DEFAULT_MAX_RECORD_SIZE: usize = 1 << 20;
+const RS: u8 = 0x1E;
+
+/// How the records are delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// A record per line, blank lines are skipped. It is used by
+    /// `application/x-ndjson`, `application/jsonl` and Kubernetes watch
+    /// streams.
+    Lines,
+    /// Records start with the record separator `0x1E` as in RFC 7464. A
+    /// record may span lines.
+    JsonSeq,
+}
+
+/// Incremental splitter of the records of a stream.
+#[derive(Debug)]
+pub struct RecordSplitter {
+    framing: Framing,
+    buf: BytesMut,
+    // Bytes of buf searched for the end of the record.
+    scanned: usize,
+    max_record_size: usize,
+}
+
+impl RecordSplitter {
+    pub fn new(framing: Framing, max_record_size: usize) -> Self {
+        Self {
+            framing,
+            buf: BytesMut::new(),
+            scanned: 0,
+            max_record_size,
+        }
+    }
+
+    /// Feed a chunk of the stream.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Take the next complete record without the delimiters. Fails if a
+    /// record exceeds the size limit.
+    pub fn next_record(&mut self) -> crate::Result<Option<Bytes>> {
+        loop {
+            let end = match self.framing {
+                Framing::Lines => self.find_line_end(),
+                Framing::JsonSeq => self.find_seq_end(),
+            };
+            let end = match end {
+                Some(end) => end,
+                None => {
+                    self.scanned = self.buf.len();
+                    if self.buf.len() > self.max_record_size {
+                        return Err(self.too_large());
+                    }
+                    return Ok(None);
+                }
+            };
+            let record = trim(self.buf.split_to(end).freeze());
+            self.scanned = 0;
+            if record.len() > self.max_record_size {
+                return Err(self.too_large());
+            }
+            if !record.is_empty() {
+                return Ok(Some(record));
+            }
+        }
+    }
+
+    /// Take the buffered record at the end of the stream, it may miss the
+    /// final delimiter.
+    pub fn finish(&mut self) -> Option<Bytes> {
+        self.scanned = 0;
+        Some(trim(self.buf.split().freeze())).filter(|record| !record.is_empty())
+    }
+
+    /// Discard the buffered data.
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.scanned = 0;
+    }
+
+    fn too_large(&mut self) -> crate::Error {
+        self.reset();
+        crate::Error::new(ErrorKind::RecordTooLarge(self.max_record_size), Stage::Body)
+    }
+
+    // The end includes the line feed.
+    fn find_line_end(&self) -> Option<usize> {
+        let pos = self.buf[self.scanned..].iter().position(|b| *b == b'\n')?;
+        Some(self.scanned + pos + 1)
+    }
+
+    // A record ends before the separator of the next one. It also ends at a
+    // line feed at the end of the buffer if it is a complete JSON text, so
+    // it is not held until the next record arrives.
+    fn find_seq_end(&mut self) -> Option<usize> {
+        let from = self.scanned.max(1);
+        for (i, b) in self.buf.iter().enumerate().skip(from) {
+            match *b {
+                RS => return Some(i),
+                b'\n' if i + 1 == self.buf.len() => {
+                    let record = trim(Bytes::copy_from_slice(&self.buf[..i]));
+                    if serde_json::from_slice::<IgnoredAny>(&record).is_ok() {
+                        return Some(i + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+fn trim(mut record: Bytes) -> Bytes {
+    let skip = |b: &u8| b.is_ascii_whitespace() || *b == RS;
+    let start = record.iter().position(|b| !skip(b)).unwrap_or(record.len());
+    let end = record
+        .iter()
+        .rposition(|b| !skip(b))
+        .map_or(start, |i| i + 1);
+    record.truncate(end);
+    record.split_off(start)
+}
+
+/// Stream of the JSON values of a response body, created with
+/// `ClientResponse::json_stream`.
+///
+/// A value which fails to decode yields an error and the stream goes on,
+/// while a body error or a record over the size limit ends it. The body
+/// limit of the client applies to the whole stream, send long-lived streams
+/// with `ClientRequest::max_body_size(None)`.
+pub struct JsonStream<T> {
+    body: Option<HttpBody>,
+    splitter: RecordSplitter,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> JsonStream<T> {
+    pub fn new(body: HttpBody, framing: Framing) -> Self {
+        Self {
+            body: Some(body),
+            splitter: RecordSplitter::new(framing, DEFAULT_MAX_RECORD_SIZE),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Limit the size of a record, 1 MiB by default.
+    pub fn max_record_size(mut self, limit: usize) -> Self {
+        self.splitter.max_record_size = limit;
+        self
+    }
+}
+
+impl<T: DeserializeOwned> Stream for JsonStream<T> {
+    type Item = crate::Result<T>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        let record = loop {
+            match self.splitter.next_record() {
+                Ok(Some(record)) => break record,
+                Ok(None) => {}
+                Err(e) => {
+                    self.body = None;
+                    return Some(Err(e));
+                }
+            }
+            match self.body.as_mut()?.next_data().await {
+                Some(Ok(data)) => self.splitter.feed(&data),
+                Some(Err(e)) => {
+                    self.body = None;
+                    self.splitter.reset();
+                    return Some(Err(crate::Error::from(e).stage(Stage::Body)));
+                }
+                None => {
+                    self.body = None;
+                    break self.splitter.finish()?;
+                }
+            }
+        };
+        Some(serde_json::from_slice(&record).map_err(|e| crate::Error::from(e).stage(Stage::Body)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::{
+        io::{AsyncReadRent, AsyncWriteRentExt},
+        net::TcpListener,
+    };
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::Builder;
+
+    fn records(splitter: &mut RecordSplitter) -> Vec<Bytes> {
+        std::iter::from_fn(|| splitter.next_record().unwrap()).collect()
+    }
+
+    #[test]
+    fn lines() {
+        let mut splitter = RecordSplitter::new(Framing::Lines, 16);
+        splitter.feed(b"{\"a\":1}\r\n\n  \n{\"a\"");
+        assert_eq!(records(&mut splitter), ["{\"a\":1}"]);
+        splitter.feed(b":2}\n[3");
+        assert_eq!(records(&mut splitter), ["{\"a\":2}"]);
+        assert_eq!(splitter.finish().unwrap(), "[3");
+        assert!(splitter.finish().is_none());
+
+        splitter.feed(b"[1,2,3,4,5,6,7,8,9]\n");
+        let err = splitter.next_record().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::RecordTooLarge(16)));
+        splitter.feed(b"[1,2,3,4,5,6,7,8,");
+        assert!(splitter.next_record().is_err());
+    }
+
+    #[test]
+    fn json_seq() {
+        let mut splitter = RecordSplitter::new(Framing::JsonSeq, 64);
+        splitter.feed(b"\x1e{\"a\":1}\n\x1e{\n\"a\": 2\n");
+        assert_eq!(records(&mut splitter), ["{\"a\":1}"]);
+        // A complete text is taken at the line feed.
+        splitter.feed(b"}\n");
+        assert_eq!(records(&mut splitter), ["{\n\"a\": 2\n}"]);
+        splitter.feed(b"\x1e12");
+        assert!(records(&mut splitter).is_empty());
+        splitter.feed(b"3\n\x1e\"b\"");
+        assert_eq!(records(&mut splitter), ["123"]);
+        assert_eq!(splitter.finish().unwrap(), "\"b\"");
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Event {
+        kind: String,
+        n: u32,
+    }
+
+    // Records are split across the chunks.
+    const NDJSON: &str =
+        "HTTP/1.1 200 OK\r\ncontent-type: application/x-ndjson\r\ntransfer-encoding: \
+         chunked\r\n\r\nc\r\n{\"kind\":\"a\",\r\n12\r\n\"n\":1}\n{\"kind\":\"b\"\r\n11\r\n,\"n\":\
+         2}\nnot json\n\r\n10\r\n{\"kind\":\"c\",\"n\":\r\n2\r\n3}\r\n0\r\n\r\n";
+    const JSON_SEQ: &str = "HTTP/1.1 200 OK\r\ncontent-type: \
+                            application/json-seq\r\ncontent-length: \
+                            40\r\n\r\n\x1e{\"kind\":\"a\",\"n\":1}\n\x1e{\"kind\":\"b\",\"n\":2}\n";
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn json_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            loop {
+                let (mut io, _) = listener.accept().await.unwrap();
+                monoio::spawn(async move {
+                    let mut buf = Vec::with_capacity(4096);
+                    loop {
+                        let (r, b) = io.read(buf).await;
+                        buf = b;
+                        if !matches!(r, Ok(n) if n > 0) {
+                            return;
+                        }
+                        let response = match buf.starts_with(b"GET /seq") {
+                            true => JSON_SEQ,
+                            false => NDJSON,
+                        };
+                        buf.clear();
+                        io.write_all(response).await.0.unwrap();
+                    }
+                });
+            }
+        });
+        let client = Builder::new().build();
+
+        let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+        let mut stream = resp.json_stream::<Event>();
+        let event = |kind: &str, n| Event {
+            kind: kind.to_string(),
+            n,
+        };
+        assert_eq!(stream.next().await.unwrap().unwrap(), event("a", 1));
+        assert_eq!(stream.next().await.unwrap().unwrap(), event("b", 2));
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Json(_)));
+        assert_eq!(stream.next().await.unwrap().unwrap(), event("c", 3));
+        assert!(stream.next().await.is_none());
+
+        let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+        let mut stream = resp.json_stream::<Event>().max_record_size(16);
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::RecordTooLarge(16)));
+        assert!(err.is_body());
+        assert!(stream.next().await.is_none());
+
+        let resp = client
+            .get(format!("http://{addr}/seq"))
+            .send()
+            .await
+            .unwrap();
+        let mut stream = resp.json_stream::<Event>();
+        assert_eq!(stream.next().await.unwrap().unwrap(), event("a", 1));
+        assert_eq!(stream.next().await.unwrap().unwrap(), event("b", 2));
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -1,9 +1,33 @@
 mod client;
+#[cfg(feature = "download")]
+pub mod download;
 mod error;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod json_stream;
 mod request;
 mod response;
+pub mod sse;
+#[cfg(feature = "ws")]
+pub mod ws;
 
-pub use client::{connector::Connector, key::Key, unified, Builder, Client, ClientConfig};
-pub use error::{Error, Result};
+#[cfg(feature = "cache-fs")]
+pub use client::cache::DiskStore;
+pub use client::{
+    abort::AbortHandle,
+    balance::{EndpointSet, OutlierDetection, Strategy},
+    breaker::{CircuitBreaker, CircuitState},
+    cache::{CacheStatus, CacheStore, CachedResponse, HttpCache, MemoryStore, StoreFuture},
+    connector::{Connector, SocketOptions},
+    info::{ConnectionInfo, Timings, TlsInfo, TransportInfo},
+    key::Key,
+    listener::{CloseReason, ConnectionListener},
+    rate_limit::{HostPattern, RateLimit},
+    single_flight::SingleFlight,
+    unified, Builder, Client, ClientConfig,
+};
+pub use error::{Context, Error, ErrorKind, Result};
+#[cfg(feature = "encoding")]
+pub use monoio_http::common::body::Encoding;
 pub use request::ClientRequest;
 pub use response::ClientResponse;
@@ -1,10 +1,14 @@
+use std::time::Duration;
+
 use bytes::Bytes;
 use http::{header::HeaderName, request::Builder, HeaderValue, Method, Uri};
+#[cfg(feature = "encoding")]
+use monoio_http::common::body::{Body, CompressedBody, Encoding, StreamHint};
 use monoio_http::common::body::{FixedBody, HttpBody};
 
 use crate::{
     client::{
-        connector::PooledConnector, key::Key, pool::PooledConnection,
+        abort::AbortHandle, connector::PooledConnector, key::Key, pool::PooledConnection,
         unified::UnifiedTransportConnector, Client,
     },
     response::ClientResponse,
@@ -15,16 +19,68 @@ use crate::{
 pub struct ClientRequest<C = UnifiedTransportConnector> {
     client: Client<C>,
     builder: Builder,
+    abort: Option<AbortHandle>,
+    body_limit: Option<usize>,
+    hedge: Option<(Duration, usize)>,
+    single_flight: bool,
+    #[cfg(feature = "encoding")]
+    compress: Option<Encoding>,
 }
 
 impl<C> ClientRequest<C> {
     pub fn new(client: Client<C>) -> Self {
         Self {
+            body_limit: client.max_body_size(),
             client,
             builder: Builder::new(),
+            abort: None,
+            hedge: None,
+            single_flight: true,
+            #[cfg(feature = "encoding")]
+            compress: None,
         }
     }
 
+    /// Get a handle to abort the request after it is sent.
+    pub fn abort_handle(&mut self) -> AbortHandle {
+        self.abort.get_or_insert_with(AbortHandle::new).clone()
+    }
+
+    /// Override the limit of the response body of the client, None for no
+    /// limit.
+    pub fn max_body_size(mut self, limit: Option<usize>) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// If no response head arrives within `after`, send the request again on
+    /// another pooled connection, up to `max_extra` times. The first response
+    /// head wins and the other attempts are aborted.
+    ///
+    /// It is meant for idempotent reads against replicated backends, requests
+    /// of non-idempotent methods are never hedged. Extra attempts are limited
+    /// by `Builder::hedge_budget`.
+    pub fn hedge(mut self, after: Duration, max_extra: usize) -> Self {
+        self.hedge = Some((after, max_extra));
+        self
+    }
+
+    /// Set false to send the request on its own even if
+    /// `Builder::single_flight` is enabled, e.g. for a stream which is read
+    /// as it arrives.
+    pub fn single_flight(mut self, enable: bool) -> Self {
+        self.single_flight = enable;
+        self
+    }
+
+    /// Compress the request body on the fly and set `Content-Encoding`. The
+    /// body is sent chunked, or without a length in http2.
+    #[cfg(feature = "encoding")]
+    pub fn compress(mut self, encoding: Encoding) -> Self {
+        self.compress = Some(encoding);
+        self
+    }
+
     pub fn method<T>(mut self, method: T) -> Self
     where
         Method: TryFrom<T>,
@@ -76,25 +132,257 @@ where
     >,
 {
     pub async fn send(self) -> crate::Result<ClientResponse> {
-        let request = Self::build_request(self.builder, HttpBody::fixed_body(None))?;
-        let resp = self.client.send_request(request).await?;
-        Ok(ClientResponse::new(resp))
+        self.send_with(HttpBody::fixed_body(None)).await
     }
 
     pub async fn send_body(self, data: Bytes) -> crate::Result<ClientResponse> {
-        let request = Self::build_request(self.builder, HttpBody::fixed_body(Some(data)))?;
-        let resp = self.client.send_request(request).await?;
-        Ok(ClientResponse::new(resp))
+        self.send_with(HttpBody::fixed_body(Some(data))).await
     }
 
-    pub async fn send_json<T: serde::Serialize>(self, data: &T) -> crate::Result<ClientResponse> {
+    /// Send a streamed body, e.g. a `Payload::Stream` fed as the data is
+    /// produced. The request is never hedged, the body can't be replayed.
+    pub async fn send_stream(self, body: HttpBody) -> crate::Result<ClientResponse> {
+        self.send_with(body).await
+    }
+
+    pub async fn send_json<T: serde::Serialize>(
+        mut self,
+        data: &T,
+    ) -> crate::Result<ClientResponse> {
         let body: Bytes = serde_json::to_vec(data)?.into();
-        let builder = self.builder.header(
+        self.builder = self.builder.header(
             http::header::CONTENT_TYPE,
             HeaderValue::from_static("application/json"),
         );
-        let request = Self::build_request(builder, HttpBody::fixed_body(Some(body)))?;
-        let resp = self.client.send_request(request).await?;
-        Ok(ClientResponse::new(resp))
+        self.send_with(HttpBody::fixed_body(Some(body))).await
+    }
+
+    async fn send_with(mut self, body: HttpBody) -> crate::Result<ClientResponse> {
+        let request = Self::build_request(std::mem::take(&mut self.builder), body)?;
+        let (client, body_limit) = (self.client.clone(), self.body_limit);
+        let resp = client
+            .send_cached(request, body_limit, |request| async move {
+                match self.single_flight {
+                    true => {
+                        let client = self.client.clone();
+                        client
+                            .send_coalesced(request, body_limit, |request| self.dispatch(request))
+                            .await
+                    }
+                    false => self.dispatch(request).await,
+                }
+            })
+            .await?;
+        Ok(ClientResponse::new(resp).with_body_limit(body_limit))
+    }
+
+    async fn dispatch(
+        self,
+        request: http::Request<HttpBody>,
+    ) -> crate::Result<http::Response<HttpBody>> {
+        let hedge = self.hedge.filter(|(_, max_extra)| {
+            *max_extra > 0
+                && request.method().is_idempotent()
+                && matches!(request.body(), HttpBody::Ready(_))
+        });
+        #[cfg(feature = "encoding")]
+        if let (Some(encoding), true) = (
+            self.compress,
+            request.body().stream_hint() != StreamHint::None,
+        ) {
+            let mut request = request;
+            let headers = request.headers_mut();
+            headers.remove(http::header::CONTENT_LENGTH);
+            headers.insert(
+                http::header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+            return match hedge {
+                Some((after, max_extra)) => {
+                    let make = || replay(&request).map(|body| CompressedBody::new(body, encoding));
+                    self.client
+                        .execute_hedged(make, self.abort, self.body_limit, after, max_extra)
+                        .await
+                }
+                None => {
+                    let request = request.map(|body| CompressedBody::new(body, encoding));
+                    self.client
+                        .execute(request, self.abort, self.body_limit)
+                        .await
+                }
+            };
+        }
+        match hedge {
+            Some((after, max_extra)) => {
+                self.client
+                    .execute_hedged(
+                        || replay(&request),
+                        self.abort,
+                        self.body_limit,
+                        after,
+                        max_extra,
+                    )
+                    .await
+            }
+            None => {
+                self.client
+                    .execute(request, self.abort, self.body_limit)
+                    .await
+            }
+        }
+    }
+}
+
+/// Copy a request with a fixed body, extensions are not copied.
+fn replay(request: &http::Request<HttpBody>) -> http::Request<HttpBody> {
+    let body = match request.body() {
+        HttpBody::Ready(data) => data.clone(),
+        _ => unreachable!("requests are sent with fixed bodies"),
+    };
+    let mut copy = http::Request::new(HttpBody::fixed_body(body));
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    copy
+}
+
+#[cfg(all(test, feature = "encoding"))]
+mod tests {
+    use std::time::Duration;
+
+    use http::header::{CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING};
+    use monoio::{
+        io::{sink::SinkExt, stream::Stream},
+        net::TcpListener,
+    };
+    use monoio_http::{
+        common::body::{BodyEncodeExt, FixedBody, HttpBody},
+        h1::{
+            codec::{decoder::FillPayload, ServerCodec},
+            payload::{stream_payload_pair, Payload},
+        },
+        h2,
+    };
+
+    use crate::{Builder, Encoding};
+
+    // Respond the decompressed request body.
+    async fn serve_h1(listener: TcpListener) {
+        let (io, _) = listener.accept().await.unwrap();
+        let mut codec = ServerCodec::new(io);
+        while let Some(Ok(request)) = codec.next().await {
+            let (parts, payload) = request.into_parts();
+            assert_eq!(parts.headers[TRANSFER_ENCODING], "chunked");
+            let encoding = parts.headers[CONTENT_ENCODING]
+                .to_str()
+                .unwrap()
+                .to_string();
+            codec.fill_payload().await.unwrap();
+            let data = HttpBody::from(payload)
+                .decode_content(encoding)
+                .await
+                .unwrap();
+            let response = http::Response::new(HttpBody::fixed_body(Some(data)));
+            codec.send_and_flush(response).await.unwrap();
+        }
+    }
+
+    async fn serve_h2(listener: TcpListener) {
+        let (io, _) = listener.accept().await.unwrap();
+        let mut conn = h2::server::handshake(io).await.unwrap();
+        while let Some(Ok((request, mut respond))) = conn.accept().await {
+            monoio::spawn(async move {
+                let (parts, mut body) = request.into_parts();
+                assert!(!parts.headers.contains_key(CONTENT_LENGTH));
+                let encoding = parts.headers[CONTENT_ENCODING]
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                let mut data = Vec::new();
+                while let Some(Ok(chunk)) = body.data().await {
+                    let _ = body.flow_control().release_capacity(chunk.len());
+                    data.extend_from_slice(&chunk);
+                }
+                let data = HttpBody::fixed_body(Some(data.into()))
+                    .decode_content(encoding)
+                    .await
+                    .unwrap();
+                let mut send = respond
+                    .send_response(http::Response::new(()), false)
+                    .unwrap();
+                send.send_data(data, true).unwrap();
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn compress() {
+        let records: Vec<_> = (0..2000).map(|i| format!("record {i}")).collect();
+        let expected = serde_json::to_vec(&records).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve_h1(listener));
+        let client = Builder::new().build();
+        for encoding in [Encoding::Gzip, Encoding::Deflate] {
+            let resp = client
+                .post(format!("http://{addr}/"))
+                .compress(encoding)
+                .send_json(&records)
+                .await
+                .unwrap();
+            assert_eq!(resp.bytes().await.unwrap(), expected);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve_h2(listener));
+        let client = Builder::new().http2_client().build();
+        let resp = client
+            .post(format!("http://{addr}/"))
+            .header(CONTENT_LENGTH, expected.len())
+            .compress(Encoding::Br)
+            .send_json(&records)
+            .await
+            .unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), expected);
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn compress_stream() {
+        let stream = || {
+            let (payload, mut sender) = stream_payload_pair();
+            sender.feed_data(Some("hello ".into()));
+            sender.feed_data(Some("world".into()));
+            sender.feed_data(None);
+            Payload::Stream(payload).into()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve_h1(listener));
+        let resp = Builder::new()
+            .build()
+            .post(format!("http://{addr}/"))
+            .compress(Encoding::Gzip)
+            .send_stream(stream())
+            .await
+            .unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "hello world");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve_h2(listener));
+        let resp = Builder::new()
+            .http2_client()
+            .build()
+            .put(format!("http://{addr}/"))
+            .hedge(Duration::from_millis(1), 1)
+            .compress(Encoding::Br)
+            .send_stream(stream())
+            .await
+            .unwrap();
+        assert_eq!(resp.bytes().await.unwrap(), "hello world");
     }
 }
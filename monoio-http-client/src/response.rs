@@ -1,6 +1,15 @@
 use bytes::Bytes;
 use http::{Extensions, HeaderMap, HeaderValue, StatusCode, Version};
-use monoio_http::common::body::{BodyExt, HttpBody};
+use monoio_http::common::{
+    body::{BodyExt, HttpBody},
+    error::HttpError,
+};
+
+use crate::{
+    client::info::Timings,
+    error::Stage,
+    json_stream::{Framing, JsonStream},
+};
 
 pub struct ClientResponse {
     /// The response's status
@@ -13,6 +22,8 @@ pub struct ClientResponse {
     extensions: Extensions,
     /// Payload
     body: HttpBody,
+    /// Limit of the payload
+    body_limit: Option<usize>,
 }
 
 impl ClientResponse {
@@ -24,9 +35,16 @@ impl ClientResponse {
             headers: head.headers,
             extensions: head.extensions,
             body,
+            body_limit: None,
         }
     }
 
+    /// Limit the size of body read by `bytes` and `json`.
+    pub fn with_body_limit(mut self, limit: Option<usize>) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
     /// Get the `StatusCode` of this `Response`.
     #[inline]
     pub fn status(&self) -> StatusCode {
@@ -63,8 +81,29 @@ impl ClientResponse {
 
     /// Get the full response body as `Bytes`.
     pub async fn bytes(self) -> crate::Result<Bytes> {
-        let body = self.body;
-        body.bytes().await.map_err(Into::into)
+        let timings = self.extensions.get::<Timings>().cloned();
+        let bytes = self.read_body().await?;
+        if let Some(timings) = timings {
+            timings.finish_body();
+        }
+        Ok(bytes)
+    }
+
+    async fn read_body(self) -> crate::Result<Bytes> {
+        let limit = match self.body_limit {
+            Some(limit) => limit,
+            None => return self.body.bytes().await.map_err(body_error),
+        };
+        // Fail early without reading the body.
+        let content_length = self
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if matches!(content_length, Some(length) if length > limit as u64) {
+            return Err(crate::ErrorKind::BodyTooLarge(limit).into());
+        }
+        self.body.bytes_limited(limit).await.map_err(body_error)
     }
 
     /// Get raw body(Payload).
@@ -77,8 +116,139 @@ impl ClientResponse {
     // than using from_slice. So here we read the entire content into
     // a Bytes and call from_slice.
     pub async fn json<T: serde::de::DeserializeOwned>(self) -> crate::Result<T> {
-        let bytes = self.body.bytes().await?;
+        let bytes = self.bytes().await?;
         let d = serde_json::from_slice(&bytes)?;
         Ok(d)
     }
+
+    /// Decode the body as a stream of JSON values. Records are delimited as
+    /// in `application/json-seq` if it is the `Content-Type`, and by lines
+    /// otherwise.
+    pub fn json_stream<T: serde::de::DeserializeOwned>(self) -> JsonStream<T> {
+        let json_seq = self
+            .headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| {
+                v.trim_start()
+                    .to_ascii_lowercase()
+                    .starts_with("application/json-seq")
+            });
+        let framing = match json_seq {
+            true => Framing::JsonSeq,
+            false => Framing::Lines,
+        };
+        JsonStream::new(self.body, framing)
+    }
+}
+
+fn body_error(e: HttpError) -> crate::Error {
+    crate::Error::from(e).stage(Stage::Body)
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::{
+        io::{AsyncReadRent, AsyncWriteRentExt},
+        net::TcpListener,
+    };
+    use monoio_http::h2;
+
+    use crate::{Builder, Error, ErrorKind};
+
+    const FIXED: &str = "HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\n0123456789";
+    const CHUNKED: &str = "HTTP/1.1 200 OK\r\ntransfer-encoding: \
+                           chunked\r\n\r\n5\r\n01234\r\n5\r\n56789\r\n0\r\n\r\n";
+
+    async fn serve(listener: TcpListener) {
+        loop {
+            let (mut io, _) = listener.accept().await.unwrap();
+            monoio::spawn(async move {
+                let mut buf = Vec::with_capacity(4096);
+                loop {
+                    let (r, b) = io.read(buf).await;
+                    buf = b;
+                    if !matches!(r, Ok(n) if n > 0) {
+                        return;
+                    }
+                    let response = if buf.starts_with(b"GET /chunked") {
+                        CHUNKED
+                    } else {
+                        FIXED
+                    };
+                    buf.clear();
+                    let (r, _) = io.write_all(response).await;
+                    r.unwrap();
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn body_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve(listener));
+
+        let client = Builder::new().max_body_size(8).build();
+        for path in ["fixed", "chunked"] {
+            let uri = format!("http://{addr}/{path}");
+            let resp = client.get(&uri).send().await.unwrap();
+            let err = resp.bytes().await.unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::BodyTooLarge(8)));
+            assert!(err.is_body());
+
+            // Enforced even if the body is not buffered by `bytes`.
+            let resp = client.get(&uri).send().await.unwrap();
+            let mut body = resp.raw_body();
+            let err = body.to_ready().await.map_err(Error::from).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::BodyTooLarge(8)));
+
+            let resp = client.get(&uri).max_body_size(None).send().await.unwrap();
+            assert_eq!(resp.bytes().await.unwrap(), "0123456789");
+        }
+    }
+
+    async fn serve_h2(listener: TcpListener) {
+        loop {
+            let (io, _) = listener.accept().await.unwrap();
+            monoio::spawn(async move {
+                let mut conn = h2::server::handshake(io).await.unwrap();
+                while let Some(Ok((req, mut respond))) = conn.accept().await {
+                    let mut resp = http::Response::builder();
+                    if req.uri().path() == "/fixed" {
+                        resp = resp.header("content-length", "10");
+                    }
+                    let resp = resp.body(()).unwrap();
+                    let mut send = respond.send_response(resp, false).unwrap();
+                    send.send_data("01234".into(), false).unwrap();
+                    send.send_data("56789".into(), true).unwrap();
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn body_limit_h2() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve_h2(listener));
+
+        let client = Builder::new().http2_client().max_body_size(8).build();
+        for path in ["fixed", "stream"] {
+            let uri = format!("http://{addr}/{path}");
+            let resp = client.get(&uri).send().await.unwrap();
+            let err = resp.bytes().await.unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::BodyTooLarge(8)));
+            assert!(err.is_body());
+
+            let resp = client.get(&uri).send().await.unwrap();
+            let mut body = resp.raw_body();
+            let err = body.to_ready().await.map_err(Error::from).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::BodyTooLarge(8)));
+
+            let resp = client.get(&uri).max_body_size(None).send().await.unwrap();
+            assert_eq!(resp.bytes().await.unwrap(), "0123456789");
+        }
+    }
 }
@@ -0,0 +1,558 @@
+//! Server-Sent Events(text/event-stream) client.
+use std::time::Duration;
+
+use bytes::{Buf, BytesMut};
+use http::{header::HeaderName, HeaderMap, HeaderValue, StatusCode, Uri};
+use monoio::io::stream::Stream;
+use monoio_http::common::body::{Body, HttpBody};
+
+use crate::{
+    client::{
+        connector::PooledConnector, key::Key, pool::PooledConnection,
+        unified::UnifiedTransportConnector, Client,
+    },
+    error::Stage,
+    unified::UnifiedTransportConnection,
+    Connector, ErrorKind,
+};
+
+const DEFAULT_MAX_EVENT_SIZE: usize = 1 << 20;
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+const LAST_EVENT_ID: HeaderName = HeaderName::from_static("last-event-id");
+const EVENT_STREAM: &str = "text/event-stream";
+
+/// A dispatched event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Event type, `message` if the server does not specify one.
+    pub event: String,
+    /// Event data, multiple data lines are joined with `\n`.
+    pub data: String,
+    /// The last event id when the event is dispatched.
+    pub id: String,
+}
+
+/// Incremental parser of the event stream format.
+#[derive(Debug)]
+pub struct EventParser {
+    buf: BytesMut,
+    // If the last line ends with \r, the following \n should be skipped.
+    skip_lf: bool,
+    // The BOM is only allowed at the start of the stream.
+    started: bool,
+    event: String,
+    data: String,
+    last_event_id: String,
+    retry: Option<Duration>,
+    max_event_size: usize,
+}
+
+impl Default for EventParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventParser {
+    pub fn new() -> Self {
+        Self::with_max_event_size(DEFAULT_MAX_EVENT_SIZE)
+    }
+
+    /// Create a parser which fails once a line or the data of an event
+    /// exceeds the limit.
+    pub fn with_max_event_size(max_event_size: usize) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            skip_lf: false,
+            started: false,
+            event: String::new(),
+            data: String::new(),
+            last_event_id: String::new(),
+            retry: None,
+            max_event_size,
+        }
+    }
+
+    /// Feed a chunk of the stream.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Parse buffered lines until an event is dispatched. Fails if a line or
+    /// the data of an event exceeds the size limit.
+    pub fn next_event(&mut self) -> crate::Result<Option<Event>> {
+        loop {
+            if self.skip_lf && !self.buf.is_empty() {
+                if self.buf[0] == b'\n' {
+                    self.buf.advance(1);
+                }
+                self.skip_lf = false;
+            }
+            if !self.started {
+                if self.buf.len() < 3 && b"\xEF\xBB\xBF".starts_with(&self.buf) {
+                    return Ok(None);
+                }
+                if self.buf.starts_with(b"\xEF\xBB\xBF") {
+                    self.buf.advance(3);
+                }
+                self.started = true;
+            }
+
+            let end = match self.buf.iter().position(|b| *b == b'\r' || *b == b'\n') {
+                Some(end) => end,
+                None if self.buf.len() > self.max_event_size => return Err(self.too_large()),
+                None => return Ok(None),
+            };
+            if end > self.max_event_size {
+                return Err(self.too_large());
+            }
+            self.skip_lf = self.buf[end] == b'\r';
+            let line = self.buf.split_to(end);
+            self.buf.advance(1);
+
+            if line.is_empty() {
+                if let Some(event) = self.dispatch() {
+                    return Ok(Some(event));
+                }
+                continue;
+            }
+            self.process_line(&String::from_utf8_lossy(&line));
+            if self.data.len() > self.max_event_size {
+                return Err(self.too_large());
+            }
+        }
+    }
+
+    /// Take the reconnection time sent by the server.
+    #[inline]
+    pub fn take_retry(&mut self) -> Option<Duration> {
+        self.retry.take()
+    }
+
+    /// The last event id sent by the server.
+    #[inline]
+    pub fn last_event_id(&self) -> &str {
+        &self.last_event_id
+    }
+
+    /// Discard the incomplete data, it is called when the connection is lost.
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.skip_lf = false;
+        self.started = false;
+        self.event.clear();
+        self.data.clear();
+    }
+
+    fn too_large(&mut self) -> crate::Error {
+        self.reset();
+        crate::Error::new(ErrorKind::EventTooLarge(self.max_event_size), Stage::Body)
+    }
+
+    fn process_line(&mut self, line: &str) {
+        if line.starts_with(':') {
+            // comment
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => {
+                self.event.clear();
+                self.event.push_str(value);
+            }
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => {
+                self.last_event_id.clear();
+                self.last_event_id.push_str(value);
+            }
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(ms) = value.parse() {
+                    self.retry = Some(Duration::from_millis(ms));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        if self.data.is_empty() {
+            self.event.clear();
+            return None;
+        }
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        let event = match std::mem::take(&mut self.event) {
+            e if e.is_empty() => "message".to_string(),
+            e => e,
+        };
+        Some(Event {
+            event,
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+/// EventSource connects to an event stream and reconnects automatically
+/// when the connection is lost.
+///
+/// Events are delivered as a `Stream`. The stream ends if the server responds
+/// with `204 No Content`, or with an error if the response is not a valid
+/// event stream.
+///
+/// A line or the data of an event over the size limit fails the stream, it
+/// is not reconnected.
+///
+/// Reconnections are delayed with the `time` feature. Without it, a failed
+/// reconnection is returned as an error, the stream reconnects again when it
+/// is polled next.
+pub struct EventSource<C = UnifiedTransportConnector> {
+    client: Client<C>,
+    uri: Uri,
+    headers: HeaderMap,
+    parser: EventParser,
+    body: Option<HttpBody>,
+    retry: Duration,
+    connected: bool,
+    closed: bool,
+}
+
+impl<C> EventSource<C> {
+    pub fn new(client: Client<C>, uri: Uri) -> Self {
+        Self {
+            client,
+            uri,
+            headers: HeaderMap::new(),
+            parser: EventParser::new(),
+            body: None,
+            retry: DEFAULT_RETRY,
+            connected: false,
+            closed: false,
+        }
+    }
+
+    /// Add a header to the requests.
+    pub fn header(mut self, key: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(key, value);
+        self
+    }
+
+    /// Set the initial reconnection time, the server may override it.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Limit the size of a line and of the data of an event, 1 MiB by
+    /// default.
+    pub fn max_event_size(mut self, limit: usize) -> Self {
+        self.parser.max_event_size = limit;
+        self
+    }
+
+    /// The last event id, it is sent as `Last-Event-ID` on reconnection.
+    #[inline]
+    pub fn last_event_id(&self) -> &str {
+        self.parser.last_event_id()
+    }
+
+    /// Close the event source, the stream will end.
+    pub fn close(&mut self) {
+        self.body = None;
+        self.closed = true;
+    }
+}
+
+impl<C> EventSource<C>
+where
+    PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+        Key,
+        Connection = PooledConnection<Key, UnifiedTransportConnection>,
+        Error = crate::Error,
+    >,
+{
+    async fn connect(&mut self) -> crate::Result<Option<HttpBody>> {
+        let mut req = self
+            .client
+            .get(self.uri.clone())
+            // The stream is not buffered, and it may never end.
+            .max_body_size(None)
+            .single_flight(false)
+            .header(http::header::ACCEPT, HeaderValue::from_static(EVENT_STREAM))
+            .header(
+                http::header::CACHE_CONTROL,
+                HeaderValue::from_static("no-cache"),
+            );
+        for (key, value) in self.headers.iter() {
+            req = req.header(key, value);
+        }
+        if !self.parser.last_event_id().is_empty() {
+            req = req.header(LAST_EVENT_ID, self.parser.last_event_id());
+        }
+        let resp = req.send().await?;
+        match resp.status() {
+            StatusCode::OK => {}
+            StatusCode::NO_CONTENT => return Ok(None),
+            status => return Err(crate::ErrorKind::EventStreamStatus(status).into()),
+        }
+        let is_event_stream = resp
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim_start().starts_with(EVENT_STREAM))
+            .unwrap_or(false);
+        if !is_event_stream {
+            return Err(crate::ErrorKind::EventStreamContentType.into());
+        }
+        Ok(Some(resp.raw_body()))
+    }
+}
+
+impl<C> Stream for EventSource<C>
+where
+    PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+        Key,
+        Connection = PooledConnection<Key, UnifiedTransportConnection>,
+        Error = crate::Error,
+    >,
+{
+    type Item = crate::Result<Event>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.closed {
+                return None;
+            }
+            match self.parser.next_event() {
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.close();
+                    return Some(Err(e));
+                }
+            }
+            if let Some(retry) = self.parser.take_retry() {
+                self.retry = retry;
+            }
+
+            let body = match self.body.as_mut() {
+                Some(body) => body,
+                None => {
+                    if self.connected {
+                        #[cfg(feature = "time")]
+                        monoio::time::sleep(self.retry).await;
+                    }
+                    self.connected = true;
+                    match self.connect().await {
+                        Ok(Some(body)) => self.body.insert(body),
+                        Ok(None) => {
+                            self.closed = true;
+                            return None;
+                        }
+                        Err(e)
+                            if matches!(
+                                e.kind(),
+                                crate::ErrorKind::EventStreamStatus(_)
+                                    | crate::ErrorKind::EventStreamContentType
+                            ) =>
+                        {
+                            self.closed = true;
+                            return Some(Err(e));
+                        }
+                        // Failed to establish the connection, retry later.
+                        #[cfg(feature = "time")]
+                        Err(_e) => {
+                            #[cfg(feature = "logging")]
+                            tracing::warn!("event source connect error {_e:?}");
+                            continue;
+                        }
+                        // Retrying at once would spin without the time driver.
+                        #[cfg(not(feature = "time"))]
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+            };
+
+            match body.next_data().await {
+                Some(Ok(data)) => self.parser.feed(&data),
+                Some(Err(_e)) => {
+                    #[cfg(feature = "logging")]
+                    tracing::warn!("event source read error {_e:?}");
+                    self.body = None;
+                    self.parser.reset();
+                }
+                None => {
+                    self.body = None;
+                    self.parser.reset();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "time")]
+    use std::{cell::RefCell, rc::Rc, time::Instant};
+
+    #[cfg(feature = "time")]
+    use monoio::{
+        io::{AsyncReadRent, AsyncWriteRentExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    fn parse_all(parser: &mut EventParser) -> Vec<Event> {
+        std::iter::from_fn(|| parser.next_event().unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_events() {
+        let mut parser = EventParser::new();
+        parser.feed(b": comment\nevent: add\ndata: a\ndata:b\nid: 1\n\ndata: c\n\n");
+        let events = parse_all(&mut parser);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "add");
+        assert_eq!(events[0].data, "a\nb");
+        assert_eq!(events[0].id, "1");
+        assert_eq!(events[1].event, "message");
+        assert_eq!(events[1].data, "c");
+        assert_eq!(events[1].id, "1");
+    }
+
+    #[test]
+    fn parse_split_chunks() {
+        let mut parser = EventParser::new();
+        parser.feed(b"\xEF\xBB");
+        assert!(parser.next_event().unwrap().is_none());
+        parser.feed(b"\xBFdata: hel");
+        assert!(parser.next_event().unwrap().is_none());
+        parser.feed(b"lo\r");
+        assert!(parser.next_event().unwrap().is_none());
+        parser.feed(b"\n\r\n");
+        assert_eq!(parser.next_event().unwrap().unwrap().data, "hello");
+    }
+
+    #[test]
+    fn parse_retry_and_empty_event() {
+        let mut parser = EventParser::new();
+        parser.feed(b"retry: 1500\n\nretry: x\nevent: e\n\n");
+        assert!(parse_all(&mut parser).is_empty());
+        assert_eq!(parser.take_retry(), Some(Duration::from_millis(1500)));
+        assert_eq!(parser.take_retry(), None);
+    }
+
+    #[test]
+    fn parse_too_large() {
+        let mut parser = EventParser::with_max_event_size(8);
+        parser.feed(b"data: 123456789");
+        let err = parser.next_event().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::EventTooLarge(8)));
+
+        // Lines within the limit whose data is over it.
+        parser.feed(b"data: 12345\ndata: 12345\n\ndata: a\n\n");
+        let err = parser.next_event().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::EventTooLarge(8)));
+        assert!(parser.next_event().unwrap().is_none());
+    }
+
+    // Arrival and `Last-Event-ID` of the requests.
+    #[cfg(feature = "time")]
+    type Requests = Rc<RefCell<Vec<(Instant, Option<String>)>>>;
+
+    // Respond the bodies in turn with `connection: close`.
+    #[cfg(feature = "time")]
+    async fn serve(listener: TcpListener, bodies: &'static [&'static str], requests: Requests) {
+        for body in bodies {
+            let (mut io, _) = listener.accept().await.unwrap();
+            let (r, buf) = io.read(Vec::with_capacity(4096)).await;
+            r.unwrap();
+            let head = String::from_utf8(buf).unwrap();
+            let last_event_id = head
+                .lines()
+                .find_map(|l| l.strip_prefix("last-event-id: "))
+                .map(str::to_string);
+            requests.borrow_mut().push((Instant::now(), last_event_id));
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: \
+                 close\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            io.write_all(resp.into_bytes()).await.0.unwrap();
+        }
+    }
+
+    #[cfg(feature = "time")]
+    #[monoio::test_all(enable_timer = true)]
+    async fn reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Default::default();
+        monoio::spawn(serve(
+            listener,
+            &["retry: 50\nid: 7\ndata: a\n\n", "data: b\n\n"],
+            Rc::clone(&requests),
+        ));
+
+        let client = crate::Builder::new().build();
+        let mut events = client.event_source(format!("http://{addr}/").parse().unwrap());
+        assert_eq!(events.next().await.unwrap().unwrap().data, "a");
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!((event.data.as_str(), event.id.as_str()), ("b", "7"));
+
+        let requests = requests.borrow();
+        assert_eq!(requests[0].1, None);
+        assert_eq!(requests[1].1.as_deref(), Some("7"));
+        // Reconnected after the retry sent by the server, not the default.
+        let delay = requests[1].0 - requests[0].0;
+        assert!(
+            delay >= Duration::from_millis(50) && delay < DEFAULT_RETRY,
+            "{delay:?}"
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[monoio::test_all(enable_timer = true)]
+    async fn event_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Default::default();
+        monoio::spawn(serve(
+            listener,
+            &["data: a\n\ndata: 123456789\n\n"],
+            Rc::clone(&requests),
+        ));
+
+        let client = crate::Builder::new().build();
+        let mut events = client
+            .event_source(format!("http://{addr}/").parse().unwrap())
+            .max_event_size(8);
+        assert_eq!(events.next().await.unwrap().unwrap().data, "a");
+        let err = events.next().await.unwrap().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::EventTooLarge(8)));
+        // Not reconnected.
+        assert!(events.next().await.is_none());
+        assert_eq!(requests.borrow().len(), 1);
+    }
+
+    #[cfg(not(feature = "time"))]
+    #[monoio::test_all]
+    async fn connect_error_without_timer() {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let client = crate::Builder::new().build();
+        let mut events = client.event_source(format!("http://{closed}/").parse().unwrap());
+        // Returned rather than retried at once.
+        assert!(events.next().await.unwrap().unwrap_err().is_connect());
+        assert!(events.next().await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,545 @@
+//! WebSocket client over HTTP/1.1 upgrade or HTTP/2 extended CONNECT.
+use bytes::BytesMut;
+use http::{header, uri::Scheme, HeaderMap, HeaderValue, StatusCode, Uri};
+use monoio::io::{sink::SinkExt, stream::Stream, AsyncReadRent, AsyncWriteRent, Split};
+#[cfg(feature = "ws-deflate")]
+pub use monoio_http::ws::deflate::DeflateConfig;
+pub use monoio_http::ws::{
+    close_code, h2::H2Stream, CloseFrame, Config, Message, WebSocket, WsError,
+};
+use monoio_http::{
+    common::body::{FixedBody, HttpBody},
+    h1::codec::ClientCodec,
+    ws::{handshake, Role},
+};
+
+use crate::{
+    client::{
+        connector::PooledConnector, key::Key, pool::PooledConnection,
+        unified::UnifiedTransportConnector, Client,
+    },
+    unified::UnifiedTransportConnection,
+    Connector,
+};
+
+/// Builder of a WebSocket connection.
+///
+/// `connect` dials with the transport connector of the client, bypassing the
+/// pool. `handshake` can be used to run the handshake on any connection,
+/// e.g. a unix socket. `connect_h2` opens a stream on a pooled HTTP/2
+/// connection, so many WebSockets to the same server share one connection.
+pub struct WebSocketRequest<C = UnifiedTransportConnector> {
+    client: Client<C>,
+    uri: Uri,
+    headers: HeaderMap,
+    protocols: Vec<String>,
+    config: Config,
+    #[cfg(feature = "ws-deflate")]
+    deflate: Option<DeflateConfig>,
+}
+
+impl<C> WebSocketRequest<C> {
+    pub fn new(client: Client<C>, uri: Uri) -> Self {
+        Self {
+            client,
+            uri,
+            headers: HeaderMap::new(),
+            protocols: Vec::new(),
+            config: Config::default(),
+            #[cfg(feature = "ws-deflate")]
+            deflate: None,
+        }
+    }
+
+    /// Add a header to the upgrade request.
+    pub fn header(mut self, key: header::HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(key, value);
+        self
+    }
+
+    /// Offer a subprotocol, the server may select one of the offered.
+    pub fn protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.protocols.push(protocol.into());
+        self
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Offer permessage-deflate.
+    #[cfg(feature = "ws-deflate")]
+    pub fn deflate(mut self, deflate: DeflateConfig) -> Self {
+        self.deflate = Some(deflate);
+        self
+    }
+
+    // Headers shared by both HTTP/1.1 and HTTP/2 handshakes.
+    fn build_request<B>(&self, uri: Uri, body: B) -> crate::Result<http::Request<B>> {
+        let mut builder = http::Request::get(uri);
+        for (name, value) in self.client.default_headers().iter() {
+            builder = builder.header(name, value);
+        }
+        let mut req = builder.body(body)?;
+        let headers = req.headers_mut();
+        for (name, value) in self.headers.iter() {
+            headers.append(name, value.clone());
+        }
+        if !self.protocols.is_empty() {
+            let protocols =
+                HeaderValue::try_from(self.protocols.join(", ")).map_err(http::Error::from)?;
+            headers.insert(header::SEC_WEBSOCKET_PROTOCOL, protocols);
+        }
+        #[cfg(feature = "ws-deflate")]
+        if let Some(deflate) = self.deflate.as_ref() {
+            headers.insert(header::SEC_WEBSOCKET_EXTENSIONS, deflate.offer());
+        }
+        Ok(req)
+    }
+
+    fn build_h1_request(&self, key: &HeaderValue) -> crate::Result<http::Request<HttpBody>> {
+        let mut req = self.build_request(self.uri.clone(), HttpBody::fixed_body(None))?;
+        *req.version_mut() = http::Version::HTTP_11;
+        let headers = req.headers_mut();
+        if let Some(host) = self.uri.host() {
+            if !headers.contains_key(header::HOST) {
+                let host = match self.uri.port() {
+                    Some(port) => HeaderValue::try_from(format!("{host}:{port}")),
+                    None => HeaderValue::try_from(host),
+                };
+                headers.insert(header::HOST, host.map_err(http::Error::from)?);
+            }
+        }
+        headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(header::SEC_WEBSOCKET_KEY, key.clone());
+        headers.insert(header::SEC_WEBSOCKET_VERSION, handshake::VERSION);
+        Ok(req)
+    }
+
+    // Validate the negotiated subprotocol and extensions, and create the socket.
+    fn finish<IO>(
+        &self,
+        io: IO,
+        buf: BytesMut,
+        headers: &HeaderMap,
+    ) -> crate::Result<WebSocket<IO>> {
+        if let Some(protocol) = headers.get(header::SEC_WEBSOCKET_PROTOCOL) {
+            let offered = protocol
+                .to_str()
+                .map(|p| self.protocols.iter().any(|o| o == p))
+                .unwrap_or(false);
+            if !offered {
+                return Err(WsError::Handshake("unexpected sec-websocket-protocol").into());
+            }
+        }
+        #[cfg(feature = "ws-deflate")]
+        let deflate = match headers.get(header::SEC_WEBSOCKET_EXTENSIONS) {
+            Some(_) if self.deflate.is_none() => {
+                return Err(WsError::Handshake("unexpected sec-websocket-extensions").into())
+            }
+            Some(ext) => DeflateConfig::from_response(ext)?,
+            None => None,
+        };
+        #[cfg(not(feature = "ws-deflate"))]
+        if headers.contains_key(header::SEC_WEBSOCKET_EXTENSIONS) {
+            return Err(WsError::Handshake("unexpected sec-websocket-extensions").into());
+        }
+
+        #[allow(unused_mut)]
+        let mut ws = WebSocket::from_partially_read(io, buf, Role::Client, self.config);
+        #[cfg(feature = "ws-deflate")]
+        if let Some(deflate) = deflate {
+            ws.set_deflate(monoio_http::ws::deflate::DeflateContext::new(
+                deflate,
+                Role::Client,
+            ));
+        }
+        Ok(ws)
+    }
+
+    /// Run the handshake on an established connection.
+    pub async fn handshake<IO>(&self, io: IO) -> crate::Result<(WebSocket<IO>, http::Response<()>)>
+    where
+        IO: AsyncReadRent + AsyncWriteRent + Split,
+    {
+        let key = handshake::generate_key();
+        let request = self.build_h1_request(&key)?;
+
+        let mut codec = ClientCodec::new(io);
+        codec.send_and_flush(request).await?;
+        let (parts, _) = match codec.next().await {
+            Some(resp) => resp?.into_parts(),
+            None => return Err(monoio_http::h1::codec::decoder::DecodeError::UnexpectedEof.into()),
+        };
+        if parts.status != StatusCode::SWITCHING_PROTOCOLS {
+            return Err(crate::ErrorKind::WebSocketStatus(parts.status).into());
+        }
+        handshake::verify_response(&parts.headers, &key)?;
+
+        let (io, buf) = codec.into_io();
+        let ws = self.finish(io, buf, &parts.headers)?;
+        Ok((ws, http::Response::from_parts(parts, ())))
+    }
+
+    /// Connect to the server and run the handshake.
+    pub async fn connect<IO>(&self) -> crate::Result<(WebSocket<IO>, http::Response<()>)>
+    where
+        C: Connector<Key, Connection = IO>,
+        crate::Error: From<C::Error>,
+        IO: AsyncReadRent + AsyncWriteRent + Split,
+    {
+        let key = Key::try_from(&self.uri).map_err(crate::ErrorKind::FromUri)?;
+        let io = self
+            .client
+            .pooled_connector()
+            .transport_connector()
+            .connect(key)
+            .await
+            .map_err(|e| crate::Error::from(e).stage(crate::error::Stage::Connect))?;
+        self.handshake(io).await
+    }
+}
+
+impl<C> WebSocketRequest<C>
+where
+    PooledConnector<C, Key, UnifiedTransportConnection>: Connector<
+        Key,
+        Connection = PooledConnection<Key, UnifiedTransportConnection>,
+        Error = crate::Error,
+    >,
+{
+    /// Open the WebSocket on a pooled HTTP/2 connection with extended
+    /// CONNECT(RFC 8441).
+    ///
+    /// The client must be built with `http2_client` or `http_auto`, and the
+    /// server must enable extended CONNECT.
+    pub async fn connect_h2(&self) -> crate::Result<(WebSocket<H2Stream>, http::Response<()>)> {
+        // :scheme of the CONNECT request is http or https.
+        let mut parts = self.uri.clone().into_parts();
+        parts.scheme = match parts.scheme {
+            Some(scheme) if scheme.as_str() == "wss" => Some(Scheme::HTTPS),
+            Some(scheme) if scheme.as_str() == "ws" => Some(Scheme::HTTP),
+            scheme => scheme,
+        };
+        let uri = Uri::from_parts(parts).map_err(http::Error::from)?;
+
+        let send_request = self.client.http2_send_request(&uri).await?;
+
+        let request = self.build_request(uri, ())?;
+        let (response, io) = monoio_http::ws::h2::connect(send_request, request).await?;
+        if !response.status().is_success() {
+            return Err(crate::ErrorKind::WebSocketStatus(response.status()).into());
+        }
+        let ws = self.finish(io, BytesMut::new(), response.headers())?;
+        Ok((ws, response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use bytes::Bytes;
+    use http::HeaderName;
+    use monoio::{
+        io::{sink::Sink, AsyncWriteRentExt},
+        net::TcpListener,
+    };
+    use monoio_http::h2;
+
+    use super::*;
+    use crate::{Builder, ErrorKind};
+
+    // Read the upgrade request and respond 101 with the headers built from
+    // it, then echo text messages.
+    async fn serve<IO>(mut io: IO, respond: impl FnOnce(&HeaderMap) -> HeaderMap)
+    where
+        IO: AsyncReadRent + AsyncWriteRent,
+    {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let (n, buf) = io.read(Vec::with_capacity(4096)).await;
+            if n.unwrap() == 0 {
+                return;
+            }
+            head.extend_from_slice(&buf);
+        }
+        let head = String::from_utf8(head).unwrap();
+        let mut headers = HeaderMap::new();
+        for line in head.lines().skip(1).filter(|l| !l.is_empty()) {
+            let (name, value) = line.split_once(':').unwrap();
+            headers.append(
+                HeaderName::try_from(name).unwrap(),
+                value.trim().parse().unwrap(),
+            );
+        }
+        let resp_headers = respond(&headers);
+        let mut resp = "HTTP/1.1 101 Switching Protocols\r\nupgrade: websocket\r\nconnection: \
+                        Upgrade\r\n"
+            .to_string();
+        for (name, value) in resp_headers.iter() {
+            resp.push_str(&format!("{name}: {}\r\n", value.to_str().unwrap()));
+        }
+        resp.push_str("\r\n");
+        io.write_all(resp.into_bytes()).await.0.unwrap();
+
+        #[allow(unused_mut)]
+        let mut ws = WebSocket::from_raw(io, Role::Server, Config::default());
+        #[cfg(feature = "ws-deflate")]
+        if let Some(ext) = resp_headers.get(header::SEC_WEBSOCKET_EXTENSIONS) {
+            let deflate = DeflateConfig::from_response(ext).unwrap().unwrap();
+            ws.set_deflate(monoio_http::ws::deflate::DeflateContext::new(
+                deflate,
+                Role::Server,
+            ));
+        }
+        while let Some(Ok(msg)) = ws.next().await {
+            if let Message::Text(_) = msg {
+                ws.send_and_flush(msg).await.unwrap();
+            }
+        }
+    }
+
+    // Accept the handshake without subprotocol or extensions.
+    fn accept(headers: &HeaderMap) -> HeaderMap {
+        let mut resp = HeaderMap::new();
+        let accept = handshake::verify_request(headers).unwrap();
+        resp.insert(header::SEC_WEBSOCKET_ACCEPT, accept);
+        resp
+    }
+
+    fn start(respond: fn(&HeaderMap) -> HeaderMap) -> Uri {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (io, _) = listener.accept().await.unwrap();
+            serve(io, respond).await;
+        });
+        format!("ws://{addr}/chat").parse().unwrap()
+    }
+
+    async fn echo<IO: AsyncReadRent + AsyncWriteRent>(ws: &mut WebSocket<IO>) {
+        ws.send_and_flush(Message::Text("hello".into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Text("hello".into())
+        );
+        Sink::<Message>::close(ws).await.unwrap();
+        assert!(matches!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Close(_)
+        ));
+    }
+
+    fn handshake_error<T>(result: crate::Result<T>) -> &'static str {
+        let err = match result {
+            Ok(_) => panic!("handshake succeeded"),
+            Err(e) => e,
+        };
+        match err.kind() {
+            ErrorKind::WebSocket(WsError::Handshake(reason)) => reason,
+            _ => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn accept_key() {
+        let client = Builder::new().build();
+        let uri = start(accept);
+        let (mut ws, resp) = client.websocket(uri).connect().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        echo(&mut ws).await;
+
+        // The accept key must be derived from the key sent.
+        let uri = start(|_| {
+            let mut resp = HeaderMap::new();
+            let accept = handshake::derive_accept_key(b"dGhlIHNhbXBsZSBub25jZQ==");
+            resp.insert(header::SEC_WEBSOCKET_ACCEPT, accept);
+            resp
+        });
+        let reason = handshake_error(client.websocket(uri).connect().await);
+        assert_eq!(reason, "invalid sec-websocket-accept");
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn negotiate() {
+        let uri = start(|headers| {
+            let mut resp = accept(headers);
+            assert_eq!(headers[header::SEC_WEBSOCKET_PROTOCOL], "chat, superchat");
+            resp.insert(
+                header::SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static("superchat"),
+            );
+            #[cfg(feature = "ws-deflate")]
+            {
+                let offer = &headers[header::SEC_WEBSOCKET_EXTENSIONS];
+                let (_, ext) = DeflateConfig::accept(offer).unwrap();
+                resp.insert(header::SEC_WEBSOCKET_EXTENSIONS, ext);
+            }
+            resp
+        });
+        let request = Builder::new()
+            .build()
+            .websocket(uri)
+            .protocol("chat")
+            .protocol("superchat");
+        #[cfg(feature = "ws-deflate")]
+        let request = request.deflate(DeflateConfig {
+            client_no_context_takeover: true,
+            server_no_context_takeover: false,
+        });
+        let (mut ws, resp) = request.connect().await.unwrap();
+        assert_eq!(resp.headers()[header::SEC_WEBSOCKET_PROTOCOL], "superchat");
+        #[cfg(feature = "ws-deflate")]
+        assert_eq!(
+            resp.headers()[header::SEC_WEBSOCKET_EXTENSIONS],
+            "permessage-deflate; client_no_context_takeover"
+        );
+        echo(&mut ws).await;
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn unrequested() {
+        let client = Builder::new().build();
+
+        // A subprotocol that is not offered.
+        let uri = start(|headers| {
+            let mut resp = accept(headers);
+            resp.insert(
+                header::SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static("superchat"),
+            );
+            resp
+        });
+        let request = client.websocket(uri).protocol("chat");
+        let reason = handshake_error(request.connect().await);
+        assert_eq!(reason, "unexpected sec-websocket-protocol");
+
+        // An extension while none is offered.
+        let uri = start(|headers| {
+            let mut resp = accept(headers);
+            assert!(!headers.contains_key(header::SEC_WEBSOCKET_EXTENSIONS));
+            resp.insert(
+                header::SEC_WEBSOCKET_EXTENSIONS,
+                HeaderValue::from_static("permessage-deflate"),
+            );
+            resp
+        });
+        let reason = handshake_error(client.websocket(uri).connect().await);
+        assert_eq!(reason, "unexpected sec-websocket-extensions");
+    }
+
+    #[cfg(not(feature = "native-tls"))]
+    #[monoio::test_all(enable_timer = true)]
+    async fn connect_tls() {
+        use std::sync::Arc;
+
+        use crate::client::unified::UnifiedTransportConnector;
+
+        // The certificate covers `localhost` and `127.0.0.1`.
+        const CERT: &[u8] = include_bytes!("../tests/certs/coalesce.crt.der");
+        const KEY: &[u8] = include_bytes!("../tests/certs/coalesce.key.der");
+
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(CERT.to_vec())],
+                rustls::PrivateKey(KEY.to_vec()),
+            )
+            .unwrap();
+        let acceptor = monoio_rustls::TlsAcceptor::from(config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        monoio::spawn(async move {
+            let (io, _) = listener.accept().await.unwrap();
+            let io = acceptor.accept(io).await.unwrap();
+            serve(io, accept).await;
+        });
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&rustls::Certificate(CERT.to_vec())).unwrap();
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let client = Builder::new()
+            .build_with_connector(UnifiedTransportConnector::with_tls_config(Arc::new(config)));
+        let uri = format!("wss://localhost:{port}/chat").parse().unwrap();
+        let (mut ws, _) = client.websocket(uri).connect().await.unwrap();
+        echo(&mut ws).await;
+    }
+
+    // Echo WebSockets over http2, counting the accepted connections.
+    async fn serve_h2(listener: TcpListener, accepted: Rc<Cell<usize>>, connect_protocol: bool) {
+        loop {
+            let (io, _) = listener.accept().await.unwrap();
+            accepted.set(accepted.get() + 1);
+            monoio::spawn(async move {
+                let mut builder = h2::server::Builder::new();
+                if connect_protocol {
+                    builder.enable_connect_protocol();
+                }
+                let mut conn = builder.handshake::<_, Bytes>(io).await.unwrap();
+                while let Some(Ok((request, respond))) = conn.accept().await {
+                    let io =
+                        monoio_http::ws::h2::accept(request, respond, HeaderMap::new()).unwrap();
+                    monoio::spawn(async move {
+                        let mut ws = WebSocket::from_raw(io, Role::Server, Config::default());
+                        while let Some(Ok(msg)) = ws.next().await {
+                            if let Message::Text(_) = msg {
+                                ws.send_and_flush(msg).await.unwrap();
+                            }
+                        }
+                    });
+                }
+            });
+        }
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn connect_h2_pooled() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        monoio::spawn(serve_h2(listener, accepted.clone(), true));
+
+        let client = Builder::new().http2_client().build();
+        let uri: Uri = format!("ws://{addr}/chat").parse().unwrap();
+        let (mut first, resp) = client.websocket(uri.clone()).connect_h2().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (mut second, _) = client.websocket(uri).connect_h2().await.unwrap();
+        assert_eq!(accepted.get(), 1);
+
+        // Both are open at once on the same connection.
+        for (i, ws) in [&mut first, &mut second].into_iter().enumerate() {
+            ws.send_and_flush(Message::Text(format!("hello {i}")))
+                .await
+                .unwrap();
+        }
+        for (i, ws) in [&mut first, &mut second].into_iter().enumerate() {
+            assert_eq!(
+                ws.next().await.unwrap().unwrap(),
+                Message::Text(format!("hello {i}"))
+            );
+        }
+        echo(&mut second).await;
+        echo(&mut first).await;
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn connect_h2_not_enabled() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(serve_h2(listener, Default::default(), false));
+
+        let client = Builder::new().http2_client().build();
+        let uri = format!("ws://{addr}/chat").parse().unwrap();
+        let reason = handshake_error(client.websocket(uri).connect_h2().await);
+        assert_eq!(reason, "extended CONNECT is not enabled by peer");
+    }
+}
@@ -6,11 +6,12 @@ use std::{
 
 use bytes::{Bytes, BytesMut};
 use futures_core::Future;
+use http::HeaderMap;
 use monoio::buf::IoBuf;
 use monoio_compat::box_future::MaybeArmedBoxFuture;
 use smallvec::SmallVec;
 
-use super::error::HttpError;
+use super::error::{BodyTooLarge, HttpError};
 #[cfg(feature = "parsed")]
 use super::parsed::multipart::ParsedMultiPartForm;
 use crate::{
@@ -32,6 +33,12 @@ pub trait Body {
 
     fn next_data(&mut self) -> impl Future<Output = Option<Result<Self::Data, Self::Error>>>;
     fn stream_hint(&self) -> StreamHint;
+
+    /// Returns the trailers of the body, if any.
+    /// It should be called after `next_data` returns `None`.
+    fn trailers(&mut self) -> impl Future<Output = Result<Option<HeaderMap>, Self::Error>> {
+        async { Ok(None) }
+    }
 }
 
 impl Body for () {
@@ -60,6 +67,11 @@ impl<T: Body> Body for &mut T {
     fn stream_hint(&self) -> StreamHint {
         (**self).stream_hint()
     }
+
+    #[inline]
+    fn trailers(&mut self) -> impl Future<Output = Result<Option<HeaderMap>, Self::Error>> {
+        (**self).trailers()
+    }
 }
 
 pub type Chunks = SmallVec<[Bytes; 16]>;
@@ -69,6 +81,15 @@ pub trait BodyExt: Body {
     fn bytes(self) -> impl Future<Output = Result<Bytes, Self::Error>>;
     /// Return bytes array
     fn chunks(self) -> impl Future<Output = Result<Chunks, Self::Error>>;
+    /// Consumes body and return continous memory, fails once the body is
+    /// larger than the limit.
+    ///
+    /// The limit bounds the memory only for streamed bodies. A fixed body is
+    /// buffered whole before it is checked, check the `Content-Length` ahead
+    /// to reject it without reading.
+    fn bytes_limited(self, limit: usize) -> impl Future<Output = Result<Bytes, Self::Error>>
+    where
+        Self::Error: From<BodyTooLarge>;
 }
 
 pub trait BodyEncodeExt: BodyExt {
@@ -104,6 +125,33 @@ where
         }
     }
 
+    async fn bytes_limited(mut self, limit: usize) -> Result<Bytes, Self::Error>
+    where
+        Self::Error: From<BodyTooLarge>,
+    {
+        match self.stream_hint() {
+            StreamHint::None => Ok(Bytes::new()),
+            StreamHint::Fixed => {
+                let data = self.next_data().await.unwrap_or(Ok(Bytes::new()))?;
+                if data.len() > limit {
+                    return Err(BodyTooLarge { limit }.into());
+                }
+                Ok(data)
+            }
+            StreamHint::Stream => {
+                let mut data = BytesMut::new();
+                while let Some(chunk) = self.next_data().await {
+                    let chunk = chunk?;
+                    if data.len() + chunk.len() > limit {
+                        return Err(BodyTooLarge { limit }.into());
+                    }
+                    data.extend_from_slice(&chunk);
+                }
+                Ok(data.freeze())
+            }
+        }
+    }
+
     async fn chunks(mut self) -> Result<Chunks, Self::Error> {
         match self.stream_hint() {
             StreamHint::None => Ok(Chunks::new()),
@@ -206,6 +254,152 @@ impl<T: BodyExt> BodyEncodeExt for T {
     }
 }
 
+/// Content coding of a `CompressedBody`.
+#[cfg(feature = "encoding")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Br,
+    Deflate,
+}
+
+#[cfg(feature = "encoding")]
+impl Encoding {
+    /// The value of `Content-Encoding`.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Br => "br",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+#[cfg(feature = "encoding")]
+enum Compressor {
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+    Deflate(flate2::write::ZlibEncoder<Vec<u8>>),
+    Br(Box<brotli::CompressorWriter<Vec<u8>>>),
+}
+
+#[cfg(feature = "encoding")]
+impl Compressor {
+    // Default levels, the best ones are too slow for streaming.
+    fn new(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Gzip => Self::Gzip(flate2::write::GzEncoder::new(
+                Vec::new(),
+                Compression::default(),
+            )),
+            Encoding::Deflate => Self::Deflate(flate2::write::ZlibEncoder::new(
+                Vec::new(),
+                Compression::default(),
+            )),
+            Encoding::Br => Self::Br(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                5,
+                22,
+            ))),
+        }
+    }
+
+    /// Compress the data and take the output so far.
+    fn compress(&mut self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        use std::io::Write;
+        let output = match self {
+            Self::Gzip(e) => {
+                e.write_all(data)?;
+                e.get_mut()
+            }
+            Self::Deflate(e) => {
+                e.write_all(data)?;
+                e.get_mut()
+            }
+            Self::Br(e) => {
+                e.write_all(data)?;
+                e.get_mut()
+            }
+        };
+        Ok(std::mem::take(output))
+    }
+
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip(e) => e.finish(),
+            Self::Deflate(e) => e.finish(),
+            Self::Br(e) => Ok(e.into_inner()),
+        }
+    }
+}
+
+/// Compresses the inner body on the fly. The length of the output is
+/// unknown, so it is always streamed.
+#[cfg(feature = "encoding")]
+pub struct CompressedBody<B> {
+    inner: B,
+    // The hint of the remaining inner body.
+    hint: StreamHint,
+    compressor: Option<Compressor>,
+}
+
+#[cfg(feature = "encoding")]
+impl<B: Body> CompressedBody<B> {
+    pub fn new(inner: B, encoding: Encoding) -> Self {
+        Self {
+            hint: inner.stream_hint(),
+            inner,
+            compressor: Some(Compressor::new(encoding)),
+        }
+    }
+}
+
+#[cfg(feature = "encoding")]
+impl<B> Body for CompressedBody<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: From<std::io::Error>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+        loop {
+            let chunk = match self.hint {
+                StreamHint::None => None,
+                StreamHint::Fixed => {
+                    self.hint = StreamHint::None;
+                    self.inner.next_data().await
+                }
+                StreamHint::Stream => self.inner.next_data().await,
+            };
+            let compressor = self.compressor.as_mut()?;
+            match chunk {
+                Some(Ok(data)) => match compressor.compress(&data) {
+                    // An empty chunk ends a chunked http1 body.
+                    Ok(output) if output.is_empty() => continue,
+                    Ok(output) => return Some(Ok(output.into())),
+                    Err(e) => return Some(Err(e.into())),
+                },
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.hint = StreamHint::None;
+                    let output = self.compressor.take()?.finish();
+                    return Some(output.map(Bytes::from).map_err(Into::into));
+                }
+            }
+        }
+    }
+
+    fn stream_hint(&self) -> StreamHint {
+        StreamHint::Stream
+    }
+
+    fn trailers(&mut self) -> impl Future<Output = Result<Option<HeaderMap>, Self::Error>> {
+        self.inner.trailers()
+    }
+}
+
 #[derive(Debug)]
 pub enum HttpBody {
     Ready(Option<Bytes>),
@@ -217,6 +411,13 @@ pub enum HttpBody {
 
 impl HttpBody {
     pub async fn to_ready(&mut self) -> Result<Option<Bytes>, HttpError> {
+        self.to_ready_limited(usize::MAX).await
+    }
+
+    /// Like `to_ready`, but fails once the body is larger than the limit.
+    /// As with `BodyExt::bytes_limited`, a fixed body is already buffered
+    /// when it is checked.
+    pub async fn to_ready_limited(&mut self, limit: usize) -> Result<Option<Bytes>, HttpError> {
         match self.stream_hint() {
             StreamHint::None => Ok(None),
             StreamHint::Fixed => match self.next_data().await {
@@ -226,6 +427,9 @@ impl HttpBody {
                 }
                 Some(chunk) => {
                     let bytes = chunk?;
+                    if bytes.len() > limit {
+                        return Err(BodyTooLarge { limit }.into());
+                    }
                     *self = Self::Ready(Some(bytes.clone()));
                     Ok(Some(bytes))
                 }
@@ -233,7 +437,11 @@ impl HttpBody {
             StreamHint::Stream => {
                 let mut data = BytesMut::new();
                 while let Some(chunk) = self.next_data().await {
-                    data.extend_from_slice(&chunk?);
+                    let chunk = chunk?;
+                    if data.len() + chunk.len() > limit {
+                        return Err(BodyTooLarge { limit }.into());
+                    }
+                    data.extend_from_slice(&chunk);
                 }
                 let bytes = data.freeze();
                 *self = Self::Ready(Some(bytes.clone()));
@@ -323,6 +531,16 @@ impl Body for HttpBody {
             Self::Multipart(ref p) => p.stream_hint(),
         }
     }
+
+    async fn trailers(&mut self) -> Result<Option<HeaderMap>, Self::Error> {
+        match self {
+            Self::Ready(_) => Ok(None),
+            Self::H1(ref mut p) => p.trailers().await,
+            Self::H2(ref mut p) => p.trailers().await.map_err(HttpError::from),
+            #[cfg(feature = "parsed")]
+            Self::Multipart(_) => Ok(None),
+        }
+    }
 }
 
 pub trait FixedBody: Body {
@@ -374,3 +592,66 @@ impl futures_core::Stream for HttpBodyStream {
         Poll::Ready(ready!(self.stream_fut.poll(cx)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::h1::payload::stream_payload_pair;
+
+    #[monoio::test_all]
+    async fn bytes_limited() {
+        let body = HttpBody::fixed_body(Some(Bytes::from_static(b"hello")));
+        assert_eq!(body.bytes_limited(5).await.unwrap(), "hello");
+        let body = HttpBody::fixed_body(Some(Bytes::from_static(b"hello")));
+        assert!(matches!(
+            body.bytes_limited(4).await,
+            Err(HttpError::BodyTooLarge(BodyTooLarge { limit: 4 }))
+        ));
+
+        let (payload, mut sender) = stream_payload_pair();
+        sender.feed_data(Some(Bytes::from_static(b"hel")));
+        sender.feed_data(Some(Bytes::from_static(b"lo")));
+        sender.feed_data(None);
+        let mut body = HttpBody::from(Payload::Stream(payload));
+        assert!(body.to_ready_limited(4).await.is_err());
+
+        let (payload, mut sender) = stream_payload_pair();
+        sender.feed_data(Some(Bytes::from_static(b"hel")));
+        sender.feed_data(Some(Bytes::from_static(b"lo")));
+        sender.feed_data(None);
+        let mut body = HttpBody::from(Payload::Stream(payload));
+        assert_eq!(body.to_ready_limited(5).await.unwrap().unwrap(), "hello");
+        assert_eq!(body.ready_data().unwrap(), "hello");
+    }
+
+    #[cfg(feature = "encoding")]
+    #[monoio::test_all]
+    async fn compressed_body() {
+        let line = Bytes::from_static(b"hello world\n");
+        for encoding in [Encoding::Gzip, Encoding::Br, Encoding::Deflate] {
+            let (payload, mut sender) = stream_payload_pair();
+            for _ in 0..10000 {
+                sender.feed_data(Some(line.clone()));
+            }
+            sender.feed_data(None);
+            let body = CompressedBody::new(HttpBody::from(Payload::Stream(payload)), encoding);
+            let chunks = body.chunks().await.unwrap();
+            assert!(chunks.iter().all(|c| !c.is_empty()));
+            let compressed: Vec<u8> = chunks.concat();
+            assert!(compressed.len() < line.len() * 100);
+
+            let decoded = HttpBody::fixed_body(Some(compressed.into()))
+                .decode_content(encoding.as_str().to_string())
+                .await
+                .unwrap();
+            assert_eq!(decoded, line.repeat(10000));
+        }
+
+        let body = CompressedBody::new(HttpBody::fixed_body(None), Encoding::Gzip);
+        let decoded = HttpBody::fixed_body(Some(body.bytes().await.unwrap()))
+            .decode_content("gzip".to_string())
+            .await
+            .unwrap();
+        assert!(decoded.is_empty());
+    }
+}
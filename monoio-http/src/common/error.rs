@@ -46,6 +46,8 @@ pub enum HttpError {
     H2Error(#[from] crate::h2::Error),
     #[error("IO error {0}")]
     IOError(#[from] std::io::Error),
+    #[error("{0}")]
+    BodyTooLarge(#[from] BodyTooLarge),
     // #[cfg(feature = "parsed")]
     // #[error("Parse error {0}")]
     // ParseError(#[from] ParseError),
@@ -66,6 +68,13 @@ impl Clone for HttpError {
     }
 }
 
+/// The body is larger than the limit.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("body exceeds the limit of {limit} bytes")]
+pub struct BodyTooLarge {
+    pub limit: usize,
+}
+
 #[derive(ThisError, Debug)]
 pub enum EncodeDecodeError<T> {
     #[error("encode/decode error {0}")]
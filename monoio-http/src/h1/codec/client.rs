@@ -1,12 +1,16 @@
 use std::time::Duration;
 
+use bytes::BytesMut;
 use monoio::io::{
     sink::Sink, stream::Stream, AsyncReadRent, AsyncWriteRent, OwnedReadHalf, OwnedWriteHalf,
     Split, Splitable,
 };
 use monoio_codec::FramedRead;
 
-use super::{decoder::ClientResponseDecoder, encoder::GenericEncoder};
+use super::{
+    decoder::{ClientResponseDecoder, DecoderConfig},
+    encoder::GenericEncoder,
+};
 use crate::h1::BorrowFramedRead;
 
 pub struct ClientCodec<IO: AsyncWriteRent> {
@@ -32,6 +36,26 @@ impl<IO: Split + AsyncWriteRent> ClientCodec<IO> {
             decoder: ClientResponseDecoder::new_with_timeout(r, timeout),
         }
     }
+
+    /// Create a codec with the limits and leniency of the response parser.
+    pub fn with_config(io: IO, config: DecoderConfig, timeout: Option<Duration>) -> Self {
+        // # Safety: Since we will not use the encoder and decoder at once, we can split it safely.
+        let (r, w) = io.into_split();
+        Self {
+            encoder: GenericEncoder::new(w),
+            decoder: ClientResponseDecoder::with_config(r, config, timeout),
+        }
+    }
+
+    /// Take back the io and the bytes read but not decoded yet, it is used
+    /// after a protocol upgrade.
+    pub fn into_io(self) -> (IO, BytesMut) {
+        let (r, buf) = self.decoder.into_parts();
+        match r.reunite(self.encoder.into_inner()) {
+            Ok(io) => (io, buf),
+            Err(_) => unreachable!("split halves of the same io"),
+        }
+    }
 }
 
 impl<IO: AsyncWriteRent> BorrowFramedRead for ClientCodec<IO>
@@ -31,6 +31,104 @@ use crate::{
 };
 
 const MAX_HEADERS: usize = 96;
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+const MAX_REQUEST_TARGET: usize = 16 * 1024;
+const MAX_CHUNK_SIZE_LINE: usize = 1024;
+
+/// Limits and leniency of the http1 parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderConfig {
+    /// Max number of headers, it also applies to trailers.
+    pub max_headers: usize,
+    /// Max size of the head, including the start line and headers.
+    pub max_head_size: usize,
+    /// Max length of the request target.
+    pub max_request_target: usize,
+    /// Max length of the chunk-size line, excluding CRLF.
+    pub max_chunk_size_line: usize,
+    /// Allow spaces between header names and colons in responses.
+    pub allow_spaces_after_header_name: bool,
+    /// Allow obsolete line folding in responses, the folded line breaks are
+    /// replaced by spaces.
+    pub allow_obsolete_multiline_headers: bool,
+    /// Allow multiple spaces as delimiters in the start line.
+    pub allow_multiple_spaces_in_start_line: bool,
+    /// Skip invalid header lines instead of failing.
+    pub ignore_invalid_headers: bool,
+}
+
+impl Default for DecoderConfig {
+    fn default() -> Self {
+        Self {
+            max_headers: MAX_HEADERS,
+            max_head_size: MAX_HEAD_SIZE,
+            max_request_target: MAX_REQUEST_TARGET,
+            max_chunk_size_line: MAX_CHUNK_SIZE_LINE,
+            allow_spaces_after_header_name: false,
+            allow_obsolete_multiline_headers: false,
+            allow_multiple_spaces_in_start_line: false,
+            ignore_invalid_headers: false,
+        }
+    }
+}
+
+impl DecoderConfig {
+    fn parser_config(&self) -> httparse::ParserConfig {
+        let mut config = httparse::ParserConfig::default();
+        config
+            .allow_spaces_after_header_name_in_responses(self.allow_spaces_after_header_name)
+            .allow_obsolete_multiline_headers_in_responses(self.allow_obsolete_multiline_headers)
+            .allow_multiple_spaces_in_request_line_delimiters(
+                self.allow_multiple_spaces_in_start_line,
+            )
+            .allow_multiple_spaces_in_response_status_delimiters(
+                self.allow_multiple_spaces_in_start_line,
+            )
+            .ignore_invalid_headers_in_requests(self.ignore_invalid_headers)
+            .ignore_invalid_headers_in_responses(self.ignore_invalid_headers);
+        config
+    }
+
+    /// Split the head out of `src`, returns None if it is incomplete.
+    fn split_head(&self, src: &mut bytes::BytesMut) -> Result<Option<Bytes>, DecodeError> {
+        match memchr::memmem::find(src, b"\r\n\r\n") {
+            Some(idx) if idx + 4 > self.max_head_size => Err(DecodeError::HeadTooLarge),
+            Some(idx) => Ok(Some(src.split_to(idx + 4).freeze())),
+            None if src.len() > self.max_head_size => Err(DecodeError::HeadTooLarge),
+            None => Ok(None),
+        }
+    }
+}
+
+// Parse with the headers on stack unless the limit is raised.
+fn with_headers<'b, R>(max: usize, f: impl FnOnce(&mut [httparse::Header<'b>]) -> R) -> R {
+    if max <= MAX_HEADERS {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        f(&mut headers[..max])
+    } else {
+        f(&mut vec![httparse::EMPTY_HEADER; max])
+    }
+}
+
+fn parse_error(e: httparse::Error) -> DecodeError {
+    match e {
+        httparse::Error::TooManyHeaders => DecodeError::TooManyHeaders,
+        e => DecodeError::Parse(e),
+    }
+}
+
+fn header_value(header_data: &Bytes, begin: usize, end: usize) -> HeaderValue {
+    let value = &header_data[begin..end];
+    // Replace the line breaks of folded lines.
+    if value.iter().any(|b| matches!(b, b'\r' | b'\n')) {
+        let value: Vec<u8> = value
+            .iter()
+            .map(|&b| if matches!(b, b'\r' | b'\n') { b' ' } else { b })
+            .collect();
+        return unsafe { HeaderValue::from_maybe_shared_unchecked(Bytes::from(value)) };
+    }
+    unsafe { HeaderValue::from_maybe_shared_unchecked(header_data.slice(begin..end)) }
+}
 
 #[derive(ThisError, Debug)]
 pub enum InvalidRequestError {
@@ -64,6 +162,28 @@ pub enum DecodeError {
     TimedOut,
     #[error("invalid error {0}")]
     Invalid(InvalidRequestError),
+    #[error("too many headers")]
+    TooManyHeaders,
+    #[error("head too large")]
+    HeadTooLarge,
+    #[error("request target too long")]
+    UriTooLong,
+    #[error("chunk size line too long")]
+    ChunkSizeLineTooLong,
+}
+
+impl DecodeError {
+    /// The status a server should respond with if the request exceeds the
+    /// limits of `DecoderConfig`.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::TooManyHeaders | Self::HeadTooLarge => {
+                Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+            }
+            Self::UriTooLong => Some(StatusCode::URI_TOO_LONG),
+            _ => None,
+        }
+    }
 }
 
 impl Clone for DecodeError {
@@ -120,109 +240,139 @@ impl<FDE, SDE> PayloadDecoder<FDE, SDE> {
 
 /// Decoder of http1 request header.
 #[derive(Default)]
-pub struct RequestHeadDecoder;
+pub struct RequestHeadDecoder {
+    config: DecoderConfig,
+    parser: httparse::ParserConfig,
+}
+
+impl From<DecoderConfig> for RequestHeadDecoder {
+    fn from(config: DecoderConfig) -> Self {
+        Self {
+            config,
+            parser: config.parser_config(),
+        }
+    }
+}
 
 impl Decoder for RequestHeadDecoder {
     type Item = RequestHead;
     type Error = DecodeError;
 
     fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Decoded<Self::Item>, Self::Error> {
-        let header_data = match memchr::memmem::find(src, b"\r\n\r\n")
-            .map(|idx| src.split_to(idx + 4).freeze())
-        {
+        let header_data = match self.config.split_head(src)? {
             Some(h) => h,
             None => return Ok(Decoded::Insufficient),
         };
         let base_ptr = header_data.as_ptr() as usize;
-        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
-        let mut req = httparse::Request::new(&mut headers);
-        let parse_status = req.parse(&header_data)?;
-        if httparse::Status::Partial == parse_status {
-            return Ok(Decoded::Insufficient);
-        }
-        let mut headers = HeaderMap::with_capacity(req.headers.len());
-        for h in req.headers.iter() {
-            let n_begin = h.name.as_ptr() as usize - base_ptr;
-            let n_end = n_begin + h.name.len();
-            let v_begin = h.value.as_ptr() as usize - base_ptr;
-            let v_end = v_begin + h.value.len();
-            let name = HeaderName::from_bytes(&header_data[n_begin..n_end]).unwrap();
-            let value = unsafe {
-                HeaderValue::from_maybe_shared_unchecked(header_data.slice(v_begin..v_end))
-            };
-            headers.append(name, value);
-        }
-        let version = match req.version {
-            Some(1) => Version::HTTP_11,
-            _ => Version::HTTP_10,
-        };
-        let method = Method::from_bytes(req.method.unwrap().as_bytes())?;
-        let uri = match req.path {
-            Some("/") => Uri::default(),
-            Some(path) => {
-                let uri_start = path.as_bytes().as_ptr() as usize - base_ptr;
-                let uri_end = uri_start + path.len();
-                Uri::from_maybe_shared(header_data.slice(uri_start..uri_end))?
+        with_headers(self.config.max_headers, |headers| {
+            let mut req = httparse::Request::new(headers);
+            let parse_status = self
+                .parser
+                .parse_request(&mut req, &header_data)
+                .map_err(parse_error)?;
+            if httparse::Status::Partial == parse_status {
+                return Ok(Decoded::Insufficient);
             }
-            _ => Uri::default(),
-        };
+            let mut headers = HeaderMap::with_capacity(req.headers.len());
+            for h in req.headers.iter() {
+                let n_begin = h.name.as_ptr() as usize - base_ptr;
+                let n_end = n_begin + h.name.len();
+                let v_begin = h.value.as_ptr() as usize - base_ptr;
+                let v_end = v_begin + h.value.len();
+                let name = HeaderName::from_bytes(&header_data[n_begin..n_end]).unwrap();
+                headers.append(name, header_value(&header_data, v_begin, v_end));
+            }
+            let version = match req.version {
+                Some(1) => Version::HTTP_11,
+                _ => Version::HTTP_10,
+            };
+            let method = Method::from_bytes(req.method.unwrap().as_bytes())?;
+            let uri = match req.path {
+                Some(path) if path.len() > self.config.max_request_target => {
+                    return Err(DecodeError::UriTooLong)
+                }
+                Some("/") => Uri::default(),
+                Some(path) => {
+                    let uri_start = path.as_bytes().as_ptr() as usize - base_ptr;
+                    let uri_end = uri_start + path.len();
+                    Uri::from_maybe_shared(header_data.slice(uri_start..uri_end))?
+                }
+                _ => Uri::default(),
+            };
 
-        let (mut request_head, _) = http::request::Request::new(()).into_parts();
-        request_head.method = method;
-        request_head.uri = uri;
-        request_head.version = version;
-        request_head.headers = headers;
+            let (mut request_head, _) = http::request::Request::new(()).into_parts();
+            request_head.method = method;
+            request_head.uri = uri;
+            request_head.version = version;
+            request_head.headers = headers;
 
-        Ok(Decoded::Some(request_head))
+            Ok(Decoded::Some(request_head))
+        })
     }
 }
 
 // TODO: less code copy
 /// Decoder of http1 response header.
 #[derive(Default)]
-pub struct ResponseHeadDecoder;
+pub struct ResponseHeadDecoder {
+    config: DecoderConfig,
+    parser: httparse::ParserConfig,
+}
+
+impl From<DecoderConfig> for ResponseHeadDecoder {
+    fn from(config: DecoderConfig) -> Self {
+        Self {
+            config,
+            parser: config.parser_config(),
+        }
+    }
+}
 
 impl Decoder for ResponseHeadDecoder {
     type Item = ResponseHead;
     type Error = DecodeError;
 
     fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Decoded<Self::Item>, Self::Error> {
-        let header_data = match memchr::memmem::find(src, b"\r\n\r\n")
-            .map(|idx| src.split_to(idx + 4).freeze())
-        {
+        let header_data = match self.config.split_head(src)? {
             Some(h) => h,
             None => return Ok(Decoded::Insufficient),
         };
         let base_ptr = header_data.as_ptr() as usize;
-        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
-        let mut res = httparse::Response::new(&mut headers);
-        let parse_status = res.parse(&header_data)?;
-        if httparse::Status::Partial == parse_status {
-            return Ok(Decoded::Insufficient);
-        }
+        let (version, status, reason, headers) =
+            match with_headers(self.config.max_headers, |headers| {
+                let mut res = httparse::Response::new(headers);
+                let parse_status = self
+                    .parser
+                    .parse_response(&mut res, &header_data)
+                    .map_err(parse_error)?;
+                if httparse::Status::Partial == parse_status {
+                    return Ok(None);
+                }
 
-        let mut headers = HeaderMap::with_capacity(res.headers.len());
-        for h in res.headers.iter() {
-            let n_begin = h.name.as_ptr() as usize - base_ptr;
-            let n_end = n_begin + h.name.len();
-            let v_begin = h.value.as_ptr() as usize - base_ptr;
-            let v_end = v_begin + h.value.len();
-            let name = HeaderName::from_bytes(&header_data[n_begin..n_end]).unwrap();
-            let value = unsafe {
-                HeaderValue::from_maybe_shared_unchecked(header_data.slice(v_begin..v_end))
+                let mut headers = HeaderMap::with_capacity(res.headers.len());
+                for h in res.headers.iter() {
+                    let n_begin = h.name.as_ptr() as usize - base_ptr;
+                    let n_end = n_begin + h.name.len();
+                    let v_begin = h.value.as_ptr() as usize - base_ptr;
+                    let v_end = v_begin + h.value.len();
+                    let name = HeaderName::from_bytes(&header_data[n_begin..n_end]).unwrap();
+                    headers.append(name, header_value(&header_data, v_begin, v_end));
+                }
+                let version = match res.version {
+                    Some(1) => Version::HTTP_11,
+                    _ => Version::HTTP_10,
+                };
+                let status = StatusCode::from_u16(res.code.unwrap())?;
+                let reason = match res.reason {
+                    Some(r) if Some(r) == status.canonical_reason() => None,
+                    Some(r) => Some(Cow::Owned(r.to_owned())),
+                    None => None,
+                };
+                Ok::<_, DecodeError>(Some((version, status, reason, headers)))
+            })? {
+                Some(head) => head,
+                None => return Ok(Decoded::Insufficient),
             };
-            headers.append(name, value);
-        }
-        let version = match res.version {
-            Some(1) => Version::HTTP_11,
-            _ => Version::HTTP_10,
-        };
-        let status = StatusCode::from_u16(res.code.unwrap())?;
-        let reason = match res.reason {
-            Some(r) if Some(r) == status.canonical_reason() => None,
-            Some(r) => Some(Cow::Owned(r.to_owned())),
-            None => None,
-        };
 
         let (mut response_head, _) = http::response::Response::new(()).into_parts();
         response_head.version = version;
@@ -240,6 +390,14 @@ impl Decoder for ResponseHeadDecoder {
 /// Decoder of http1 body with fixed length.
 pub struct FixedBodyDecoder(usize);
 
+impl FixedBodyDecoder {
+    /// Returns the length of the body.
+    #[inline]
+    pub fn length(&self) -> usize {
+        self.0
+    }
+}
+
 impl Decoder for FixedBodyDecoder {
     type Item = Bytes;
     type Error = DecodeError;
@@ -254,10 +412,106 @@ impl Decoder for FixedBodyDecoder {
     }
 }
 
-// TODO: support trailer
+/// Decoder of http1 body with fixed length, which yields the body in pieces
+/// as they arrive rather than buffering it whole. None is yielded once the
+/// body is complete.
+pub struct FixedPiecesDecoder(usize);
+
+impl FixedPiecesDecoder {
+    /// Returns the length of the body not decoded yet.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.0
+    }
+}
+
+impl From<&FixedBodyDecoder> for FixedPiecesDecoder {
+    #[inline]
+    fn from(decoder: &FixedBodyDecoder) -> Self {
+        Self(decoder.0)
+    }
+}
+
+impl Decoder for FixedPiecesDecoder {
+    type Item = Option<Bytes>;
+    type Error = DecodeError;
+
+    #[inline]
+    fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Decoded<Self::Item>, Self::Error> {
+        if self.0 == 0 {
+            return Ok(Decoded::Some(None));
+        }
+        if src.is_empty() {
+            return Ok(Decoded::Insufficient);
+        }
+        let piece = src.split_to(self.0.min(src.len())).freeze();
+        self.0 -= piece.len();
+        Ok(Decoded::Some(Some(piece)))
+    }
+}
+
 /// Decoder of http1 chunked body.
 #[derive(Default)]
-pub struct ChunkedBodyDecoder(Option<usize>);
+pub struct ChunkedBodyDecoder {
+    chunk_len: Option<usize>,
+    trailers: Option<HeaderMap>,
+    config: DecoderConfig,
+}
+
+impl From<DecoderConfig> for ChunkedBodyDecoder {
+    fn from(config: DecoderConfig) -> Self {
+        Self {
+            chunk_len: None,
+            trailers: None,
+            config,
+        }
+    }
+}
+
+impl ChunkedBodyDecoder {
+    /// Take the trailers decoded after the last chunk.
+    #[inline]
+    pub fn take_trailers(&mut self) -> Option<HeaderMap> {
+        self.trailers.take()
+    }
+
+    fn decode_trailers(&mut self, src: &mut bytes::BytesMut) -> Result<bool, DecodeError> {
+        if src.len() < 2 {
+            return Ok(false);
+        }
+        // No trailer.
+        if &src[..2] == b"\r\n" {
+            src.advance(2);
+            return Ok(true);
+        }
+        let trailer_data = match self.config.split_head(src)? {
+            Some(t) => t,
+            None => return Ok(false),
+        };
+        let trailers =
+            with_headers(
+                self.config.max_headers,
+                |headers| match httparse::parse_headers(&trailer_data, headers)
+                    .map_err(parse_error)?
+                {
+                    httparse::Status::Complete((_, parsed)) => {
+                        let mut trailers = HeaderMap::with_capacity(parsed.len());
+                        for h in parsed.iter() {
+                            let name = HeaderName::from_bytes(h.name.as_bytes())
+                                .map_err(|_| DecodeError::Header)?;
+                            let value = HeaderValue::from_bytes(h.value)
+                                .map_err(|_| DecodeError::Header)?;
+                            trailers.append(name, value);
+                        }
+                        Ok(trailers)
+                    }
+                    httparse::Status::Partial => Err(DecodeError::Chunked),
+                },
+            )?;
+        self.trailers = Some(trailers);
+        Ok(true)
+    }
+}
 
 impl Decoder for ChunkedBodyDecoder {
     type Item = Option<Bytes>;
@@ -265,7 +519,15 @@ impl Decoder for ChunkedBodyDecoder {
 
     fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Decoded<Self::Item>, Self::Error> {
         loop {
-            match self.0 {
+            match self.chunk_len {
+                Some(0) => {
+                    // The last chunk, maybe followed by trailers.
+                    if !self.decode_trailers(src)? {
+                        return Ok(Decoded::Insufficient);
+                    }
+                    self.chunk_len = None;
+                    return Ok(Decoded::Some(None));
+                }
                 Some(len) => {
                     // Now we know how long we need
                     if src.len() < len + 2 {
@@ -275,15 +537,10 @@ impl Decoder for ChunkedBodyDecoder {
                     if &src[len..len + 2] != b"\r\n" {
                         return Err(DecodeError::Chunked);
                     }
-                    let data = if len != 0 {
-                        let body = src.split_to(len).freeze();
-                        Some(body)
-                    } else {
-                        None
-                    };
+                    let body = src.split_to(len).freeze();
                     src.advance(2);
-                    self.0 = None;
-                    return Ok(Decoded::Some(data));
+                    self.chunk_len = None;
+                    return Ok(Decoded::Some(Some(body)));
                 }
                 None => {
                     // We don't know what size the next block is.
@@ -302,6 +559,9 @@ impl Decoder for ChunkedBodyDecoder {
                             _ => return Err(DecodeError::Chunked),
                         };
                         read += 1;
+                        if read > self.config.max_chunk_size_line {
+                            return Err(DecodeError::ChunkSizeLineTooLong);
+                        }
                         match len.checked_mul(16) {
                             Some(new_len) => {
                                 len = new_len + n as usize;
@@ -323,7 +583,7 @@ impl Decoder for ChunkedBodyDecoder {
                         return Err(DecodeError::Chunked);
                     }
                     src.advance(read + 2);
-                    self.0 = Some(len);
+                    self.chunk_len = Some(len);
                     // Now we can read data, just continue will be fine.
                     // The loop will only happen once.
                 }
@@ -336,7 +596,7 @@ pub trait ItemWrapper<I, R> {
     type Output;
     fn wrap_none(input: I) -> Self::Output;
     fn wrap_fixed(input: I, length: usize) -> Self::Output;
-    fn wrap_stream(input: I) -> Self::Output;
+    fn wrap_stream(input: I, decoder: ChunkedBodyDecoder) -> Self::Output;
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -368,13 +628,10 @@ where
     }
 
     #[inline]
-    fn wrap_stream(header: H) -> Self::Output {
+    fn wrap_stream(header: H, decoder: ChunkedBodyDecoder) -> Self::Output {
         let (payload, sender) = stream_payload_pair();
         let request = R::from_parts(header, Payload::from(payload));
-        (
-            request,
-            NextDecoder::Streamed(ChunkedBodyDecoder::default(), sender),
-        )
+        (request, NextDecoder::Streamed(decoder, sender))
     }
 }
 
@@ -392,8 +649,8 @@ impl<H, R> ItemWrapper<H, R> for DirectWrapper {
     }
 
     #[inline]
-    fn wrap_stream(header: H) -> Self::Output {
-        (header, PayloadDecoder::Streamed(ChunkedBodyDecoder(None)))
+    fn wrap_stream(header: H, decoder: ChunkedBodyDecoder) -> Self::Output {
+        (header, PayloadDecoder::Streamed(decoder))
     }
 }
 
@@ -402,6 +659,7 @@ impl<H, R> ItemWrapper<H, R> for DirectWrapper {
 /// satisfy the constraint of `ComposeDecoder`.
 pub struct GenericHeadDecoder<R, D, F> {
     decoder: D,
+    config: DecoderConfig,
     _marker_f: PhantomData<F>,
     _marker_r: PhantomData<R>,
 }
@@ -410,6 +668,18 @@ impl<R, D, F> GenericHeadDecoder<R, D, F> {
     pub fn new(decoder: D) -> Self {
         Self {
             decoder,
+            config: DecoderConfig::default(),
+            _marker_f: PhantomData,
+            _marker_r: PhantomData,
+        }
+    }
+}
+
+impl<R, D: From<DecoderConfig>, F> From<DecoderConfig> for GenericHeadDecoder<R, D, F> {
+    fn from(config: DecoderConfig) -> Self {
+        Self {
+            decoder: D::from(config),
+            config,
             _marker_f: PhantomData,
             _marker_r: PhantomData,
         }
@@ -441,7 +711,8 @@ where
                 if let Some(x) = head.header_map().get(http::header::TRANSFER_ENCODING) {
                     // Check chunked
                     if x.as_bytes().eq_ignore_ascii_case(b"chunked") {
-                        return Ok(Decoded::Some(F::wrap_stream(head)));
+                        let decoder = ChunkedBodyDecoder::from(self.config);
+                        return Ok(Decoded::Some(F::wrap_stream(head, decoder)));
                     }
                     // Check not identity
                     if !x.as_bytes().eq_ignore_ascii_case(b"identity") {
@@ -500,6 +771,16 @@ impl<IO, HD: Default> GenericDecoder<IO, HD> {
     }
 }
 
+impl<IO, HD: From<DecoderConfig>> GenericDecoder<IO, HD> {
+    pub fn with_config(io: IO, config: DecoderConfig, timeout: Option<Duration>) -> Self {
+        Self {
+            framed: FramedRead::new(io, HD::from(config)),
+            next_decoder: NextDecoder::default(),
+            timeout,
+        }
+    }
+}
+
 impl<IO, HD> GenericDecoder<IO, HD> {
     #[inline]
     pub fn set_timeout(&mut self, timeout: Option<Duration>) {
@@ -567,6 +848,9 @@ where
                                     sender.feed_data(Some(item));
                                 }
                                 None => {
+                                    if let Some(trailers) = decoder.take_trailers() {
+                                        sender.feed_trailers(trailers);
+                                    }
                                     sender.feed_data(None);
                                     self.next_decoder = NextDecoder::None;
                                 }
@@ -657,11 +941,27 @@ impl<IO, HD: Default> IoOwnedDecoder<IO, HD> {
     }
 }
 
+impl<IO, HD: From<DecoderConfig>> IoOwnedDecoder<IO, HD> {
+    #[inline]
+    pub fn with_config(io: IO, config: DecoderConfig, timeout: Option<Duration>) -> Self {
+        Self {
+            framed: FramedRead::new(io, HD::from(config)),
+            timeout,
+        }
+    }
+}
+
 impl<IO, HD> IoOwnedDecoder<IO, HD> {
     #[inline]
     pub fn set_timeout(&mut self, timeout: Option<Duration>) {
         self.timeout = timeout;
     }
+
+    /// Take back the io and the bytes read but not decoded yet.
+    pub fn into_parts(mut self) -> (IO, bytes::BytesMut) {
+        let buf = self.framed.read_buffer_mut().split();
+        (self.framed.into_inner(), buf)
+    }
 }
 
 impl<IO, HD> Stream for IoOwnedDecoder<IO, HD>
@@ -723,13 +1023,14 @@ mod tests {
     use monoio::{buf::IoVecWrapperMut, BufResult};
 
     use super::*;
+    use crate::common::body::Body;
 
     #[test]
     fn decode_request_header_multiple_times() {
         let current = Instant::now();
         for _ in 1..10000 {
             let mut data = BytesMut::from("GET /ping HTTP/1.1\r\n\r\n");
-            let _ = RequestHeadDecoder.decode(&mut data).unwrap();
+            let _ = RequestHeadDecoder::default().decode(&mut data).unwrap();
         }
         let elapse = current.elapsed().as_millis();
 
@@ -739,7 +1040,10 @@ mod tests {
     #[test]
     fn decode_request_header() {
         let mut data = BytesMut::from("GET /test HTTP/1.1\r\n\r\n");
-        let head = RequestHeadDecoder.decode(&mut data).unwrap().unwrap();
+        let head = RequestHeadDecoder::default()
+            .decode(&mut data)
+            .unwrap()
+            .unwrap();
         assert_eq!(head.method, Method::GET);
         assert_eq!(head.version, Version::HTTP_11);
         assert_eq!(head.uri, "/test");
@@ -752,7 +1056,7 @@ mod tests {
         for _ in 1..10000 {
             let mut data =
                 BytesMut::from("HTTP/1.1 200 OK\r\nContent-Type:application/json\r\n\r\n");
-            let _ = ResponseHeadDecoder.decode(&mut data).unwrap();
+            let _ = ResponseHeadDecoder::default().decode(&mut data).unwrap();
         }
         let elapse = current.elapsed().as_millis();
 
@@ -762,7 +1066,10 @@ mod tests {
     #[test]
     fn decode_response_header() {
         let mut data = BytesMut::from("HTTP/1.1 200 OK\r\nContent-Type:application/json\r\n\r\n");
-        let head = ResponseHeadDecoder.decode(&mut data).unwrap().unwrap();
+        let head = ResponseHeadDecoder::default()
+            .decode(&mut data)
+            .unwrap()
+            .unwrap();
         assert_eq!(head.status, StatusCode::OK);
         assert_eq!(head.version, Version::HTTP_11);
         assert_eq!(
@@ -795,6 +1102,25 @@ mod tests {
         assert_eq!(data.len(), 8);
     }
 
+    #[test]
+    fn decode_fixed_body_in_pieces() {
+        let mut data = BytesMut::from("bala");
+        let mut decoder = FixedPiecesDecoder::from(&FixedBodyDecoder(6));
+
+        let piece = decoder.decode(&mut data).unwrap().unwrap();
+        assert_eq!(piece.unwrap(), "bala");
+        assert!(matches!(
+            decoder.decode(&mut data).unwrap(),
+            Decoded::Insufficient
+        ));
+        data.extend_from_slice(b"balabala");
+        let piece = decoder.decode(&mut data).unwrap().unwrap();
+        assert_eq!(piece.unwrap(), "ba");
+        assert_eq!(decoder.remaining(), 0);
+        assert!(decoder.decode(&mut data).unwrap().unwrap().is_none());
+        assert_eq!(data.len(), 6);
+    }
+
     #[test]
     fn decode_chunked_body() {
         let mut data = BytesMut::from("a\r\n0000000000\r\n1\r\nx\r\n0\r\n\r\n");
@@ -807,6 +1133,31 @@ mod tests {
         assert!(decoder.decode(&mut data).unwrap().unwrap().is_none());
     }
 
+    #[test]
+    fn decode_chunked_body_with_trailers() {
+        let mut data = BytesMut::from("4\r\ndata\r\n0\r\ngrpc-status: 0\r\nx-sum: 1\r\n\r\n");
+        let mut decoder = ChunkedBodyDecoder::default();
+        assert_eq!(decoder.decode(&mut data).unwrap().unwrap().unwrap(), "data");
+        assert!(decoder.decode(&mut data).unwrap().unwrap().is_none());
+        assert!(data.is_empty());
+        let trailers = decoder.take_trailers().unwrap();
+        assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+        assert_eq!(trailers.get("x-sum").unwrap(), "1");
+    }
+
+    #[test]
+    fn decode_chunked_body_partial_trailers() {
+        let mut data = BytesMut::from("0\r\nx-sum: 1\r\n");
+        let mut decoder = ChunkedBodyDecoder::default();
+        assert!(matches!(
+            decoder.decode(&mut data).unwrap(),
+            Decoded::Insufficient
+        ));
+        data.extend_from_slice(b"\r\n");
+        assert!(decoder.decode(&mut data).unwrap().unwrap().is_none());
+        assert_eq!(decoder.take_trailers().unwrap().get("x-sum").unwrap(), "1");
+    }
+
     #[test]
     fn decode_too_big_chunked_body() {
         let mut data = BytesMut::from("a\r\n0000000000\r\ndeadbeefcafebabe0\r\nx\r\n0\r\n\r\n");
@@ -818,6 +1169,89 @@ mod tests {
         assert!(&decoder.decode(&mut data).is_err());
     }
 
+    #[test]
+    fn decode_head_limits() {
+        let config = DecoderConfig {
+            max_headers: 2,
+            max_head_size: 64,
+            max_request_target: 8,
+            ..Default::default()
+        };
+        let decode =
+            |data: &str| RequestHeadDecoder::from(config).decode(&mut BytesMut::from(data));
+
+        assert!(decode("GET /1234567 HTTP/1.1\r\n\r\n").is_ok());
+        let err = decode("GET /12345678 HTTP/1.1\r\n\r\n").unwrap_err();
+        assert!(matches!(err, DecodeError::UriTooLong));
+        assert_eq!(err.status(), Some(StatusCode::URI_TOO_LONG));
+
+        let err = decode("GET / HTTP/1.1\r\na: 1\r\nb: 2\r\nc: 3\r\n\r\n").unwrap_err();
+        assert!(matches!(err, DecodeError::TooManyHeaders));
+        assert_eq!(
+            err.status(),
+            Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        );
+
+        // Fail without the terminator once the buffer exceeds the limit.
+        let long = format!("GET / HTTP/1.1\r\na: {}", "x".repeat(64));
+        assert!(matches!(decode(&long[..60]), Ok(Decoded::Insufficient)));
+        assert!(matches!(decode(&long), Err(DecodeError::HeadTooLarge)));
+        let long = format!("{long}\r\n\r\n");
+        assert!(matches!(decode(&long), Err(DecodeError::HeadTooLarge)));
+
+        // The raised header limit is not bounded by the stack buffer.
+        let config = DecoderConfig {
+            max_headers: 200,
+            ..Default::default()
+        };
+        let mut data = String::from("GET / HTTP/1.1\r\n");
+        for i in 0..150 {
+            data.push_str(&format!("x-{i}: {i}\r\n"));
+        }
+        data.push_str("\r\n");
+        let head = RequestHeadDecoder::from(config)
+            .decode(&mut BytesMut::from(data.as_str()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(head.headers.len(), 150);
+        assert!(matches!(
+            RequestHeadDecoder::default().decode(&mut BytesMut::from(data.as_str())),
+            Err(DecodeError::TooManyHeaders)
+        ));
+    }
+
+    #[test]
+    fn decode_head_leniency() {
+        let data = "HTTP/1.1 200 OK\r\nx-folded : a\r\n b\r\n\r\n";
+        assert!(ResponseHeadDecoder::default()
+            .decode(&mut BytesMut::from(data))
+            .is_err());
+
+        let mut decoder = ResponseHeadDecoder::from(DecoderConfig {
+            allow_spaces_after_header_name: true,
+            allow_obsolete_multiline_headers: true,
+            ..Default::default()
+        });
+        let head = decoder.decode(&mut BytesMut::from(data)).unwrap().unwrap();
+        assert_eq!(head.headers.get("x-folded").unwrap(), "a   b");
+    }
+
+    #[test]
+    fn decode_chunk_size_line_limit() {
+        let mut decoder = ChunkedBodyDecoder::from(DecoderConfig {
+            max_chunk_size_line: 4,
+            ..Default::default()
+        });
+        let mut data = BytesMut::from("0004\r\ndata\r\n");
+        assert_eq!(decoder.decode(&mut data).unwrap().unwrap().unwrap(), "data");
+        // Leading zeros never overflow the size, but are bounded by the limit.
+        let mut data = BytesMut::from("00000");
+        assert!(matches!(
+            decoder.decode(&mut data),
+            Err(DecodeError::ChunkSizeLineTooLong)
+        ));
+    }
+
     macro_rules! mock {
         ($($x:expr),*) => {{
             let mut v = VecDeque::new();
@@ -924,6 +1358,24 @@ mod tests {
         handler.await
     }
 
+    #[monoio::test_all]
+    async fn decode_chunked_request_with_trailers() {
+        let io = mock! { Ok(b"POST /test HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n\
+        4\r\ndata\r\n0\r\nx-checksum: abc\r\n\r\n".to_vec()) };
+        let mut decoder = RequestDecoder::new(io);
+        let req = decoder.next().await.unwrap().unwrap();
+        let mut payload = req.into_body();
+        let handler = monoio::spawn(async move {
+            assert_eq!(&payload.next_data().await.unwrap().unwrap(), &"data");
+            assert!(payload.next_data().await.is_none());
+            let trailers = payload.trailers().await.unwrap().unwrap();
+            assert_eq!(trailers.get("x-checksum").unwrap(), "abc");
+        });
+        assert!(decoder.fill_payload().await.is_ok());
+        assert!(decoder.next().await.is_none());
+        handler.await
+    }
+
     // Mock struct copied from monoio-codec and tokio-util.
     struct Mock {
         calls: VecDeque<io::Result<Vec<u8>>>,
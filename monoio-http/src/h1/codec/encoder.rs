@@ -346,6 +346,11 @@ impl<T> GenericEncoder<T> {
             buf: BytesMut::with_capacity(INITIAL_CAPACITY),
         }
     }
+
+    /// Take back the io. Data not flushed will be dropped.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -432,7 +437,11 @@ where
                     }
                     self.buf.put_slice(b"\r\n");
                 }
-                self.buf.put_slice(b"0\r\n\r\n");
+                self.buf.put_slice(b"0\r\n");
+                if let Some(trailers) = payload.trailers().await? {
+                    HeadEncoder::write_headers(&trailers, &mut self.buf);
+                }
+                self.buf.put_slice(b"\r\n");
             }
         }
         Ok(())
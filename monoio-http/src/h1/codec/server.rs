@@ -6,7 +6,7 @@ use monoio::io::{
 };
 
 use super::{
-    decoder::{FillPayload, RequestDecoder},
+    decoder::{DecoderConfig, FillPayload, RequestDecoder},
     encoder::GenericEncoder,
 };
 
@@ -35,6 +35,17 @@ impl<IO: Split + AsyncReadRent + AsyncWriteRent> ServerCodec<IO> {
             decoder: RequestDecoder::new_with_timeout(r, timeout),
         }
     }
+
+    /// Create a codec with the limits and leniency of the request parser.
+    #[inline]
+    pub fn with_config(io: IO, config: DecoderConfig, timeout: Option<Duration>) -> Self {
+        // # Safety: Since we will not use the encoder and decoder at once, we can split it safely.
+        let (r, w) = io.into_split();
+        Self {
+            encoder: GenericEncoder::new(w),
+            decoder: RequestDecoder::with_config(r, config, timeout),
+        }
+    }
 }
 
 impl<IO: AsyncWriteRent, R> Sink<R> for ServerCodec<IO>
@@ -8,6 +8,7 @@ use std::{
 };
 
 use bytes::Bytes;
+use http::HeaderMap;
 use monoio::{
     buf::IoBuf,
     io::{stream::Stream, AsyncReadRent},
@@ -16,7 +17,9 @@ use monoio::{
 use thiserror::Error as ThisError;
 
 use super::{
-    codec::decoder::{ChunkedBodyDecoder, DecodeError, FixedBodyDecoder, PayloadDecoder},
+    codec::decoder::{
+        ChunkedBodyDecoder, DecodeError, FixedBodyDecoder, FixedPiecesDecoder, PayloadDecoder,
+    },
     BorrowFramedRead,
 };
 use crate::common::{
@@ -63,6 +66,13 @@ impl<D: IoBuf, E> Body for Payload<D, E> {
             Payload::Stream(_) => StreamHint::Stream,
         }
     }
+
+    async fn trailers(&mut self) -> Result<Option<HeaderMap>, E> {
+        match self {
+            Payload::Stream(p) => Ok(p.trailers().await),
+            _ => Ok(None),
+        }
+    }
 }
 
 pub enum PayloadSender<D, E> {
@@ -194,6 +204,12 @@ impl<D: IoBuf, E> FixedPayload<D, E> {
 }
 
 impl<D, E> FixedPayloadSender<D, E> {
+    /// Returns true if the receiver has been dropped.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.inner.strong_count() == 0
+    }
+
     pub fn feed(self, item: Result<D, E>) {
         if let Some(shared) = self.inner.upgrade() {
             let inner = unsafe { &mut *shared.get() };
@@ -221,7 +237,12 @@ pub struct StreamPayloadSender<D = Bytes, E = HttpError> {
 struct StreamInner<D, E> {
     eof: bool,
     items: VecDeque<Result<D, E>>,
+    trailers: Option<HeaderMap>,
     task: Option<Waker>,
+    // The sender waiting for the items to be taken.
+    sender_task: Option<Waker>,
+    // The receiver has seen the eof.
+    ended: bool,
 }
 
 impl<D, E> Default for StreamInner<D, E> {
@@ -229,7 +250,10 @@ impl<D, E> Default for StreamInner<D, E> {
         Self {
             eof: false,
             items: VecDeque::new(),
+            trailers: None,
             task: None,
+            sender_task: None,
+            ended: false,
         }
     }
 }
@@ -240,6 +264,12 @@ impl<D, E> StreamInner<D, E> {
             waker.wake();
         }
     }
+
+    fn wake_sender(&mut self) {
+        if let Some(waker) = self.sender_task.take() {
+            waker.wake();
+        }
+    }
 }
 
 impl<D: IoBuf, E> Stream for StreamPayload<D, E> {
@@ -250,9 +280,14 @@ impl<D: IoBuf, E> Stream for StreamPayload<D, E> {
             {
                 let inner = unsafe { &mut *self.inner.get() };
                 if let Some(data) = inner.items.pop_front() {
+                    if inner.items.is_empty() {
+                        inner.wake_sender();
+                    }
                     return Some(data);
                 }
                 if inner.eof {
+                    inner.ended = true;
+                    inner.wake_sender();
                     return None;
                 }
             }
@@ -272,7 +307,57 @@ impl<D: IoBuf, E> Stream for StreamPayload<D, E> {
     }
 }
 
+impl<D: IoBuf, E> StreamPayload<D, E> {
+    /// Wait for the end of the stream and take the trailers.
+    pub async fn trailers(&mut self) -> Option<HeaderMap> {
+        poll_fn(|cx| {
+            let inner = unsafe { &mut *self.inner.get() };
+            if inner.eof {
+                std::task::Poll::Ready(inner.trailers.take())
+            } else {
+                if !matches!(inner.task, Some(ref waker) if waker.will_wake(cx.waker())) {
+                    inner.task = Some(cx.waker().clone());
+                }
+                std::task::Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<D: IoBuf, E> Drop for StreamPayload<D, E> {
+    fn drop(&mut self) {
+        // The sender may wait for the items to be taken.
+        unsafe { &mut *self.inner.get() }.wake_sender();
+    }
+}
+
 impl<D, E> StreamPayloadSender<D, E> {
+    /// Returns true if the receiver has been dropped.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.inner.strong_count() == 0
+    }
+
+    /// Wait until the fed items, and the eof if it is fed, are taken by the
+    /// receiver, or it is dropped.
+    pub async fn drained(&mut self) {
+        poll_fn(|cx| {
+            let shared = match self.inner.upgrade() {
+                Some(shared) => shared,
+                None => return std::task::Poll::Ready(()),
+            };
+            let inner = unsafe { &mut *shared.get() };
+            if inner.items.is_empty() && (!inner.eof || inner.ended) {
+                std::task::Poll::Ready(())
+            } else {
+                inner.sender_task = Some(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        })
+        .await
+    }
+
     pub fn feed_data(&mut self, data: Option<D>) {
         if let Some(shared) = self.inner.upgrade() {
             let inner = unsafe { &mut *shared.get() };
@@ -284,10 +369,20 @@ impl<D, E> StreamPayloadSender<D, E> {
         }
     }
 
+    /// Feed trailers, they should be fed before the eof.
+    pub fn feed_trailers(&mut self, trailers: HeaderMap) {
+        if let Some(shared) = self.inner.upgrade() {
+            let inner = unsafe { &mut *shared.get() };
+            inner.trailers = Some(trailers);
+        }
+    }
+
+    /// Feed an error, it ends the stream.
     pub fn feed_error(&mut self, err: E) {
         if let Some(shared) = self.inner.upgrade() {
             let inner = unsafe { &mut *shared.get() };
             inner.items.push_back(Err(err));
+            inner.eof = true;
             inner.wake();
         }
     }
@@ -300,6 +395,9 @@ pub struct FramedPayload<T> {
     // so we may require it to provide something we can do read.
     io_source: T,
     payload_decoder: PayloadDecoder<FixedBodyDecoder, ChunkedBodyDecoder>,
+    // Set if a fixed length body is yielded in pieces.
+    pieces: Option<FixedPiecesDecoder>,
+    trailers: Option<HeaderMap>,
     eof: bool,
 }
 
@@ -311,9 +409,20 @@ impl<T> FramedPayload<T> {
         Self {
             io_source,
             payload_decoder,
+            pieces: None,
+            trailers: None,
             eof: false,
         }
     }
+
+    /// Yield a fixed length body in pieces as they arrive, so a large body is
+    /// not buffered whole.
+    pub fn stream_fixed(mut self) -> Self {
+        if let PayloadDecoder::Fixed(decoder) = &self.payload_decoder {
+            self.pieces = Some(decoder.into());
+        }
+        self
+    }
 }
 
 impl<T> Body for FramedPayload<T>
@@ -328,6 +437,22 @@ where
         if self.eof {
             return None;
         }
+        if let Some(decoder) = self.pieces.as_mut() {
+            // The io is not read again once the body is complete.
+            if decoder.remaining() == 0 {
+                self.eof = true;
+                return None;
+            }
+            return match self.io_source.framed_mut().next_with(decoder).await {
+                None => Some(Err(DecodeError::UnexpectedEof.into())),
+                Some(Ok(Some(item))) => Some(Ok(item)),
+                Some(Ok(None)) => {
+                    self.eof = true;
+                    None
+                }
+                Some(Err(e)) => Some(Err(e.into())),
+            };
+        }
         // The logic here is alike with GenericDecoder's Fillpayload
         match &mut self.payload_decoder {
             PayloadDecoder::None => None,
@@ -345,6 +470,7 @@ where
                     Some(Ok(Some(item))) => Some(Ok(item)),
                     Some(Ok(None)) => {
                         self.eof = true;
+                        self.trailers = decoder.take_trailers();
                         None
                     }
                     Some(Err(e)) => Some(Err(e.into())),
@@ -354,7 +480,14 @@ where
     }
 
     fn stream_hint(&self) -> StreamHint {
-        self.payload_decoder.hint()
+        match self.pieces {
+            Some(_) => StreamHint::Stream,
+            None => self.payload_decoder.hint(),
+        }
+    }
+
+    async fn trailers(&mut self) -> Result<Option<HeaderMap>, Self::Error> {
+        Ok(self.trailers.take())
     }
 }
 
@@ -388,6 +521,42 @@ mod tests {
         assert!(payload.next().await.is_none());
     }
 
+    #[monoio::test_all(enable_timer = true)]
+    async fn stream_payload_error() {
+        let (mut payload, mut payload_sender) = stream_payload_pair::<Bytes, _>();
+        monoio::spawn(async move {
+            monoio::time::sleep(Duration::from_millis(2)).await;
+            payload_sender.feed_error(io::Error::other("oops"));
+        });
+        // The error ends the stream, trailers don't wait for more.
+        assert!(payload.trailers().await.is_none());
+        assert!(payload.next().await.unwrap().is_err());
+        assert!(payload.next().await.is_none());
+    }
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn stream_payload_drained() {
+        let (mut payload, mut payload_sender) = stream_payload_pair::<Bytes, io::Error>();
+        payload_sender.feed_data(Some(Bytes::from_static(b"Hello")));
+        payload_sender.feed_data(Some(Bytes::from_static(b"World")));
+        let drained = monoio::spawn(async move {
+            payload_sender.drained().await;
+            payload_sender
+        });
+        monoio::time::sleep(Duration::from_millis(2)).await;
+        payload.next().await.unwrap().unwrap();
+        monoio::time::sleep(Duration::from_millis(2)).await;
+        payload.next().await.unwrap().unwrap();
+        let mut payload_sender = drained.await;
+
+        // Resolved once the receiver is dropped.
+        payload_sender.feed_data(Some(Bytes::from_static(b"Hello")));
+        let drained = monoio::spawn(async move { payload_sender.drained().await });
+        monoio::time::sleep(Duration::from_millis(2)).await;
+        drop(payload);
+        drained.await;
+    }
+
     #[monoio::test_all(enable_timer = true)]
     async fn fixed_payload() {
         let (mut payload, payload_sender) = fixed_payload_pair::<_, Infallible>();
@@ -539,6 +539,21 @@ where
         self.inner.is_extended_connect_protocol_enabled()
     }
 
+    /// Polls for the first `SETTINGS` frame of the server.
+    ///
+    /// Settings like [`is_extended_connect_protocol_enabled`] are only
+    /// reliable after it resolves.
+    ///
+    /// [`is_extended_connect_protocol_enabled`]: SendRequest::is_extended_connect_protocol_enabled
+    pub fn poll_remote_settings(&mut self, cx: &mut Context) -> Poll<Result<(), crate::h2::Error>> {
+        self.inner.poll_remote_settings(cx)
+    }
+
+    /// Waits for the first `SETTINGS` frame of the server.
+    pub async fn remote_settings(&mut self) -> Result<(), crate::h2::Error> {
+        std::future::poll_fn(|cx| self.poll_remote_settings(cx)).await
+    }
+
     pub fn has_conn_error(&self) -> bool {
         self.inner.has_conn_error()
     }
@@ -1283,6 +1298,15 @@ where
         self.inner.take_user_pings().map(PingPong::new)
     }
 
+    /// Starts a graceful shutdown by sending GOAWAY.
+    ///
+    /// Must continue being polled to close connection. Once all active
+    /// streams have completed, the connection is closed even if there are
+    /// still `SendRequest` handles.
+    pub fn graceful_shutdown(&mut self) {
+        self.inner.go_away_gracefully();
+    }
+
     /// Returns the maximum number of concurrent streams that may be initiated
     /// by this client.
     ///
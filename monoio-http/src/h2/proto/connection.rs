@@ -538,6 +538,16 @@ where
     pub(crate) fn streams(&self) -> &Streams<B, client::Peer> {
         &self.inner.streams
     }
+
+    /// Send GOAWAY and close once the active streams are completed.
+    pub(crate) fn go_away_gracefully(&mut self) {
+        if self.inner.go_away.is_going_away() {
+            return;
+        }
+        let mut conn = self.inner.as_dyn();
+        let last_processed_id = conn.streams.last_processed_id();
+        conn.go_away(last_processed_id, Reason::NO_ERROR);
+    }
 }
 
 impl<T, B> Connection<T, server::Peer, B>
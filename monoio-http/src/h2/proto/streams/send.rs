@@ -42,6 +42,12 @@ pub(super) struct Send {
 
     /// If extended connect protocol is enabled.
     is_extended_connect_protocol_enabled: bool,
+
+    /// If the first SETTINGS frame of the remote has been received.
+    is_remote_settings_received: bool,
+
+    /// Task waiting for the first SETTINGS frame of the remote.
+    remote_settings_task: Option<Waker>,
 }
 
 /// A value to detect which public API has called `poll_reset`.
@@ -61,6 +67,8 @@ impl Send {
             prioritize: Prioritize::new(config),
             is_push_enabled: true,
             is_extended_connect_protocol_enabled: false,
+            is_remote_settings_received: false,
+            remote_settings_task: None,
         }
     }
 
@@ -430,6 +438,8 @@ impl Send {
         if let Some(val) = settings.is_extended_connect_protocol_enabled() {
             self.is_extended_connect_protocol_enabled = val;
         }
+        self.is_remote_settings_received = true;
+        self.wake_remote_settings_task();
 
         // Applies an update to the remote endpoint's initial window size.
         //
@@ -562,4 +572,18 @@ impl Send {
     pub(crate) fn is_extended_connect_protocol_enabled(&self) -> bool {
         self.is_extended_connect_protocol_enabled
     }
+
+    pub(crate) fn poll_remote_settings(&mut self, cx: &Context) -> Poll<()> {
+        if self.is_remote_settings_received {
+            return Poll::Ready(());
+        }
+        self.remote_settings_task = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    pub(crate) fn wake_remote_settings_task(&mut self) {
+        if let Some(task) = self.remote_settings_task.take() {
+            task.wake();
+        }
+    }
 }
@@ -302,6 +302,15 @@ where
         let me = unsafe { &mut *self.inner.get() };
         me.actions.send.is_extended_connect_protocol_enabled()
     }
+
+    pub(crate) fn poll_remote_settings(
+        &mut self,
+        cx: &Context,
+    ) -> Poll<Result<(), crate::h2::Error>> {
+        let me = unsafe { &mut *self.inner.get() };
+        me.actions.ensure_no_conn_error()?;
+        me.actions.send.poll_remote_settings(cx).map(Ok)
+    }
 }
 
 impl<B> DynStreams<'_, B> {
@@ -648,6 +657,7 @@ impl Inner {
         });
 
         actions.conn_error = Some(err);
+        actions.send.wake_remote_settings_task();
 
         last_processed_id
     }
@@ -667,8 +677,10 @@ impl Inner {
 
         let err = Error::remote_go_away(frame.debug_data().clone(), frame.reason());
 
+        // The last stream id only refers to streams initiated by us, streams
+        // of the peer are still processed.
         self.store.for_each(|stream| {
-            if stream.id > last_stream_id {
+            if stream.id > last_stream_id && counts.peer().is_local_init(stream.id) {
                 counts.transition(stream, |counts, stream| {
                     actions.recv.handle_error(&err, &mut *stream);
                     actions.send.handle_error(send_buffer, stream, counts);
@@ -677,6 +689,7 @@ impl Inner {
         });
 
         actions.conn_error = Some(err);
+        actions.send.wake_remote_settings_task();
 
         Ok(())
     }
@@ -793,6 +806,7 @@ impl Inner {
                 .into(),
             );
         }
+        actions.send.wake_remote_settings_task();
 
         tracing::trace!("Streams::recv_eof");
 
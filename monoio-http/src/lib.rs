@@ -2,3 +2,5 @@ pub mod common;
 pub mod h1;
 pub mod h2;
 pub mod util;
+#[cfg(feature = "ws")]
+pub mod ws;
@@ -0,0 +1,240 @@
+//! permessage-deflate extension(RFC 7692).
+//!
+//! Only the default 15 bits window is supported, so `*_max_window_bits` is
+//! never offered, and a server `server_max_window_bits` is accepted as is
+//! since a larger window can always inflate data of a smaller one.
+use bytes::Bytes;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use http::HeaderValue;
+
+use super::{Role, WsError};
+
+const EXTENSION: &str = "permessage-deflate";
+const TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Negotiated parameters of permessage-deflate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeflateConfig {
+    /// The client resets its compression context after each message.
+    pub client_no_context_takeover: bool,
+    /// The server resets its compression context after each message.
+    pub server_no_context_takeover: bool,
+}
+
+impl DeflateConfig {
+    /// The `Sec-WebSocket-Extensions` offered by the client.
+    pub fn offer(&self) -> HeaderValue {
+        let mut offer = String::from(EXTENSION);
+        if self.client_no_context_takeover {
+            offer.push_str("; client_no_context_takeover");
+        }
+        if self.server_no_context_takeover {
+            offer.push_str("; server_no_context_takeover");
+        }
+        HeaderValue::from_str(&offer).expect("valid header value")
+    }
+
+    /// Parse the `Sec-WebSocket-Extensions` responded by the server.
+    ///
+    /// Returns `None` if the server declines the extension.
+    pub fn from_response(value: &HeaderValue) -> Result<Option<Self>, WsError> {
+        let value = value
+            .to_str()
+            .map_err(|_| WsError::Handshake("invalid sec-websocket-extensions"))?;
+        let mut negotiated = None;
+        for ext in value.split(',') {
+            let mut params = ext.split(';').map(str::trim);
+            if params.next() != Some(EXTENSION) {
+                return Err(WsError::Handshake("unsupported extension"));
+            }
+            if negotiated.is_some() {
+                return Err(WsError::Handshake("duplicate permessage-deflate"));
+            }
+            let mut config = Self::default();
+            for param in params {
+                match param
+                    .split_once('=')
+                    .map(|(k, _)| k.trim())
+                    .unwrap_or(param)
+                {
+                    "client_no_context_takeover" => config.client_no_context_takeover = true,
+                    "server_no_context_takeover" => config.server_no_context_takeover = true,
+                    "server_max_window_bits" => {}
+                    _ => return Err(WsError::Handshake("unsupported deflate parameter")),
+                }
+            }
+            negotiated = Some(config);
+        }
+        Ok(negotiated)
+    }
+
+    /// Pick an acceptable offer from the `Sec-WebSocket-Extensions` sent by
+    /// the client, and returns the negotiated config and the response header.
+    pub fn accept(value: &HeaderValue) -> Option<(Self, HeaderValue)> {
+        let value = value.to_str().ok()?;
+        'offers: for ext in value.split(',') {
+            let mut params = ext.split(';').map(str::trim);
+            if params.next() != Some(EXTENSION) {
+                continue;
+            }
+            let mut config = Self::default();
+            for param in params {
+                match param.split_once('=') {
+                    None if param == "client_no_context_takeover" => {
+                        config.client_no_context_takeover = true
+                    }
+                    None if param == "server_no_context_takeover" => {
+                        config.server_no_context_takeover = true
+                    }
+                    // The client is able to use any window we ask for.
+                    None if param == "client_max_window_bits" => {}
+                    Some((k, v)) if k.trim() == "client_max_window_bits" => {
+                        if v.trim().trim_matches('"').parse::<u8>().is_err() {
+                            continue 'offers;
+                        }
+                    }
+                    _ => continue 'offers,
+                }
+            }
+            return Some((config, config.offer()));
+        }
+        None
+    }
+}
+
+/// Compression context of one side of a connection.
+pub struct DeflateContext {
+    compress: Compress,
+    decompress: Decompress,
+    reset_compress: bool,
+    reset_decompress: bool,
+}
+
+impl DeflateContext {
+    pub fn new(config: DeflateConfig, role: Role) -> Self {
+        let (reset_compress, reset_decompress) = match role {
+            Role::Client => (
+                config.client_no_context_takeover,
+                config.server_no_context_takeover,
+            ),
+            Role::Server => (
+                config.server_no_context_takeover,
+                config.client_no_context_takeover,
+            ),
+        };
+        Self {
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            reset_compress,
+            reset_decompress,
+        }
+    }
+
+    /// Compress a whole message.
+    pub fn compress(&mut self, data: &[u8]) -> Result<Bytes, WsError> {
+        let mut out = Vec::with_capacity(data.len() / 2 + 16);
+        let start = self.compress.total_in();
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            out.reserve(256.max(data.len() - consumed));
+            self.compress
+                .compress_vec(&data[consumed..], &mut out, FlushCompress::Sync)
+                .map_err(|_| WsError::Protocol("deflate error"))?;
+            // The sync flush is done when there is spare output space left.
+            if (self.compress.total_in() - start) as usize == data.len()
+                && out.len() < out.capacity()
+            {
+                break;
+            }
+        }
+        if out.ends_with(&TAIL) {
+            out.truncate(out.len() - TAIL.len());
+        }
+        if self.reset_compress {
+            self.compress.reset();
+        }
+        Ok(out.into())
+    }
+
+    /// Decompress a whole message, fails if the output exceeds `max_size`.
+    pub fn decompress(&mut self, data: &[u8], max_size: usize) -> Result<Bytes, WsError> {
+        let mut out = Vec::with_capacity((data.len() * 2).min(max_size) + 16);
+        for input in [data, &TAIL[..]] {
+            let start = self.decompress.total_in();
+            loop {
+                let consumed = (self.decompress.total_in() - start) as usize;
+                if out.len() == out.capacity() {
+                    out.reserve(out.capacity().max(256));
+                }
+                let status = self
+                    .decompress
+                    .decompress_vec(&input[consumed..], &mut out, FlushDecompress::Sync)
+                    .map_err(|_| WsError::Protocol("invalid deflate data"))?;
+                if out.len() > max_size {
+                    return Err(WsError::TooLarge);
+                }
+                let consumed = (self.decompress.total_in() - start) as usize;
+                if status == Status::StreamEnd
+                    || (consumed == input.len() && out.len() < out.capacity())
+                {
+                    break;
+                }
+            }
+        }
+        if self.reset_decompress {
+            self.decompress.reset(false);
+        }
+        Ok(out.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let offer = DeflateConfig {
+            client_no_context_takeover: true,
+            server_no_context_takeover: false,
+        }
+        .offer();
+        assert_eq!(offer, "permessage-deflate; client_no_context_takeover");
+        let (config, resp) = DeflateConfig::accept(&HeaderValue::from_static(
+            "x-foo, permessage-deflate; client_max_window_bits",
+        ))
+        .unwrap();
+        assert_eq!(config, DeflateConfig::default());
+        assert_eq!(
+            DeflateConfig::from_response(&resp).unwrap(),
+            Some(DeflateConfig::default())
+        );
+        assert!(DeflateConfig::from_response(&HeaderValue::from_static("x-foo")).is_err());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let config = DeflateConfig {
+            client_no_context_takeover: false,
+            server_no_context_takeover: true,
+        };
+        let mut client = DeflateContext::new(config, Role::Client);
+        let mut server = DeflateContext::new(config, Role::Server);
+        let data = "hello websocket ".repeat(1000);
+        for _ in 0..3 {
+            let compressed = client.compress(data.as_bytes()).unwrap();
+            assert!(compressed.len() < data.len());
+            let plain = server.decompress(&compressed, usize::MAX).unwrap();
+            assert_eq!(plain, data.as_bytes());
+
+            let compressed = server.compress(data.as_bytes()).unwrap();
+            let plain = client.decompress(&compressed, usize::MAX).unwrap();
+            assert_eq!(plain, data.as_bytes());
+        }
+        let compressed = client.compress(data.as_bytes()).unwrap();
+        assert!(matches!(
+            server.decompress(&compressed, 100),
+            Err(WsError::TooLarge)
+        ));
+    }
+}
@@ -0,0 +1,287 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use monoio_codec::{Decoded, Decoder, Encoder};
+
+use super::WsError;
+
+// 2 bytes head + 8 bytes extended length + 4 bytes mask key.
+const MAX_HEAD_SIZE: usize = 14;
+const MAX_CONTROL_PAYLOAD: usize = 125;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    #[inline]
+    pub fn is_control(&self) -> bool {
+        matches!(self, Self::Close | Self::Ping | Self::Pong)
+    }
+
+    #[inline]
+    fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            0x0 => Some(Self::Continuation),
+            0x1 => Some(Self::Text),
+            0x2 => Some(Self::Binary),
+            0x8 => Some(Self::Close),
+            0x9 => Some(Self::Ping),
+            0xA => Some(Self::Pong),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn as_u8(&self) -> u8 {
+        match self {
+            Self::Continuation => 0x0,
+            Self::Text => 0x1,
+            Self::Binary => 0x2,
+            Self::Close => 0x8,
+            Self::Ping => 0x9,
+            Self::Pong => 0xA,
+        }
+    }
+}
+
+/// Which side of the connection we are, it decides the masking direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+/// A WebSocket frame with unmasked payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    /// Set on the first frame of a compressed message.
+    pub rsv1: bool,
+    pub opcode: OpCode,
+    pub payload: Bytes,
+}
+
+impl Frame {
+    #[inline]
+    pub fn new(fin: bool, opcode: OpCode, payload: Bytes) -> Self {
+        Self {
+            fin,
+            rsv1: false,
+            opcode,
+            payload,
+        }
+    }
+}
+
+/// Codec of WebSocket frames.
+///
+/// Frames sent by the client are masked and frames sent by the server are
+/// not, the decoder rejects frames with the wrong masking.
+#[derive(Debug, Clone)]
+pub struct FrameCodec {
+    role: Role,
+    max_frame_size: usize,
+}
+
+impl FrameCodec {
+    pub fn new(role: Role) -> Self {
+        Self {
+            role,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    #[inline]
+    pub fn set_max_frame_size(&mut self, size: usize) {
+        self.max_frame_size = size;
+    }
+
+    #[inline]
+    pub fn role(&self) -> Role {
+        self.role
+    }
+}
+
+#[inline]
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, b) in data.iter_mut().enumerate() {
+        *b ^= mask[i & 3];
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = WsError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Decoded<Self::Item>, Self::Error> {
+        if src.len() < 2 {
+            return Ok(Decoded::Insufficient);
+        }
+        let (b0, b1) = (src[0], src[1]);
+        let fin = b0 & 0x80 != 0;
+        let rsv1 = b0 & 0x40 != 0;
+        if b0 & 0x30 != 0 {
+            return Err(WsError::Protocol("reserved bits set"));
+        }
+        let opcode = OpCode::from_u8(b0 & 0x0F).ok_or(WsError::Protocol("unknown opcode"))?;
+        let masked = b1 & 0x80 != 0;
+        if masked != (self.role == Role::Server) {
+            return Err(WsError::Protocol("invalid frame masking"));
+        }
+
+        let (length, mut head_len) = match b1 & 0x7F {
+            126 => {
+                if src.len() < 4 {
+                    return Ok(Decoded::InsufficientAtLeast(4));
+                }
+                (u16::from_be_bytes([src[2], src[3]]) as u64, 4)
+            }
+            127 => {
+                if src.len() < 10 {
+                    return Ok(Decoded::InsufficientAtLeast(10));
+                }
+                let mut len = [0; 8];
+                len.copy_from_slice(&src[2..10]);
+                (u64::from_be_bytes(len), 10)
+            }
+            l => (l as u64, 2),
+        };
+        if opcode.is_control() && (!fin || length > MAX_CONTROL_PAYLOAD as u64) {
+            return Err(WsError::Protocol("invalid control frame"));
+        }
+        if length > self.max_frame_size as u64 {
+            return Err(WsError::TooLarge);
+        }
+        let length = length as usize;
+
+        let mask = if masked {
+            if src.len() < head_len + 4 {
+                return Ok(Decoded::InsufficientAtLeast(head_len + 4));
+            }
+            let mut mask = [0; 4];
+            mask.copy_from_slice(&src[head_len..head_len + 4]);
+            head_len += 4;
+            Some(mask)
+        } else {
+            None
+        };
+
+        if src.len() < head_len + length {
+            return Ok(Decoded::InsufficientAtLeast(head_len + length));
+        }
+        src.advance(head_len);
+        let mut payload = src.split_to(length);
+        if let Some(mask) = mask {
+            apply_mask(&mut payload, mask);
+        }
+        Ok(Decoded::Some(Frame {
+            fin,
+            rsv1,
+            opcode,
+            payload: payload.freeze(),
+        }))
+    }
+}
+
+impl Encoder<Frame> for FrameCodec {
+    type Error = WsError;
+
+    fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let length = item.payload.len();
+        dst.reserve(MAX_HEAD_SIZE + length);
+
+        let mut b0 = item.opcode.as_u8();
+        if item.fin {
+            b0 |= 0x80;
+        }
+        if item.rsv1 {
+            b0 |= 0x40;
+        }
+        dst.put_u8(b0);
+
+        let mask_bit = if self.role == Role::Client { 0x80 } else { 0 };
+        if length < 126 {
+            dst.put_u8(mask_bit | length as u8);
+        } else if length <= u16::MAX as usize {
+            dst.put_u8(mask_bit | 126);
+            dst.put_u16(length as u16);
+        } else {
+            dst.put_u8(mask_bit | 127);
+            dst.put_u64(length as u64);
+        }
+
+        if self.role == Role::Client {
+            let mask: [u8; 4] = rand::random();
+            dst.put_slice(&mask);
+            let start = dst.len();
+            dst.put_slice(&item.payload);
+            apply_mask(&mut dst[start..], mask);
+        } else {
+            dst.put_slice(&item.payload);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_masked() {
+        let frame = Frame::new(true, OpCode::Text, Bytes::from_static(b"hello"));
+        let mut buf = BytesMut::new();
+        FrameCodec::new(Role::Client)
+            .encode(frame.clone(), &mut buf)
+            .unwrap();
+        assert_eq!(buf.len(), 2 + 4 + 5);
+        assert_ne!(&buf[6..], b"hello");
+
+        let decoded = FrameCodec::new(Role::Server)
+            .decode(&mut buf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded, frame);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_extended_length() {
+        let payload = Bytes::from(vec![7; 70000]);
+        let mut buf = BytesMut::new();
+        FrameCodec::new(Role::Server)
+            .encode(Frame::new(false, OpCode::Binary, payload.clone()), &mut buf)
+            .unwrap();
+        let mut codec = FrameCodec::new(Role::Client);
+        let mut partial = buf.split_to(100);
+        assert!(matches!(
+            codec.decode(&mut partial).unwrap(),
+            Decoded::InsufficientAtLeast(70010)
+        ));
+        partial.unsplit(buf);
+        let decoded = codec.decode(&mut partial).unwrap().unwrap();
+        assert!(!decoded.fin);
+        assert_eq!(decoded.payload, payload);
+    }
+
+    #[test]
+    fn decode_invalid_frames() {
+        // Unmasked frame sent to server.
+        let mut buf = BytesMut::from(&b"\x81\x05hello"[..]);
+        assert!(FrameCodec::new(Role::Server).decode(&mut buf).is_err());
+        // Fragmented ping.
+        let mut buf = BytesMut::from(&b"\x09\x00"[..]);
+        assert!(FrameCodec::new(Role::Client).decode(&mut buf).is_err());
+        // Too large frame.
+        let mut buf = BytesMut::from(&b"\x82\x7f\x00\x00\x00\x00\x10\x00\x00\x00"[..]);
+        let mut codec = FrameCodec::new(Role::Client);
+        codec.set_max_frame_size(1024);
+        assert!(matches!(codec.decode(&mut buf), Err(WsError::TooLarge)));
+    }
+}
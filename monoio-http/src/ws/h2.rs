@@ -0,0 +1,275 @@
+//! WebSocket bootstrapped on HTTP/2 streams(RFC 8441).
+//!
+//! The handshake is an extended CONNECT request with `:protocol` set to
+//! `websocket`, and the stream is wrapped as an io so that the frame codec
+//! is shared with the HTTP/1.1 path. Each WebSocket takes one stream, so many
+//! of them can be multiplexed on a single connection.
+use std::{future::poll_fn, io};
+
+use bytes::{Buf, Bytes};
+use http::{header, HeaderMap, Method, Request, Response, StatusCode, Version};
+use monoio::{
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut, IoVecWrapper, IoVecWrapperMut},
+    io::{AsyncReadRent, AsyncWriteRent},
+    BufResult,
+};
+
+use super::{handshake, WsError};
+use crate::h2::{client::SendRequest, ext::Protocol, server::SendResponse, RecvStream, SendStream};
+
+const PROTOCOL: Protocol = Protocol::from_static("websocket");
+
+/// A pair of h2 stream halves used as an io.
+pub struct H2Stream {
+    send: SendStream<Bytes>,
+    recv: RecvStream,
+    // Data received but not read yet.
+    buf: Bytes,
+}
+
+impl H2Stream {
+    pub fn new(send: SendStream<Bytes>, recv: RecvStream) -> Self {
+        Self {
+            send,
+            recv,
+            buf: Bytes::new(),
+        }
+    }
+
+    /// Reset the stream, it is the abortive close of the WebSocket.
+    pub fn reset(&mut self) {
+        self.send.send_reset(crate::h2::Reason::CANCEL);
+    }
+}
+
+#[inline]
+fn to_io_error(e: crate::h2::Error) -> io::Error {
+    if e.is_io() {
+        e.into_io().expect("io error")
+    } else {
+        io::Error::other(e)
+    }
+}
+
+impl AsyncReadRent for H2Stream {
+    async fn read<T: IoBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        if self.buf.is_empty() {
+            match self.recv.data().await {
+                Some(Ok(data)) => {
+                    let _ = self.recv.flow_control().release_capacity(data.len());
+                    self.buf = data;
+                }
+                Some(Err(e)) => return (Err(to_io_error(e)), buf),
+                None => return (Ok(0), buf),
+            }
+        }
+        let n = buf.bytes_total().min(self.buf.len());
+        unsafe {
+            buf.write_ptr()
+                .copy_from_nonoverlapping(self.buf.as_ptr(), n);
+            buf.set_init(n);
+        }
+        self.buf.advance(n);
+        (Ok(n), buf)
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        let slice = match IoVecWrapperMut::new(buf) {
+            Ok(slice) => slice,
+            Err(buf) => return (Ok(0), buf),
+        };
+
+        let (result, slice) = self.read(slice).await;
+        buf = slice.into_inner();
+        if let Ok(n) = result {
+            unsafe { buf.set_init(n) };
+        }
+        (result, buf)
+    }
+}
+
+impl AsyncWriteRent for H2Stream {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        let len = buf.bytes_init();
+        if len == 0 {
+            return (Ok(0), buf);
+        }
+        // Wait for the flow control window instead of buffering unboundedly.
+        self.send.reserve_capacity(len);
+        let mut capacity = self.send.capacity();
+        while capacity == 0 {
+            capacity = match poll_fn(|cx| self.send.poll_capacity(cx)).await {
+                Some(Ok(capacity)) => capacity,
+                Some(Err(e)) => return (Err(to_io_error(e)), buf),
+                None => return (Err(io::ErrorKind::BrokenPipe.into()), buf),
+            };
+        }
+        let n = capacity.min(len);
+        let data = unsafe { std::slice::from_raw_parts(buf.read_ptr(), n) };
+        match self.send.send_data(Bytes::copy_from_slice(data), false) {
+            Ok(_) => (Ok(n), buf),
+            Err(e) => (Err(to_io_error(e)), buf),
+        }
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        let slice = match IoVecWrapper::new(buf) {
+            Ok(slice) => slice,
+            Err(buf) => return (Ok(0), buf),
+        };
+
+        let (result, slice) = self.write(slice).await;
+        (result, slice.into_inner())
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> io::Result<()> {
+        // Data is flushed by the connection task.
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.send.send_data(Bytes::new(), true).map_err(to_io_error)
+    }
+}
+
+/// Send an extended CONNECT request on the connection.
+///
+/// The method, version, `:protocol` and `Sec-WebSocket-Version` of the
+/// request are set. It waits for the settings of the server to know if
+/// extended CONNECT is enabled. The response status is not checked, the
+/// caller should only use the stream if it is `2xx`.
+pub async fn connect(
+    mut send_request: SendRequest<Bytes>,
+    mut request: Request<()>,
+) -> Result<(Response<()>, H2Stream), WsError> {
+    *request.method_mut() = Method::CONNECT;
+    *request.version_mut() = Version::HTTP_2;
+    request.extensions_mut().insert(PROTOCOL);
+    request
+        .headers_mut()
+        .insert(header::SEC_WEBSOCKET_VERSION, handshake::VERSION);
+
+    send_request.remote_settings().await?;
+    let mut send_request = send_request.ready().await?;
+    if !send_request.is_extended_connect_protocol_enabled() {
+        return Err(WsError::Handshake(
+            "extended CONNECT is not enabled by peer",
+        ));
+    }
+    let (response, send) = send_request.send_request(request, false)?;
+    let (parts, recv) = response.await?.into_parts();
+    Ok((Response::from_parts(parts, ()), H2Stream::new(send, recv)))
+}
+
+/// Validate an extended CONNECT request for WebSocket.
+pub fn verify_request<B>(request: &Request<B>) -> Result<(), WsError> {
+    if request.method() != Method::CONNECT {
+        return Err(WsError::Handshake("method is not CONNECT"));
+    }
+    if request.extensions().get::<Protocol>() != Some(&PROTOCOL) {
+        return Err(WsError::Handshake("missing :protocol websocket"));
+    }
+    if request.headers().get(header::SEC_WEBSOCKET_VERSION) != Some(&handshake::VERSION) {
+        return Err(WsError::Handshake("unsupported sec-websocket-version"));
+    }
+    Ok(())
+}
+
+/// Accept a WebSocket request, respond `200 OK` with the headers.
+///
+/// The server connection must be built with `enable_connect_protocol`.
+pub fn accept(
+    request: Request<RecvStream>,
+    mut respond: SendResponse<Bytes>,
+    headers: HeaderMap,
+) -> Result<H2Stream, WsError> {
+    if let Err(e) = verify_request(&request) {
+        let mut response = Response::new(());
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        let _ = respond.send_response(response, true);
+        return Err(e);
+    }
+    let mut response = Response::new(());
+    *response.headers_mut() = headers;
+    let send = respond.send_response(response, false)?;
+    Ok(H2Stream::new(send, request.into_body()))
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::io::{
+        sink::{Sink, SinkExt},
+        stream::Stream,
+    };
+
+    use super::*;
+    use crate::ws::{Message, Role, WebSocket};
+
+    #[monoio::test_all(enable_timer = true)]
+    async fn multiplex_websockets() {
+        let (a, b) = monoio::net::UnixStream::pair().unwrap();
+        monoio::spawn(async move {
+            let mut conn = crate::h2::server::Builder::new()
+                .enable_connect_protocol()
+                .handshake::<_, Bytes>(b)
+                .await
+                .unwrap();
+            while let Some(Ok((request, respond))) = conn.accept().await {
+                let Ok(io) = accept(request, respond, HeaderMap::new()) else {
+                    continue;
+                };
+                monoio::spawn(async move {
+                    let mut ws = WebSocket::from_raw(io, Role::Server, Default::default());
+                    while let Some(Ok(msg)) = ws.next().await {
+                        if let Message::Text(_) = msg {
+                            ws.send_and_flush(msg).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+
+        let (send_request, conn) = crate::h2::client::handshake(a).await.unwrap();
+        monoio::spawn(conn);
+
+        let mut sockets = Vec::new();
+        for i in 0..3 {
+            let request = Request::builder()
+                .uri("https://localhost/chat")
+                .body(())
+                .unwrap();
+            let (response, io) = connect(send_request.clone(), request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut ws = WebSocket::from_raw(io, Role::Client, Default::default());
+            ws.send_and_flush(Message::Text(format!("hello {i}")))
+                .await
+                .unwrap();
+            sockets.push(ws);
+        }
+        for (i, ws) in sockets.iter_mut().enumerate().rev() {
+            assert_eq!(
+                ws.next().await.unwrap().unwrap(),
+                Message::Text(format!("hello {i}"))
+            );
+            Sink::<Message>::close(ws).await.unwrap();
+            assert!(matches!(
+                ws.next().await.unwrap().unwrap(),
+                Message::Close(_)
+            ));
+        }
+
+        // Not an extended CONNECT request.
+        let request = Request::builder()
+            .uri("https://localhost/chat")
+            .body(())
+            .unwrap();
+        let (response, _) = send_request
+            .ready()
+            .await
+            .unwrap()
+            .send_request(request, true)
+            .unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,99 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::{header, HeaderMap, HeaderValue};
+use sha1::{Digest, Sha1};
+
+use super::WsError;
+
+const GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The only version defined by RFC 6455.
+pub const VERSION: HeaderValue = HeaderValue::from_static("13");
+
+/// Generate a random `Sec-WebSocket-Key`.
+pub fn generate_key() -> HeaderValue {
+    let key: [u8; 16] = rand::random();
+    HeaderValue::from_str(&STANDARD.encode(key)).expect("base64 is valid header value")
+}
+
+/// Derive the `Sec-WebSocket-Accept` from the `Sec-WebSocket-Key`.
+pub fn derive_accept_key(key: &[u8]) -> HeaderValue {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(GUID);
+    HeaderValue::from_str(&STANDARD.encode(sha1.finalize())).expect("base64 is valid header value")
+}
+
+/// Returns true if the comma separated header contains the token,
+/// compared case-insensitively.
+pub fn header_contains(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case(token))
+}
+
+/// Validate the headers of a `101 Switching Protocols` response.
+pub fn verify_response(headers: &HeaderMap, key: &HeaderValue) -> Result<(), WsError> {
+    if !header_contains(headers, header::UPGRADE, "websocket") {
+        return Err(WsError::Handshake("missing upgrade: websocket"));
+    }
+    if !header_contains(headers, header::CONNECTION, "upgrade") {
+        return Err(WsError::Handshake("missing connection: upgrade"));
+    }
+    match headers.get(header::SEC_WEBSOCKET_ACCEPT) {
+        Some(accept) if accept == derive_accept_key(key.as_bytes()) => Ok(()),
+        _ => Err(WsError::Handshake("invalid sec-websocket-accept")),
+    }
+}
+
+/// Validate the headers of an upgrade request and returns the
+/// `Sec-WebSocket-Accept` to respond with.
+pub fn verify_request(headers: &HeaderMap) -> Result<HeaderValue, WsError> {
+    if !header_contains(headers, header::UPGRADE, "websocket") {
+        return Err(WsError::Handshake("missing upgrade: websocket"));
+    }
+    if !header_contains(headers, header::CONNECTION, "upgrade") {
+        return Err(WsError::Handshake("missing connection: upgrade"));
+    }
+    if headers.get(header::SEC_WEBSOCKET_VERSION) != Some(&VERSION) {
+        return Err(WsError::Handshake("unsupported sec-websocket-version"));
+    }
+    match headers.get(header::SEC_WEBSOCKET_KEY) {
+        Some(key) => Ok(derive_accept_key(key.as_bytes())),
+        None => Err(WsError::Handshake("missing sec-websocket-key")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key() {
+        // Example from RFC 6455 section 1.3.
+        assert_eq!(
+            derive_accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(generate_key().len(), 24);
+    }
+
+    #[test]
+    fn verify_upgrade_response() {
+        let key = HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ==");
+        let mut headers = HeaderMap::new();
+        headers.insert(header::UPGRADE, HeaderValue::from_static("WebSocket"));
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("keep-alive, Upgrade"),
+        );
+        assert!(verify_response(&headers, &key).is_err());
+        headers.insert(
+            header::SEC_WEBSOCKET_ACCEPT,
+            HeaderValue::from_static("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="),
+        );
+        assert!(verify_response(&headers, &key).is_ok());
+    }
+}
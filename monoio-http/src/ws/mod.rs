@@ -0,0 +1,63 @@
+//! WebSocket(RFC 6455) framing and handshake helpers.
+use thiserror::Error as ThisError;
+
+#[cfg(feature = "ws-deflate")]
+pub mod deflate;
+pub mod frame;
+pub mod h2;
+pub mod handshake;
+pub mod socket;
+
+pub use frame::{Frame, FrameCodec, OpCode, Role};
+pub use socket::{CloseFrame, Config, Message, WebSocket};
+
+#[derive(ThisError, Debug)]
+pub enum WsError {
+    #[error("io error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("h2 error {0}")]
+    H2(#[from] crate::h2::Error),
+    #[error("protocol error: {0}")]
+    Protocol(&'static str),
+    #[error("invalid utf-8 in text message")]
+    InvalidUtf8,
+    #[error("frame or message too large")]
+    TooLarge,
+    #[error("connection closed")]
+    Closed,
+    #[error("handshake error: {0}")]
+    Handshake(&'static str),
+}
+
+impl WsError {
+    /// The close code sent to the peer when the error is raised by the
+    /// receiving side.
+    pub fn close_code(&self) -> Option<u16> {
+        match self {
+            Self::Protocol(_) => Some(close_code::PROTOCOL),
+            Self::InvalidUtf8 => Some(close_code::INVALID_DATA),
+            Self::TooLarge => Some(close_code::TOO_LARGE),
+            _ => None,
+        }
+    }
+}
+
+/// Close codes defined in RFC 6455.
+pub mod close_code {
+    pub const NORMAL: u16 = 1000;
+    pub const AWAY: u16 = 1001;
+    pub const PROTOCOL: u16 = 1002;
+    pub const UNSUPPORTED: u16 = 1003;
+    pub const STATUS: u16 = 1005;
+    pub const ABNORMAL: u16 = 1006;
+    pub const INVALID_DATA: u16 = 1007;
+    pub const POLICY: u16 = 1008;
+    pub const TOO_LARGE: u16 = 1009;
+    pub const EXTENSION: u16 = 1010;
+    pub const ERROR: u16 = 1011;
+
+    /// Returns true if the code is allowed to be sent in a close frame.
+    pub fn is_valid(code: u16) -> bool {
+        matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999)
+    }
+}
@@ -0,0 +1,475 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use monoio::io::{sink::Sink, stream::Stream, AsyncReadRent, AsyncWriteRent};
+use monoio_codec::Framed;
+
+#[cfg(feature = "ws-deflate")]
+use super::deflate::DeflateContext;
+use super::{close_code, Frame, FrameCodec, OpCode, Role, WsError};
+
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 << 20;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16 << 20;
+
+/// A WebSocket message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Bytes),
+    Ping(Bytes),
+    Pong(Bytes),
+    Close(Option<CloseFrame>),
+}
+
+/// The payload of a close frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl CloseFrame {
+    fn parse(payload: &[u8]) -> Result<Option<Self>, WsError> {
+        match payload.len() {
+            0 => Ok(None),
+            1 => Err(WsError::Protocol("invalid close frame")),
+            _ => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                if !close_code::is_valid(code) {
+                    return Err(WsError::Protocol("invalid close code"));
+                }
+                let reason = std::str::from_utf8(&payload[2..])
+                    .map_err(|_| WsError::InvalidUtf8)?
+                    .to_string();
+                Ok(Some(Self { code, reason }))
+            }
+        }
+    }
+
+    fn encode(frame: Option<&Self>) -> Bytes {
+        match frame {
+            None => Bytes::new(),
+            Some(frame) => {
+                let mut buf = BytesMut::with_capacity(2 + frame.reason.len());
+                buf.put_u16(frame.code);
+                buf.put_slice(frame.reason.as_bytes());
+                buf.freeze()
+            }
+        }
+    }
+}
+
+/// Limits and behaviors of a WebSocket.
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    /// Max size of a reassembled(and decompressed) message.
+    pub max_message_size: usize,
+    /// Max payload size of a single frame.
+    pub max_frame_size: usize,
+    /// Split outgoing data messages into frames of this size.
+    pub fragment_size: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            fragment_size: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Open,
+    // We have sent a close frame, and are waiting for the peer's.
+    CloseSent,
+    Closed,
+}
+
+struct Partial {
+    opcode: OpCode,
+    compressed: bool,
+    data: BytesMut,
+}
+
+/// A WebSocket connection after the handshake.
+///
+/// Messages are received with `Stream` and sent with `Sink`. Pings are
+/// answered and close frames are echoed automatically while receiving, the
+/// stream ends after the closing handshake.
+pub struct WebSocket<IO> {
+    framed: Framed<IO, FrameCodec>,
+    config: Config,
+    state: State,
+    partial: Option<Partial>,
+    #[cfg(feature = "ws-deflate")]
+    deflate: Option<DeflateContext>,
+}
+
+impl<IO> WebSocket<IO> {
+    /// Create a WebSocket on a connection which has done the handshake.
+    pub fn from_raw(io: IO, role: Role, config: Config) -> Self {
+        Self::from_partially_read(io, BytesMut::new(), role, config)
+    }
+
+    /// Like `from_raw`, `buf` is the data already read from the connection
+    /// after the handshake.
+    pub fn from_partially_read(io: IO, buf: BytesMut, role: Role, config: Config) -> Self {
+        let mut codec = FrameCodec::new(role);
+        codec.set_max_frame_size(config.max_frame_size);
+        let mut framed = Framed::new(io, codec);
+        framed.read_buffer_mut().unsplit(buf);
+        Self {
+            framed,
+            config,
+            state: State::Open,
+            partial: None,
+            #[cfg(feature = "ws-deflate")]
+            deflate: None,
+        }
+    }
+
+    /// Enable permessage-deflate with the negotiated parameters.
+    #[cfg(feature = "ws-deflate")]
+    pub fn set_deflate(&mut self, deflate: DeflateContext) {
+        self.deflate = Some(deflate);
+    }
+
+    #[inline]
+    pub fn role(&self) -> Role {
+        self.framed.codec().role()
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &IO {
+        self.framed.get_ref()
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> IO {
+        self.framed.into_inner()
+    }
+
+    #[inline]
+    fn compressed_enabled(&self) -> bool {
+        #[cfg(feature = "ws-deflate")]
+        return self.deflate.is_some();
+        #[cfg(not(feature = "ws-deflate"))]
+        false
+    }
+
+    fn push_frame(&mut self, frame: Frame) -> Result<Option<Message>, WsError> {
+        let partial = match frame.opcode {
+            OpCode::Continuation => {
+                let partial = self
+                    .partial
+                    .as_mut()
+                    .ok_or(WsError::Protocol("unexpected continuation frame"))?;
+                if frame.rsv1 {
+                    return Err(WsError::Protocol("rsv1 set on continuation frame"));
+                }
+                if partial.data.len() + frame.payload.len() > self.config.max_message_size {
+                    return Err(WsError::TooLarge);
+                }
+                partial.data.extend_from_slice(&frame.payload);
+                if !frame.fin {
+                    return Ok(None);
+                }
+                self.partial.take().expect("partial message exists")
+            }
+            opcode => {
+                if self.partial.is_some() {
+                    return Err(WsError::Protocol("expect continuation frame"));
+                }
+                if frame.rsv1 && !self.compressed_enabled() {
+                    return Err(WsError::Protocol("rsv1 set without extension"));
+                }
+                if frame.payload.len() > self.config.max_message_size {
+                    return Err(WsError::TooLarge);
+                }
+                let partial = Partial {
+                    opcode,
+                    compressed: frame.rsv1,
+                    data: BytesMut::from(&frame.payload[..]),
+                };
+                if !frame.fin {
+                    self.partial = Some(partial);
+                    return Ok(None);
+                }
+                partial
+            }
+        };
+
+        let data = if partial.compressed {
+            self.decompress(&partial.data)?
+        } else {
+            partial.data.freeze()
+        };
+        match partial.opcode {
+            OpCode::Text => String::from_utf8(data.into())
+                .map(|text| Some(Message::Text(text)))
+                .map_err(|_| WsError::InvalidUtf8),
+            _ => Ok(Some(Message::Binary(data))),
+        }
+    }
+
+    #[cfg(feature = "ws-deflate")]
+    fn decompress(&mut self, data: &[u8]) -> Result<Bytes, WsError> {
+        let max_size = self.config.max_message_size;
+        self.deflate
+            .as_mut()
+            .expect("deflate is enabled")
+            .decompress(data, max_size)
+    }
+
+    #[cfg(not(feature = "ws-deflate"))]
+    fn decompress(&mut self, _data: &[u8]) -> Result<Bytes, WsError> {
+        unreachable!("rsv1 is rejected without deflate")
+    }
+}
+
+impl<IO: AsyncWriteRent> WebSocket<IO> {
+    async fn send_frame(&mut self, frame: Frame) -> Result<(), WsError> {
+        self.framed.send(frame).await
+    }
+
+    async fn send_close(&mut self, frame: Option<&CloseFrame>) -> Result<(), WsError> {
+        self.send_frame(Frame::new(true, OpCode::Close, CloseFrame::encode(frame)))
+            .await?;
+        Sink::<Frame>::flush(&mut self.framed).await
+    }
+
+    async fn send_data(&mut self, opcode: OpCode, data: Bytes) -> Result<(), WsError> {
+        #[cfg(feature = "ws-deflate")]
+        let (data, compressed) = match self.deflate.as_mut() {
+            Some(deflate) => (deflate.compress(&data)?, true),
+            None => (data, false),
+        };
+        #[cfg(not(feature = "ws-deflate"))]
+        let compressed = false;
+
+        let fragment_size = match self.config.fragment_size {
+            Some(size) if size > 0 && data.len() > size => size,
+            _ => {
+                let mut frame = Frame::new(true, opcode, data);
+                frame.rsv1 = compressed;
+                return self.send_frame(frame).await;
+            }
+        };
+        let mut data = data;
+        let mut opcode = opcode;
+        let mut rsv1 = compressed;
+        while !data.is_empty() {
+            let chunk = data.split_to(fragment_size.min(data.len()));
+            let mut frame = Frame::new(data.is_empty(), opcode, chunk);
+            frame.rsv1 = rsv1;
+            self.send_frame(frame).await?;
+            opcode = OpCode::Continuation;
+            rsv1 = false;
+        }
+        Ok(())
+    }
+}
+
+impl<IO: AsyncReadRent + AsyncWriteRent> Stream for WebSocket<IO> {
+    type Item = Result<Message, WsError>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.state == State::Closed {
+                return None;
+            }
+            let frame = match self.framed.next().await {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => return Some(Err(self.fail(e).await)),
+                None => {
+                    self.state = State::Closed;
+                    return Some(Err(WsError::Io(std::io::ErrorKind::UnexpectedEof.into())));
+                }
+            };
+            let msg = match frame.opcode {
+                OpCode::Ping => {
+                    if self.state == State::Open {
+                        let pong = Frame::new(true, OpCode::Pong, frame.payload.clone());
+                        let res = match self.send_frame(pong).await {
+                            Ok(_) => Sink::<Frame>::flush(&mut self.framed).await,
+                            Err(e) => Err(e),
+                        };
+                        if let Err(e) = res {
+                            self.state = State::Closed;
+                            return Some(Err(e));
+                        }
+                    }
+                    Message::Ping(frame.payload)
+                }
+                OpCode::Pong => Message::Pong(frame.payload),
+                OpCode::Close => {
+                    let close = match CloseFrame::parse(&frame.payload) {
+                        Ok(close) => close,
+                        Err(e) => return Some(Err(self.fail(e).await)),
+                    };
+                    if self.state == State::Open {
+                        // Echo the close frame to complete the closing handshake.
+                        let echo = close.as_ref().map(|c| CloseFrame {
+                            code: c.code,
+                            reason: String::new(),
+                        });
+                        let _ = self.send_close(echo.as_ref()).await;
+                    }
+                    self.state = State::Closed;
+                    Message::Close(close)
+                }
+                _ => match self.push_frame(frame) {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => continue,
+                    Err(e) => return Some(Err(self.fail(e).await)),
+                },
+            };
+            return Some(Ok(msg));
+        }
+    }
+}
+
+impl<IO: AsyncReadRent + AsyncWriteRent> WebSocket<IO> {
+    // Close the connection with the code matching the error.
+    async fn fail(&mut self, e: WsError) -> WsError {
+        if self.state == State::Open {
+            if let Some(code) = e.close_code() {
+                let close = CloseFrame {
+                    code,
+                    reason: String::new(),
+                };
+                let _ = self.send_close(Some(&close)).await;
+            }
+        }
+        self.state = State::Closed;
+        e
+    }
+}
+
+impl<IO: AsyncWriteRent> Sink<Message> for WebSocket<IO> {
+    type Error = WsError;
+
+    async fn send(&mut self, item: Message) -> Result<(), Self::Error> {
+        if self.state != State::Open {
+            return Err(WsError::Closed);
+        }
+        match item {
+            Message::Text(text) => self.send_data(OpCode::Text, text.into()).await,
+            Message::Binary(data) => self.send_data(OpCode::Binary, data).await,
+            Message::Ping(data) | Message::Pong(data) if data.len() > 125 => {
+                Err(WsError::Protocol("control frame payload too large"))
+            }
+            Message::Ping(data) => self.send_frame(Frame::new(true, OpCode::Ping, data)).await,
+            Message::Pong(data) => self.send_frame(Frame::new(true, OpCode::Pong, data)).await,
+            Message::Close(close) => {
+                self.state = State::CloseSent;
+                self.send_close(close.as_ref()).await
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Sink::<Frame>::flush(&mut self.framed).await
+    }
+
+    /// Start the closing handshake, the stream should be polled until it ends
+    /// to receive the close frame from the peer.
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        if self.state == State::Open {
+            self.state = State::CloseSent;
+            let close = CloseFrame {
+                code: close_code::NORMAL,
+                reason: String::new(),
+            };
+            return self.send_close(Some(&close)).await;
+        }
+        Sink::<Frame>::flush(&mut self.framed).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pipe two sockets over a unix socket pair.
+    fn pair(
+        config: Config,
+    ) -> (
+        WebSocket<impl AsyncReadRent + AsyncWriteRent>,
+        WebSocket<impl AsyncReadRent + AsyncWriteRent>,
+    ) {
+        let (a, b) = monoio::net::UnixStream::pair().unwrap();
+        (
+            WebSocket::from_raw(a, Role::Client, config),
+            WebSocket::from_raw(b, Role::Server, config),
+        )
+    }
+
+    #[monoio::test_all]
+    async fn fragment_ping_and_close() {
+        let config = Config {
+            fragment_size: Some(3),
+            ..Default::default()
+        };
+        let (mut client, mut server) = pair(config);
+        client.send(Message::Text("hello".into())).await.unwrap();
+        client
+            .send(Message::Ping(Bytes::from_static(b"p")))
+            .await
+            .unwrap();
+        Sink::<Message>::flush(&mut client).await.unwrap();
+
+        assert_eq!(
+            server.next().await.unwrap().unwrap(),
+            Message::Text("hello".into())
+        );
+        assert_eq!(
+            server.next().await.unwrap().unwrap(),
+            Message::Ping(Bytes::from_static(b"p"))
+        );
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            Message::Pong(Bytes::from_static(b"p"))
+        );
+
+        Sink::<Message>::close(&mut client).await.unwrap();
+        assert!(matches!(
+            server.next().await.unwrap().unwrap(),
+            Message::Close(Some(CloseFrame { code: 1000, .. }))
+        ));
+        assert!(server.next().await.is_none());
+        assert!(matches!(
+            client.next().await.unwrap().unwrap(),
+            Message::Close(Some(CloseFrame { code: 1000, .. }))
+        ));
+        assert!(client.next().await.is_none());
+        assert!(matches!(
+            client.send(Message::Text("x".into())).await,
+            Err(WsError::Closed)
+        ));
+    }
+
+    #[monoio::test_all]
+    async fn message_too_large() {
+        let config = Config {
+            max_message_size: 4,
+            fragment_size: Some(2),
+            ..Default::default()
+        };
+        let (mut client, mut server) = pair(config);
+        client
+            .send(Message::Binary(Bytes::from_static(b"123456")))
+            .await
+            .unwrap();
+        Sink::<Message>::flush(&mut client).await.unwrap();
+        assert!(matches!(
+            server.next().await.unwrap(),
+            Err(WsError::TooLarge)
+        ));
+        assert!(matches!(
+            client.next().await.unwrap().unwrap(),
+            Message::Close(Some(CloseFrame { code: 1009, .. }))
+        ));
+    }
+}